@@ -0,0 +1,123 @@
+//! What every design shares: call frames and the errors built from them,
+//! names for the kinds of AST nodes, and the tables of the [`prelude`].
+//!
+//! Each design's `runtime` names these for its own `Value` and `Error`, so
+//! they're usually reached from there.
+use std::fmt::{self, Display};
+
+pub mod prelude;
+
+/// A function call that's in progress, with arguments of type `V`.
+#[derive(Debug, Clone)]
+pub struct Frame<V> {
+    pub name: String,
+    pub args: Vec<V>,
+}
+
+impl<V: Display> Display for Frame<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Number of innermost frames shown when printing a call stack.
+const MAX_TRACE_FRAMES: usize = 10;
+
+/// Default limit on the number of nested function calls, the same in every
+/// design and build, so that a program that runs in one runs in all of them.
+///
+/// A call takes about 8 KiB of native stack in debug builds of the sync
+/// evaluators, and polling nested futures recurses on the native stack too,
+/// so this stays well within the 2 MiB stacks of test and runtime worker
+/// threads.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+/// Evaluation exceeded the evaluator's maximum call depth.
+///
+/// The calls that led to it are in the [`TracedError`] it escapes in.
+#[derive(Debug)]
+pub struct StackOverflow {
+    pub max_depth: usize,
+}
+
+impl Display for StackOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stack overflow: maximum call depth of {} exceeded",
+            self.max_depth
+        )
+    }
+}
+
+impl std::error::Error for StackOverflow {}
+
+/// An error `E` that escaped evaluation, with the calls that led to it.
+#[derive(Debug)]
+pub struct TracedError<E, V> {
+    pub error: E,
+    /// Innermost frame first.
+    pub backtrace: Vec<Frame<V>>,
+}
+
+impl<E: Display, V: Display> Display for TracedError<E, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if self.backtrace.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\nStack trace:")?;
+        for frame in self.backtrace.iter().take(MAX_TRACE_FRAMES) {
+            write!(f, "\n  at {frame}")?;
+        }
+        if self.backtrace.len() > MAX_TRACE_FRAMES {
+            write!(
+                f,
+                "\n  ... {} more",
+                self.backtrace.len() - MAX_TRACE_FRAMES
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: fmt::Debug + Display> std::error::Error for TracedError<Box<dyn std::error::Error>, V> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+impl<V: fmt::Debug + Display> std::error::Error
+    for TracedError<Box<dyn std::error::Error + Send + Sync>, V>
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// Defines `kind()` on AST node types, given each type's variants.
+macro_rules! node_kinds {
+    ($($node:ident { $($variant:ident),* $(,)? })*) => {
+        $(
+            impl $node {
+                /// The node's type and variant, such as `"Factor::Call"`, for
+                /// analyses to group nodes by.
+                pub fn kind(&self) -> &'static str {
+                    match self {
+                        $(Self::$variant { .. } => {
+                            concat!(stringify!($node), "::", stringify!($variant))
+                        })*
+                    }
+                }
+            }
+        )*
+    };
+}
+pub(crate) use node_kinds;
@@ -0,0 +1,75 @@
+//! The math functions and constants that every design binds in a new
+//! `Evaluator`.  Each design's own `prelude` wraps them in its values with
+//! [`bindings`].
+use std::{collections::HashMap, f64::consts};
+
+/// A function of one number, and its name.
+pub type Unary = (&'static str, fn(f64) -> f64);
+
+/// A function of two numbers, and its name.
+pub type Binary = (&'static str, fn(f64, f64) -> f64);
+
+pub const UNARY: [Unary; 11] = [
+    ("abs", f64::abs),
+    ("floor", f64::floor),
+    ("ceil", f64::ceil),
+    ("round", f64::round),
+    ("sqrt", f64::sqrt),
+    ("exp", f64::exp),
+    ("ln", f64::ln),
+    ("log10", f64::log10),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+];
+
+pub const BINARY: [Binary; 3] = [("min", f64::min), ("max", f64::max), ("pow", f64::powf)];
+
+pub const CONSTANTS: [(&str, f64); 2] = [("pi", consts::PI), ("e", consts::E)];
+
+/// Whether `name` is one of the prelude's functions.
+pub fn is_function(name: &str) -> bool {
+    UNARY.iter().any(|&(other, _)| other == name) || BINARY.iter().any(|&(other, _)| other == name)
+}
+
+/// Whether `name` is the prelude's constant of value `x`.
+pub fn is_constant(name: &str, x: f64) -> bool {
+    CONSTANTS.contains(&(name, x))
+}
+
+/// A prelude function, called with the numbers it's given.
+pub type Call = Box<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+
+/// The prelude as a design's bindings.  `native` makes the value of a
+/// function from its name, arity and call, and `number` that of a constant.
+pub fn bindings<V>(
+    native: impl Fn(&'static str, usize, Call) -> V,
+    number: impl Fn(f64) -> V,
+) -> HashMap<String, V> {
+    let unary = UNARY.into_iter().map(|(name, fun)| {
+        let call: Call = Box::new(move |args| fun(args[0]));
+        (name, native(name, 1, call))
+    });
+    let binary = BINARY.into_iter().map(|(name, fun)| {
+        let call: Call = Box::new(move |args| fun(args[0], args[1]));
+        (name, native(name, 2, call))
+    });
+    let constants = CONSTANTS.into_iter().map(|(name, x)| (name, number(x)));
+    unary
+        .chain(binary)
+        .chain(constants)
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+/// The arguments of a call to `name` as numbers, given how to get the number
+/// of a value if it is one.
+pub fn numbers<V>(
+    name: &str,
+    args: &[V],
+    number: impl Fn(&V) -> Option<f64>,
+) -> Result<Vec<f64>, String> {
+    args.iter()
+        .map(|arg| number(arg).ok_or_else(|| format!("{name} only supported for numbers")))
+        .collect()
+}
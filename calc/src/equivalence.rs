@@ -0,0 +1,131 @@
+//! Checks that every design evaluates the same programs to the same values.
+//!
+//! The programs are the well-typed ones that `typed_async` generates, with
+//! their types erased for the untyped designs.
+use proptest::prelude::*;
+
+use crate::{
+    mutable_self, mutable_self_async, recursion_crate,
+    typed_async::{self, ast::Stmt, erase, generate::program, unparser::Unparser},
+};
+
+/// What the designs' values have in common: numbers, and the parameters of
+/// functions.
+#[derive(Debug)]
+enum Shape {
+    Number(f64),
+    Function(Vec<String>),
+    Native(String),
+}
+
+impl PartialEq for Shape {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Shape::Number(a), Shape::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Shape::Function(a), Shape::Function(b)) => a == b,
+            (Shape::Native(a), Shape::Native(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl From<&mutable_self::runtime::Value> for Shape {
+    fn from(value: &mutable_self::runtime::Value) -> Self {
+        use mutable_self::runtime::Value;
+        match value {
+            Value::Number(n) => Shape::Number(*n),
+            Value::Closure(_, fun, _) => Shape::Function(fun.params.clone()),
+            Value::Native(native) => Shape::Native(native.name.clone()),
+        }
+    }
+}
+
+impl From<&mutable_self_async::runtime::Value> for Shape {
+    fn from(value: &mutable_self_async::runtime::Value) -> Self {
+        use mutable_self_async::runtime::Value;
+        match value {
+            Value::Number(n) => Shape::Number(*n),
+            Value::Closure(_, fun, _) => Shape::Function(fun.params.clone()),
+            Value::Native(native) => Shape::Native(native.name.clone()),
+        }
+    }
+}
+
+impl From<&recursion_crate::runtime::Value> for Shape {
+    fn from(value: &recursion_crate::runtime::Value) -> Self {
+        use recursion_crate::runtime::Value;
+        match value {
+            Value::Number(n) => Shape::Number(*n),
+            Value::Closure(_, fun, _) => Shape::Function(fun.params.clone()),
+            Value::Native(native) => Shape::Native(native.name.clone()),
+        }
+    }
+}
+
+impl From<&typed_async::runtime::Value> for Shape {
+    fn from(value: &typed_async::runtime::Value) -> Self {
+        use typed_async::runtime::Value;
+        match value {
+            Value::Number(n) => Shape::Number(*n),
+            Value::Closure(_, fun, _) => {
+                Shape::Function(fun.params.iter().map(|param| param.name.clone()).collect())
+            }
+            Value::Native(native) => Shape::Native(native.name.clone()),
+        }
+    }
+}
+
+/// The value of a program in one design, and the values of the names it
+/// binds.
+type Outcome = (Shape, Vec<(String, Shape)>);
+
+proptest! {
+    #[test]
+    fn designs_evaluate_programs_to_the_same_values(stmts in program()) {
+        let mut unparser = Unparser::default();
+        unparser.unparse_stmts(&stmts).unwrap();
+        let source = unparser.output();
+
+        let mut recursion = recursion_crate::evaluator::Evaluator::default();
+        let value = recursion.eval_stmts(&erase::recursion_crate::stmts(&stmts));
+        let value = value.map_err(|err| TestCaseError::fail(format!("{source}{err}")))?;
+        let mut names = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Let(name, _, _) | Stmt::Fun(name, _) => name.clone(),
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let outcome = |value, shape: &dyn Fn(&str) -> Shape| -> Outcome {
+            let bindings = names.iter().map(|name| (name.clone(), shape(name))).collect();
+            (value, bindings)
+        };
+        let expected = outcome(Shape::from(&value), &|name| {
+            Shape::from(&recursion.bindings[name])
+        });
+
+        let mut sync = mutable_self::evaluator::Evaluator::default();
+        let value = sync.eval_stmts(&erase::mutable_self::stmts(&stmts));
+        let value = value.map_err(|err| TestCaseError::fail(format!("{source}{err}")))?;
+        let actual = outcome(Shape::from(&value), &|name| Shape::from(&sync.bindings[name]));
+        prop_assert_eq!(&actual, &expected, "mutable_self\n{}", source);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let mut async_ = mutable_self_async::evaluator::Evaluator::default();
+        async_.op_delay = std::time::Duration::ZERO;
+        let value = runtime.block_on(async_.eval_stmts(&erase::mutable_self_async::stmts(&stmts)));
+        let value = value.map_err(|err| TestCaseError::fail(format!("{source}{err}")))?;
+        let actual = outcome(Shape::from(&value), &|name| Shape::from(&async_.bindings[name]));
+        prop_assert_eq!(&actual, &expected, "mutable_self_async\n{}", source);
+
+        let mut typed = typed_async::evaluator::Evaluator::default();
+        let value = runtime.block_on(typed.eval_stmts(&stmts));
+        let value = value.map_err(|err| TestCaseError::fail(format!("{source}{err}")))?;
+        let actual = outcome(Shape::from(&value), &|name| Shape::from(&typed.bindings[name]));
+        prop_assert_eq!(&actual, &expected, "typed_async\n{}", source);
+    }
+}
@@ -0,0 +1,225 @@
+//! Builds arbitrary programs from fuzzer input, for each design's fuzz
+//! targets.
+//!
+//! Each design has a module of its name here whose `stmts` builds a program.
+//! Names are drawn from a few, so that most variables are bound, but the
+//! programs needn't evaluate without errors, nor be well typed.
+use std::ops::ControlFlow;
+
+use arbitrary::{Result, Unstructured};
+
+const NAMES: [&str; 4] = ["a", "b", "c", "d"];
+
+/// How deeply expressions and types nest.
+const MAX_DEPTH: usize = 4;
+
+/// Most statements in a program.
+const MAX_STMTS: u32 = 8;
+
+/// Most parameters of a function, or arguments of a call.
+const MAX_PARAMS: u32 = 3;
+
+/// Longest timeout, kept short since elapsed timeouts take real time.
+#[cfg(feature = "async")]
+const MAX_TIMEOUT: u64 = 2;
+
+fn name(u: &mut Unstructured) -> Result<String> {
+    Ok(u.choose(&NAMES)?.to_string())
+}
+
+/// Calls `f` up to `max` times.
+fn list<T>(
+    u: &mut Unstructured,
+    max: u32,
+    mut f: impl FnMut(&mut Unstructured) -> Result<T>,
+) -> Result<Vec<T>> {
+    let mut items = Vec::new();
+    u.arbitrary_loop(None, Some(max), |u| {
+        items.push(f(u)?);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(items)
+}
+
+/// Defines the module `$design`, whose `stmts` builds an untyped program,
+/// putting functions in `$new`.  Its factors are also those `$extra` builds,
+/// each from a depth that's at least 1.
+macro_rules! untyped {
+    ($design:ident, $new:expr, $extra:expr) => {
+        pub mod $design {
+            use arbitrary::{Result, Unstructured};
+
+            use super::{list, name, MAX_DEPTH, MAX_PARAMS, MAX_STMTS};
+            use crate::$design::ast::{Expr, Factor, FactorBinaryOp, Function, Stmt, TermBinaryOp};
+
+            /// A program of up to a few statements.
+            pub fn stmts(u: &mut Unstructured) -> Result<Vec<Stmt>> {
+                list(u, MAX_STMTS, stmt)
+            }
+
+            fn stmt(u: &mut Unstructured) -> Result<Stmt> {
+                let name = name(u)?;
+                if u.arbitrary()? {
+                    return Ok(Stmt::Let(name, Box::new(expr(u, MAX_DEPTH)?)));
+                }
+                let fun = Function {
+                    params: list(u, MAX_PARAMS, self::name)?,
+                    body: expr(u, MAX_DEPTH)?,
+                };
+                Ok(Stmt::Fun(name, ($new)(fun)))
+            }
+
+            pub(super) fn expr(u: &mut Unstructured, depth: usize) -> Result<Expr> {
+                if depth == 0 || u.arbitrary()? {
+                    return Ok(Expr::Factor(Box::new(factor(u, depth)?)));
+                }
+                let op = if u.arbitrary()? {
+                    TermBinaryOp::Add
+                } else {
+                    TermBinaryOp::Sub
+                };
+                Ok(Expr::BinaryOp {
+                    op,
+                    lhs: Box::new(expr(u, depth - 1)?),
+                    rhs: Box::new(expr(u, depth - 1)?),
+                })
+            }
+
+            fn factor(u: &mut Unstructured, depth: usize) -> Result<Factor> {
+                let extra: &[fn(&mut Unstructured, usize) -> Result<Factor>] = $extra;
+                let choices = if depth == 0 { 2 } else { 5 + extra.len() };
+                Ok(match u.choose_index(choices)? {
+                    0 => Factor::Literal(u.arbitrary()?),
+                    1 => Factor::Variable(name(u)?),
+                    2 => Factor::Group(Box::new(expr(u, depth - 1)?)),
+                    3 => Factor::BinaryOp {
+                        op: if u.arbitrary()? {
+                            FactorBinaryOp::Mul
+                        } else {
+                            FactorBinaryOp::Div
+                        },
+                        lhs: Box::new(factor(u, depth - 1)?),
+                        rhs: Box::new(factor(u, depth - 1)?),
+                    },
+                    4 => Factor::Call(
+                        Box::new(factor(u, depth - 1)?),
+                        list(u, MAX_PARAMS, |u| expr(u, depth - 1))?,
+                    ),
+                    i => extra[i - 5](u, depth)?,
+                })
+            }
+        }
+    };
+}
+
+untyped!(mutable_self, std::rc::Rc::new, &[]);
+#[cfg(feature = "async")]
+untyped!(
+    mutable_self_async,
+    std::sync::Arc::new,
+    &[super::timeout, super::yield_then]
+);
+#[cfg(feature = "recursion")]
+untyped!(recursion_crate, Box::new, &[]);
+
+#[cfg(feature = "async")]
+fn timeout(u: &mut Unstructured, depth: usize) -> Result<crate::mutable_self_async::ast::Factor> {
+    Ok(crate::mutable_self_async::ast::Factor::Timeout {
+        milliseconds: u.int_in_range(0..=MAX_TIMEOUT)?,
+        expr: Box::new(mutable_self_async::expr(u, depth - 1)?),
+    })
+}
+
+#[cfg(feature = "async")]
+fn yield_then(
+    u: &mut Unstructured,
+    depth: usize,
+) -> Result<crate::mutable_self_async::ast::Factor> {
+    let expr = mutable_self_async::expr(u, depth - 1)?;
+    Ok(crate::mutable_self_async::ast::Factor::Yield(Box::new(
+        expr,
+    )))
+}
+
+#[cfg(feature = "typing")]
+pub mod typed_async {
+    use std::sync::Arc;
+
+    use arbitrary::{Result, Unstructured};
+
+    use super::{list, name, MAX_DEPTH, MAX_PARAMS, MAX_STMTS};
+    use crate::typed_async::ast::{
+        Expr, Factor, FactorBinaryOp, Function, Param, Stmt, TermBinaryOp, Type,
+    };
+
+    fn ty(u: &mut Unstructured, depth: usize) -> Result<Type> {
+        if depth == 0 || u.ratio(3, 4)? {
+            return Ok(Type::Number);
+        }
+        let params = list(u, MAX_PARAMS, |u| ty(u, depth - 1))?;
+        Ok(Type::Arrow(params, Box::new(ty(u, depth - 1)?)))
+    }
+
+    /// A program of up to a few statements, with arbitrary types.
+    pub fn stmts(u: &mut Unstructured) -> Result<Vec<Stmt>> {
+        list(u, MAX_STMTS, stmt)
+    }
+
+    fn stmt(u: &mut Unstructured) -> Result<Stmt> {
+        let name = name(u)?;
+        if u.arbitrary()? {
+            let ty = ty(u, MAX_DEPTH)?;
+            return Ok(Stmt::Let(name, ty, Box::new(expr(u, MAX_DEPTH)?)));
+        }
+        let params = list(u, MAX_PARAMS, |u| {
+            Ok(Param {
+                name: self::name(u)?,
+                ty: ty(u, MAX_DEPTH)?,
+            })
+        })?;
+        let fun = Function {
+            params,
+            return_ty: ty(u, MAX_DEPTH)?,
+            body: expr(u, MAX_DEPTH)?,
+        };
+        Ok(Stmt::Fun(name, Arc::new(fun)))
+    }
+
+    fn expr(u: &mut Unstructured, depth: usize) -> Result<Expr> {
+        if depth == 0 || u.arbitrary()? {
+            return Ok(Expr::Factor(Box::new(factor(u, depth)?)));
+        }
+        let op = if u.arbitrary()? {
+            TermBinaryOp::Add
+        } else {
+            TermBinaryOp::Sub
+        };
+        Ok(Expr::BinaryOp {
+            op,
+            lhs: Box::new(expr(u, depth - 1)?),
+            rhs: Box::new(expr(u, depth - 1)?),
+        })
+    }
+
+    fn factor(u: &mut Unstructured, depth: usize) -> Result<Factor> {
+        let choices = if depth == 0 { 2 } else { 5 };
+        Ok(match u.choose_index(choices)? {
+            0 => Factor::Literal(u.arbitrary()?),
+            1 => Factor::Variable(name(u)?),
+            2 => Factor::Group(Box::new(expr(u, depth - 1)?)),
+            3 => Factor::BinaryOp {
+                op: if u.arbitrary()? {
+                    FactorBinaryOp::Mul
+                } else {
+                    FactorBinaryOp::Div
+                },
+                lhs: Box::new(factor(u, depth - 1)?),
+                rhs: Box::new(factor(u, depth - 1)?),
+            },
+            _ => Factor::Call(
+                Box::new(factor(u, depth - 1)?),
+                list(u, MAX_PARAMS, |u| expr(u, depth - 1))?,
+            ),
+        })
+    }
+}
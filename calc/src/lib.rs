@@ -0,0 +1,31 @@
+//! The calc interpreter as a library.
+//!
+//! Each module is one design of the same language: its AST, runtime,
+//! evaluator, unparser and analyses.  `mutable_self` is always built, and
+//! the others are behind features:
+//!
+//! - `async`: [`mutable_self_async`], whose evaluator and visitors are async.
+//! - `typing`: [`typed_async`], which adds types, a type checker, a parser
+//!   and a pretty printer.
+//! - `recursion`: [`recursion_crate`], whose evaluator is a recursion scheme
+//!   from the `recursion` crate.
+//! - `fuzz`: [`fuzz`], which builds arbitrary programs for the fuzz targets
+//!   of the designs that are built.
+//!
+//! What the designs share is in [`common`].  The `async` feature also brings
+//! in [`protocol`], the message framing that the binaries' debug adapter and
+//! language server share.
+pub mod common;
+#[cfg(all(test, feature = "recursion", feature = "typing"))]
+mod equivalence;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod mutable_self;
+#[cfg(feature = "async")]
+pub mod mutable_self_async;
+#[cfg(feature = "async")]
+pub mod protocol;
+#[cfg(feature = "recursion")]
+pub mod recursion_crate;
+#[cfg(feature = "typing")]
+pub mod typed_async;
@@ -0,0 +1,19 @@
+//! Synchronous evaluation, with analyses as visitors that own their state.
+//!
+//! `profile` is built from the same source file as in the async designs.
+pub mod analysis;
+pub mod ast;
+pub mod count;
+pub mod coverage;
+pub mod debugger;
+pub mod evaluator;
+pub mod free_vars;
+pub mod lint;
+pub mod log;
+pub mod node_id;
+pub mod prelude;
+#[path = "shared/profile.rs"]
+pub mod profile;
+pub mod runtime;
+pub mod trace;
+pub mod unparser;
@@ -0,0 +1,260 @@
+//! Static analyses fused into a single pass.
+//!
+//! Each analysis registers hooks that run before, between and after the
+//! children of every node.  The [`Driver`] traverses the AST once and fans each
+//! node out to all of its analyses before moving on to the next node.
+use crate::mutable_self::{
+    ast::{Expr, Factor, Stmt},
+    runtime::Error,
+};
+
+pub trait Analysis: std::fmt::Debug {
+    fn pre_stmt(&mut self, _stmt: &Stmt) -> Result<(), Error> {
+        Ok(())
+    }
+    fn post_stmt(&mut self, _stmt: &Stmt) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre_expr(&mut self, _expr: &Expr) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called before visiting each child of `expr` after the first.
+    fn between_expr(&mut self, _expr: &Expr, _child: usize) -> Result<(), Error> {
+        Ok(())
+    }
+    fn post_expr(&mut self, _expr: &Expr) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, _factor: &Factor) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called before visiting each child of `factor` after the first.  The
+    /// children of a call are the function followed by the arguments.
+    fn between_factor(&mut self, _factor: &Factor, _child: usize) -> Result<(), Error> {
+        Ok(())
+    }
+    fn post_factor(&mut self, _factor: &Factor) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Runs several analyses in one traversal.
+///
+/// Pre-order and between hooks run in the order analyses were added, and
+/// post-order hooks run in reverse.
+#[derive(Debug, Default)]
+pub struct Driver<'a> {
+    pub analyses: Vec<&'a mut dyn Analysis>,
+}
+
+impl Driver<'_> {
+    pub fn run_stmts(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+        for stmt in stmts {
+            self.run_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn run_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        for analysis in self.analyses.iter_mut() {
+            analysis.pre_stmt(stmt)?;
+        }
+        match stmt {
+            Stmt::Let(_, expr) => self.run_expr(expr)?,
+            Stmt::Fun(_, fun) => self.run_expr(&fun.body)?,
+        }
+        for analysis in self.analyses.iter_mut().rev() {
+            analysis.post_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn run_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+        for analysis in self.analyses.iter_mut() {
+            analysis.pre_expr(expr)?;
+        }
+        match expr {
+            Expr::Factor(factor) => self.run_factor(factor)?,
+            Expr::BinaryOp { op: _, lhs, rhs } => {
+                self.run_expr(lhs)?;
+                for analysis in self.analyses.iter_mut() {
+                    analysis.between_expr(expr, 1)?;
+                }
+                self.run_expr(rhs)?;
+            }
+        }
+        for analysis in self.analyses.iter_mut().rev() {
+            analysis.post_expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn run_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        for analysis in self.analyses.iter_mut() {
+            analysis.pre_factor(factor)?;
+        }
+        match factor {
+            Factor::Literal(_) | Factor::Variable(_) => {}
+            Factor::Group(expr) => self.run_expr(expr)?,
+            Factor::BinaryOp { op: _, lhs, rhs } => {
+                self.run_factor(lhs)?;
+                for analysis in self.analyses.iter_mut() {
+                    analysis.between_factor(factor, 1)?;
+                }
+                self.run_factor(rhs)?;
+            }
+            Factor::Call(fun, args) => {
+                self.run_factor(fun)?;
+                for (i, arg) in args.iter().enumerate() {
+                    for analysis in self.analyses.iter_mut() {
+                        analysis.between_factor(factor, i + 1)?;
+                    }
+                    self.run_expr(arg)?;
+                }
+            }
+        }
+        for analysis in self.analyses.iter_mut().rev() {
+            analysis.post_factor(factor)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// Records its hook calls, tagged with its name, into a shared log.
+    #[derive(Debug)]
+    struct Record(&'static str, Rc<RefCell<Vec<String>>>);
+
+    impl Record {
+        fn log(&self, event: String) -> Result<(), Error> {
+            self.1.borrow_mut().push(format!("{} {event}", self.0));
+            Ok(())
+        }
+    }
+
+    impl Analysis for Record {
+        fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+            self.log(format!("pre {}", stmt.kind()))
+        }
+        fn post_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+            self.log(format!("post {}", stmt.kind()))
+        }
+        fn pre_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+            self.log(format!("pre {}", expr.kind()))
+        }
+        fn between_expr(&mut self, expr: &Expr, child: usize) -> Result<(), Error> {
+            self.log(format!("between {} {child}", expr.kind()))
+        }
+        fn post_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+            self.log(format!("post {}", expr.kind()))
+        }
+        fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+            self.log(format!("pre {}", factor.kind()))
+        }
+        fn between_factor(&mut self, factor: &Factor, child: usize) -> Result<(), Error> {
+            self.log(format!("between {} {child}", factor.kind()))
+        }
+        fn post_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+            self.log(format!("post {}", factor.kind()))
+        }
+    }
+
+    /// Fails when it's about to see the second argument of a call.
+    #[derive(Debug)]
+    struct FailBetween;
+
+    impl Analysis for FailBetween {
+        fn between_factor(&mut self, _factor: &Factor, child: usize) -> Result<(), Error> {
+            if child == 2 {
+                return Err("second argument".into());
+            }
+            Ok(())
+        }
+    }
+
+    /// `let x = f(1, 2)`.
+    fn call() -> Vec<Stmt> {
+        let num = |x| Expr::Factor(Box::new(Factor::Literal(x)));
+        vec![Stmt::Let(
+            "x".to_owned(),
+            Box::new(Expr::Factor(Box::new(Factor::Call(
+                Box::new(Factor::Variable("f".to_owned())),
+                vec![num(1.0), num(2.0)],
+            )))),
+        )]
+    }
+
+    #[test]
+    fn hooks_run_in_order_and_post_hooks_in_reverse() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (mut a, mut b) = (Record("a", log.clone()), Record("b", log.clone()));
+        let mut driver = Driver {
+            analyses: vec![&mut a, &mut b],
+        };
+        driver.run_stmts(&call()).unwrap();
+
+        let expected = [
+            "a pre Stmt::Let",
+            "b pre Stmt::Let",
+            "a pre Expr::Factor",
+            "b pre Expr::Factor",
+            "a pre Factor::Call",
+            "b pre Factor::Call",
+            "a pre Factor::Variable",
+            "b pre Factor::Variable",
+            "b post Factor::Variable",
+            "a post Factor::Variable",
+            "a between Factor::Call 1",
+            "b between Factor::Call 1",
+            "a pre Expr::Factor",
+            "b pre Expr::Factor",
+            "a pre Factor::Literal",
+            "b pre Factor::Literal",
+            "b post Factor::Literal",
+            "a post Factor::Literal",
+            "b post Expr::Factor",
+            "a post Expr::Factor",
+            "a between Factor::Call 2",
+            "b between Factor::Call 2",
+            "a pre Expr::Factor",
+            "b pre Expr::Factor",
+            "a pre Factor::Literal",
+            "b pre Factor::Literal",
+            "b post Factor::Literal",
+            "a post Factor::Literal",
+            "b post Expr::Factor",
+            "a post Expr::Factor",
+            "b post Factor::Call",
+            "a post Factor::Call",
+            "b post Expr::Factor",
+            "a post Expr::Factor",
+            "b post Stmt::Let",
+            "a post Stmt::Let",
+        ];
+        assert_eq!(*log.borrow(), expected);
+    }
+
+    #[test]
+    fn an_error_stops_the_traversal() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (mut fail, mut record) = (FailBetween, Record("a", log.clone()));
+        let mut driver = Driver {
+            analyses: vec![&mut fail, &mut record],
+        };
+        let err = driver.run_stmts(&call()).unwrap_err();
+
+        assert_eq!(err.to_string(), "second argument");
+        // Nothing after the first argument, not even the later analysis's
+        // between hook.
+        let log = log.borrow();
+        assert_eq!(log.last().unwrap(), "a post Expr::Factor");
+        assert!(!log.contains(&"a between Factor::Call 2".to_owned()));
+    }
+}
@@ -1,21 +1,23 @@
-pub(crate) type Identifier = String;
+use std::rc::Rc;
+
+pub type Identifier = String;
 
 #[derive(Debug, Clone)]
-pub(crate) enum Stmt {
+pub enum Stmt {
     Let(Identifier, Box<Expr>),
-    Fun(Identifier, Box<Function>),
+    Fun(Identifier, Rc<Function>),
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct Function {
+pub struct Function {
     pub params: Params,
     pub body: Expr,
 }
 
-pub(crate) type Params = Vec<Identifier>;
+pub type Params = Vec<Identifier>;
 
 #[derive(Debug, Clone)]
-pub(crate) enum Expr {
+pub enum Expr {
     Factor(Box<Factor>),
     BinaryOp {
         op: TermBinaryOp,
@@ -25,14 +27,14 @@ pub(crate) enum Expr {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum TermBinaryOp {
+pub enum TermBinaryOp {
     Add,
     #[allow(dead_code)]
     Sub,
 }
 
 #[derive(Debug, Clone)]
-pub(crate) enum Factor {
+pub enum Factor {
     Literal(f64),
     Variable(Identifier),
     #[allow(dead_code)]
@@ -47,9 +49,15 @@ pub(crate) enum Factor {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum FactorBinaryOp {
+pub enum FactorBinaryOp {
     #[allow(dead_code)]
     Mul,
     #[allow(dead_code)]
     Div,
 }
+
+crate::common::node_kinds! {
+    Stmt { Let, Fun }
+    Expr { Factor, BinaryOp }
+    Factor { Literal, Variable, Group, BinaryOp, Call }
+}
@@ -0,0 +1,87 @@
+//! Counts AST nodes by kind.
+use crate::mutable_self::{
+    analysis::Analysis,
+    ast::{Expr, Factor, Stmt},
+    runtime::Error,
+};
+
+#[derive(Debug, Default)]
+pub struct NodeCount {
+    pub stmts: usize,
+    pub exprs: usize,
+    pub factors: usize,
+}
+
+impl Analysis for NodeCount {
+    fn pre_stmt(&mut self, _stmt: &Stmt) -> Result<(), Error> {
+        self.stmts += 1;
+        Ok(())
+    }
+
+    fn pre_expr(&mut self, _expr: &Expr) -> Result<(), Error> {
+        self.exprs += 1;
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, _factor: &Factor) -> Result<(), Error> {
+        self.factors += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mutable_self::{
+        analysis::Driver,
+        ast::{Function, TermBinaryOp},
+    };
+
+    #[test]
+    fn counts_every_node_once() {
+        let var = |name: &str| Expr::Factor(Box::new(Factor::Variable(name.to_owned())));
+        // fun add(a, b) = a + b
+        // let x = add(1, (2))
+        let stmts = vec![
+            Stmt::Fun(
+                "add".to_owned(),
+                Rc::new(Function {
+                    params: vec!["a".to_owned(), "b".to_owned()],
+                    body: Expr::BinaryOp {
+                        op: TermBinaryOp::Add,
+                        lhs: Box::new(var("a")),
+                        rhs: Box::new(var("b")),
+                    },
+                }),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(Expr::Factor(Box::new(Factor::Call(
+                    Box::new(Factor::Variable("add".to_owned())),
+                    vec![
+                        Expr::Factor(Box::new(Factor::Literal(1.0))),
+                        Expr::Factor(Box::new(Factor::Group(Box::new(Expr::Factor(Box::new(
+                            Factor::Literal(2.0),
+                        )))))),
+                    ],
+                )))),
+            ),
+        ];
+        let mut count = NodeCount::default();
+        Driver {
+            analyses: vec![&mut count],
+        }
+        .run_stmts(&stmts)
+        .unwrap();
+
+        assert_eq!(count.stmts, 2);
+        // The sum and its two operands, then the call and its two arguments,
+        // one of which is inside a group.
+        assert_eq!(count.exprs, 3 + 4);
+        // Two variables, then the call, its function, a literal, the group
+        // and the literal inside it.
+        assert_eq!(count.factors, 2 + 5);
+    }
+}
@@ -0,0 +1,275 @@
+//! A dynamic analysis that measures which parts of a program were evaluated.
+//!
+//! Prints the unparsed program annotated with hit counts and writes an
+//! [LCOV](https://github.com/linux-test-project/lcov) tracefile against it,
+//! so coverage tools can highlight lines and functions that never ran.
+use std::{fmt::Write, fs, path::PathBuf};
+
+use crate::mutable_self::{
+    analysis::{Analysis, Driver},
+    ast::{Expr, Factor, Identifier, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, Error, Value},
+    unparser::unparse,
+};
+
+/// A node of the program, indexed by its id.
+#[derive(Debug)]
+struct Node {
+    line: usize,
+    /// Id of the enclosing node, or `None` for a statement.
+    parent: Option<usize>,
+    source: String,
+}
+
+#[derive(Debug)]
+struct FunctionDef {
+    name: Identifier,
+    line: usize,
+    /// Id of the body, which is visited once per call.
+    body: usize,
+}
+
+/// Collects the nodes of a program in id order.
+#[derive(Debug)]
+struct Collect<'a> {
+    ids: &'a NodeIds,
+    nodes: Vec<Node>,
+    functions: Vec<FunctionDef>,
+    open: Vec<usize>,
+}
+
+impl Collect<'_> {
+    fn enter(&mut self, location: Option<Location>, source: String) {
+        let location = location.expect("every node has an id");
+        // Shared nodes are collected where they first appear.
+        if location.id == self.nodes.len() {
+            self.nodes.push(Node {
+                line: location.line,
+                parent: self.open.last().copied(),
+                source,
+            });
+        }
+        self.open.push(location.id);
+    }
+
+    fn exit(&mut self) -> Result<(), Error> {
+        self.open.pop();
+        Ok(())
+    }
+}
+
+impl Analysis for Collect<'_> {
+    fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        let location = self.ids.stmt(stmt);
+        if let Stmt::Fun(name, fun) = stmt {
+            let body = self.ids.expr(&fun.body).expect("every node has an id");
+            if !self.functions.iter().any(|def| def.body == body.id) {
+                self.functions.push(FunctionDef {
+                    name: name.clone(),
+                    line: body.line,
+                    body: body.id,
+                });
+            }
+        }
+        self.enter(location, unparse(|unparser| unparser.unparse_stmt(stmt)));
+        Ok(())
+    }
+    fn post_stmt(&mut self, _stmt: &Stmt) -> Result<(), Error> {
+        self.exit()
+    }
+
+    fn pre_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+        let location = self.ids.expr(expr);
+        self.enter(location, unparse(|unparser| unparser.unparse_expr(expr)));
+        Ok(())
+    }
+    fn post_expr(&mut self, _expr: &Expr) -> Result<(), Error> {
+        self.exit()
+    }
+
+    fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        let location = self.ids.factor(factor);
+        self.enter(
+            location,
+            unparse(|unparser| unparser.unparse_factor(factor)),
+        );
+        Ok(())
+    }
+    fn post_factor(&mut self, _factor: &Factor) -> Result<(), Error> {
+        self.exit()
+    }
+}
+
+/// Counts how many times each node is visited.  When evaluation finishes,
+/// prints an annotated listing, and writes the unparsed program and an LCOV
+/// tracefile for it.
+#[derive(Debug)]
+pub struct Coverage {
+    source_path: PathBuf,
+    lcov_path: PathBuf,
+    ids: NodeIds,
+    nodes: Vec<Node>,
+    functions: Vec<FunctionDef>,
+    /// Visits per node, indexed by id.
+    pub hits: Vec<usize>,
+}
+
+impl Coverage {
+    /// Measures the coverage of `stmts`, which must be the statements that
+    /// are evaluated rather than a copy.
+    pub fn new(
+        stmts: &[Stmt],
+        source_path: impl Into<PathBuf>,
+        lcov_path: impl Into<PathBuf>,
+    ) -> Self {
+        let ids = NodeIds::new(stmts);
+        let mut collect = Collect {
+            ids: &ids,
+            nodes: Vec::new(),
+            functions: Vec::new(),
+            open: Vec::new(),
+        };
+        let mut driver = Driver::default();
+        driver.analyses.push(&mut collect);
+        driver
+            .run_stmts(stmts)
+            .expect("collecting nodes never fails");
+        let Collect {
+            nodes, functions, ..
+        } = collect;
+        Self {
+            source_path: source_path.into(),
+            lcov_path: lcov_path.into(),
+            hits: vec![0; nodes.len()],
+            ids,
+            nodes,
+            functions,
+        }
+    }
+
+    fn hit(&mut self, location: Option<Location>) {
+        if let Some(location) = location {
+            self.hits[location.id] += 1;
+        }
+    }
+
+    /// The unparsed program, one statement per line.
+    pub fn source(&self) -> String {
+        let mut out = String::new();
+        for node in self.nodes.iter().filter(|node| node.parent.is_none()) {
+            out.push_str(&node.source);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Visits of the most visited node on each line, indexed by line - 1.
+    fn line_hits(&self) -> Vec<usize> {
+        let mut lines = Vec::new();
+        for (node, &hits) in self.nodes.iter().zip(&self.hits) {
+            if node.line > lines.len() {
+                lines.resize(node.line, 0);
+            }
+            lines[node.line - 1] = lines[node.line - 1].max(hits);
+        }
+        lines
+    }
+
+    /// Each statement prefixed by the number of times it was evaluated.  Calls
+    /// are counted for every function, and under each line are the outermost
+    /// nodes that were never evaluated.
+    pub fn listing(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let hits = self.hits[id];
+            let Some(parent) = node.parent else {
+                writeln!(out, "{hits:>6} | {}", node.source)?;
+                continue;
+            };
+            if let Some(fun) = self.functions.iter().find(|fun| fun.body == id) {
+                let marker = if hits == 0 { "!" } else { " " };
+                writeln!(out, "{marker:>6} |   `{}`: {hits} calls", fun.name)?;
+            } else if hits == 0 && self.hits[parent] > 0 {
+                writeln!(out, "{:>6} |   never evaluated: {}", "!", node.source)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// An LCOV tracefile for the program written by [`Self::source`].
+    pub fn lcov(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", self.source_path.display())?;
+        for fun in &self.functions {
+            writeln!(out, "FN:{},{}", fun.line, fun.name)?;
+        }
+        for fun in &self.functions {
+            writeln!(out, "FNDA:{},{}", self.hits[fun.body], fun.name)?;
+        }
+        let called = self.functions.iter().filter(|fun| self.hits[fun.body] > 0);
+        writeln!(out, "FNF:{}", self.functions.len())?;
+        writeln!(out, "FNH:{}", called.count())?;
+        let lines = self.line_hits();
+        for (i, hits) in lines.iter().enumerate() {
+            writeln!(out, "DA:{},{hits}", i + 1)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.iter().filter(|&&hits| hits > 0).count())?;
+        writeln!(out, "end_of_record")?;
+        Ok(out)
+    }
+}
+
+impl runtime::Visit for Coverage {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.stmt(stmt));
+        Ok(None)
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.expr(expr));
+        Ok(None)
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.factor(factor));
+        Ok(None)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        print!("{}", self.listing()?);
+        fs::write(&self.source_path, self.source())?;
+        fs::write(&self.lcov_path, self.lcov()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mutable_self::ast::Function;
+
+    #[test]
+    fn statements_sharing_a_function() {
+        let fun = Stmt::Fun(
+            "f".to_owned(),
+            Rc::new(Function {
+                params: vec!["a".to_owned()],
+                body: Expr::Factor(Box::new(Factor::Variable("a".to_owned()))),
+            }),
+        );
+        let stmts = vec![fun.clone(), fun];
+        let coverage = Coverage::new(&stmts, "shared.calc", "shared.lcov");
+
+        // The body is collected once, under the first statement.
+        assert_eq!(coverage.nodes.len(), 4);
+        assert_eq!(coverage.nodes[3].parent, None);
+        assert_eq!(coverage.source(), "fun f(a) = a\nfun f(a) = a\n");
+        let lcov = coverage.lcov().unwrap();
+        assert!(lcov.contains("FN:1,f\nFNDA:0,f\nFNF:1\n"));
+    }
+}
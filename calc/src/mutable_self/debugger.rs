@@ -0,0 +1,485 @@
+//! An interactive step debugger driven by the visitor hooks.
+//!
+//! Evaluation pauses in the pre-visit of a node when a breakpoint matches or
+//! a step finishes.  While paused, the debugger reads commands from its input
+//! until one of them resumes evaluation.
+use std::{
+    io::{BufRead, Write},
+    str::FromStr,
+};
+
+use crate::mutable_self::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, Env, Error, Value},
+    unparser::unparse,
+};
+
+const HELP: &str = "\
+step, s              evaluate until the next node
+next, n              evaluate until the next node that isn't nested in this one
+out, o               evaluate until the current function returns
+continue, c          evaluate until a breakpoint
+print, p NAME        show the value bound to NAME
+vars, v              show every binding in scope
+backtrace, bt        show the calls in progress
+break, b fun NAME    pause when NAME is called
+break, b line N      pause on line N
+break, b node N      pause on the node with id N
+clear                remove every breakpoint
+quit, q              stop evaluating
+";
+
+/// Where evaluation pauses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The body of any call to a function defined with this name.
+    Function(Identifier),
+    /// The first node evaluated on this line of the unparsed program.
+    Line(usize),
+    /// The node with this id.
+    Node(usize),
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["fun", name] => Ok(Breakpoint::Function(name.to_owned())),
+            ["line", line] => line
+                .parse()
+                .map(Breakpoint::Line)
+                .map_err(|_| format!("Invalid line: {line}")),
+            ["node", id] => id
+                .parse()
+                .map(Breakpoint::Node)
+                .map_err(|_| format!("Invalid node id: {id}")),
+            _ => Err("Expected `fun NAME`, `line N` or `node N`".to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    StepInto,
+    StepOver,
+    StepOut,
+    Continue,
+    Print(Identifier),
+    Vars,
+    Backtrace,
+    Break(Breakpoint),
+    Clear,
+    Quit,
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (word, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        match (word, rest) {
+            ("step" | "s", "") => Ok(Command::StepInto),
+            ("next" | "n", "") => Ok(Command::StepOver),
+            ("out" | "o", "") => Ok(Command::StepOut),
+            ("continue" | "c", "") => Ok(Command::Continue),
+            ("print" | "p", name) if !name.is_empty() => Ok(Command::Print(name.to_owned())),
+            ("vars" | "v", "") => Ok(Command::Vars),
+            ("backtrace" | "bt", "") => Ok(Command::Backtrace),
+            ("break" | "b", breakpoint) => breakpoint.parse().map(Command::Break),
+            ("clear", "") => Ok(Command::Clear),
+            ("quit" | "q", "") => Ok(Command::Quit),
+            ("help" | "h", "") => Ok(Command::Help),
+            _ => Err(format!("Unknown command: {s}.  Type `help` for a list.")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    /// Pause at the next node.
+    StepInto,
+    /// Pause at the next node nested in no more than this many others.
+    StepOver(usize),
+    /// Pause at the next node when fewer than this many scopes are open.
+    StepOut(usize),
+}
+
+/// The bindings of a call in progress, or of the top level.
+#[derive(Debug)]
+struct Scope {
+    name: Identifier,
+    env: Env,
+}
+
+/// A node that has been pre-visited but not post-visited.
+#[derive(Debug)]
+struct Open {
+    /// Address of the node.
+    node: usize,
+    /// Number of scopes when the node was entered.
+    scopes: usize,
+}
+
+/// Pauses evaluation at breakpoints and steps through it.
+///
+/// Bindings start out as those given to `new()`, and are then mirrored from
+/// `on_bind()` and `on_call()`, so they match `Evaluator::bindings` when
+/// given the evaluator's bindings.
+pub struct Debugger {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    ids: NodeIds,
+    pub breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    open: Vec<Open>,
+    /// The top level first, then each call in progress.
+    scopes: Vec<Scope>,
+    /// A function that was just called and whose body hasn't been entered.
+    called: Option<Identifier>,
+    /// Line of the last node pre-visited.
+    line: usize,
+}
+
+impl Debugger {
+    /// Debugs the evaluation of `stmts` from `bindings`, reading commands from
+    /// `input` and writing to `output`.  Evaluation pauses before the first
+    /// statement.
+    pub fn new(
+        input: impl BufRead + 'static,
+        output: impl Write + 'static,
+        stmts: &[Stmt],
+        bindings: &Env,
+    ) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            ids: NodeIds::new(stmts),
+            breakpoints: Vec::new(),
+            mode: Mode::StepInto,
+            open: Vec::new(),
+            scopes: vec![Scope {
+                name: "<top level>".to_owned(),
+                env: bindings.clone(),
+            }],
+            called: None,
+            line: 0,
+        }
+    }
+
+    fn should_pause(&self, location: Location) -> bool {
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(depth) => self.open.len() <= depth,
+            Mode::StepOut(scopes) => self.scopes.len() < scopes,
+        };
+        stepped
+            || self.breakpoints.iter().any(|breakpoint| match breakpoint {
+                Breakpoint::Function(name) => self.called.as_ref() == Some(name),
+                Breakpoint::Line(line) => *line == location.line && self.line != location.line,
+                Breakpoint::Node(id) => *id == location.id,
+            })
+    }
+
+    fn enter(
+        &mut self,
+        node: usize,
+        location: Option<Location>,
+        kind: &str,
+        source: impl FnOnce() -> String,
+    ) -> Result<(), Error> {
+        if let Some(location) = location {
+            if self.should_pause(location) {
+                writeln!(
+                    self.output,
+                    "Paused at line {} node {} ({kind}): {}",
+                    location.line,
+                    location.id,
+                    source()
+                )?;
+                self.pause()?;
+            }
+            self.line = location.line;
+        }
+        self.called = None;
+        self.open.push(Open {
+            node,
+            scopes: self.scopes.len(),
+        });
+        Ok(())
+    }
+
+    /// Closes `node`, along with any nodes inside it that weren't closed.
+    fn exit(&mut self, node: usize) {
+        while let Some(open) = self.open.pop() {
+            self.scopes.truncate(open.scopes);
+            if open.node == node {
+                break;
+            }
+        }
+    }
+
+    /// Runs commands until one resumes evaluation.
+    fn pause(&mut self) -> Result<(), Error> {
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                // Nobody is left to send commands.
+                self.mode = Mode::Continue;
+                return Ok(());
+            }
+            let command = match line.parse() {
+                Ok(command) => command,
+                Err(message) => {
+                    writeln!(self.output, "{message}")?;
+                    continue;
+                }
+            };
+            match command {
+                Command::StepInto => return self.resume(Mode::StepInto),
+                Command::StepOver => return self.resume(Mode::StepOver(self.open.len())),
+                Command::StepOut => return self.resume(Mode::StepOut(self.scopes.len())),
+                Command::Continue => return self.resume(Mode::Continue),
+                Command::Print(name) => match self.scopes[self.scopes.len() - 1].env.get(&name) {
+                    Some(value) => writeln!(self.output, "{name} = {value}")?,
+                    None => writeln!(self.output, "{name} is not bound")?,
+                },
+                Command::Vars => {
+                    let scope = &self.scopes[self.scopes.len() - 1];
+                    let mut bindings = scope.env.iter().collect::<Vec<_>>();
+                    bindings.sort_by_key(|(name, _)| *name);
+                    for (name, value) in bindings {
+                        writeln!(self.output, "{name} = {value}")?;
+                    }
+                }
+                Command::Backtrace => {
+                    for scope in self.scopes.iter().rev() {
+                        writeln!(self.output, "  in {}", scope.name)?;
+                    }
+                }
+                Command::Break(breakpoint) => self.breakpoints.push(breakpoint),
+                Command::Clear => self.breakpoints.clear(),
+                Command::Quit => return Err("Quit debugging".into()),
+                Command::Help => write!(self.output, "{HELP}")?,
+            }
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) -> Result<(), Error> {
+        self.mode = mode;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints)
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for Debugger {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        let location = self.ids.stmt(stmt);
+        self.enter(stmt as *const Stmt as usize, location, stmt.kind(), || {
+            unparse(|unparser| unparser.unparse_stmt(stmt))
+        })?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(stmt as *const Stmt as usize);
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let location = self.ids.expr(expr);
+        self.enter(expr as *const Expr as usize, location, expr.kind(), || {
+            unparse(|unparser| unparser.unparse_expr(expr))
+        })?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(expr as *const Expr as usize);
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let location = self.ids.factor(factor);
+        let kind = factor.kind();
+        self.enter(factor as *const Factor as usize, location, kind, || {
+            unparse(|unparser| unparser.unparse_factor(factor))
+        })?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(factor as *const Factor as usize);
+        result
+    }
+
+    fn on_call(&mut self, fun: &Identifier, _args: &[Value], env: &Env) -> Result<(), Error> {
+        self.scopes.push(Scope {
+            name: fun.clone(),
+            env: env.clone(),
+        });
+        self.called = Some(fun.clone());
+        Ok(())
+    }
+
+    fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("the top level is never closed");
+        scope.env.insert(name.clone(), value.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::mutable_self::{
+        ast::{Function, TermBinaryOp},
+        evaluator::Evaluator,
+    };
+
+    /// A writer to a buffer that can be read after the writer is given away.
+    #[derive(Debug, Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        Expr::Factor(Box::new(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        )))
+    }
+
+    /// ```text
+    /// fun add(a, b) = a + b
+    /// let x = add(1, 2)
+    /// let y = price(x)
+    /// ```
+    fn program() -> Vec<Stmt> {
+        let num = |x| Expr::Factor(Box::new(Factor::Literal(x)));
+        let body = Expr::BinaryOp {
+            op: TermBinaryOp::Add,
+            lhs: Box::new(var("a")),
+            rhs: Box::new(var("b")),
+        };
+        vec![
+            Stmt::Fun(
+                "add".to_owned(),
+                Rc::new(Function {
+                    params: vec!["a".to_owned(), "b".to_owned()],
+                    body,
+                }),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("add", vec![num(1.0), num(2.0)])),
+            ),
+            Stmt::Let("y".to_owned(), Box::new(call("price", vec![var("x")]))),
+        ]
+    }
+
+    /// Evaluates [`program`] with a host function `price`, running `commands`
+    /// whenever the debugger pauses, and returns what it wrote.
+    fn debug(commands: &str) -> (Result<Value, Error>, String) {
+        let stmts = program();
+        let mut evaluator = Evaluator::default();
+        evaluator.register_fn("price", 1, |args| match args {
+            [Value::Number(quantity)] => Ok(Value::Number(quantity * 2.5)),
+            _ => Err("Expected a quantity".into()),
+        });
+        let output = Buffer::default();
+        let debugger = Debugger::new(
+            std::io::Cursor::new(commands.to_owned()),
+            output.clone(),
+            &stmts,
+            &evaluator.bindings,
+        );
+        evaluator.visitors.push(Box::new(debugger));
+        let result = evaluator.eval_stmts(&stmts);
+        let output = String::from_utf8(output.0.take()).unwrap();
+        (result, output)
+    }
+
+    #[test]
+    fn function_breakpoints_and_stepping_out() {
+        let (result, output) = debug("b fun add\nc\nbt\np a\no\np x\np price\nc\n");
+
+        assert!(matches!(result, Ok(Value::Number(y)) if y == 7.5));
+        assert_eq!(
+            output,
+            "\
+Paused at line 1 node 0 (Stmt::Fun): fun add(a, b) = a + b
+(debug) (debug) Paused at line 1 node 1 (Expr::BinaryOp): a + b
+(debug)   in add
+  in <top level>
+(debug) a = 1
+(debug) Paused at line 3 node 14 (Stmt::Let): let y = price(x)
+(debug) x = 3
+(debug) price = <native fun price>
+(debug) "
+        );
+    }
+
+    #[test]
+    fn line_and_node_breakpoints_and_stepping_over() {
+        let (result, output) = debug("n\nb node 9\nb line 3\nc\nc\ns\nq\n");
+
+        assert_eq!(result.unwrap_err().to_string(), "Quit debugging");
+        assert_eq!(
+            output,
+            "\
+Paused at line 1 node 0 (Stmt::Fun): fun add(a, b) = a + b
+(debug) Paused at line 2 node 6 (Stmt::Let): let x = add(1, 2)
+(debug) (debug) (debug) Paused at line 2 node 9 (Factor::Variable): add
+(debug) Paused at line 3 node 14 (Stmt::Let): let y = price(x)
+(debug) Paused at line 3 node 15 (Expr::Factor): price(x)
+(debug) "
+        );
+    }
+}
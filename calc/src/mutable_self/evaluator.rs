@@ -0,0 +1,513 @@
+use std::rc::Rc;
+
+use crate::mutable_self::{
+    ast::{Expr, Factor, FactorBinaryOp, Identifier, Stmt, TermBinaryOp},
+    prelude,
+    runtime::{Env, Error, Frame, Native, StackOverflow, TracedError, Value, Visit},
+};
+
+pub use crate::common::DEFAULT_MAX_CALL_DEPTH;
+
+#[derive(Debug)]
+pub struct Evaluator {
+    /// Starts out with the built-ins from [`prelude`].
+    pub bindings: Env,
+    pub visitors: Vec<Box<dyn Visit>>,
+    /// Calls nested deeper than this fail with a [`StackOverflow`] error.
+    pub max_call_depth: usize,
+    /// Calls currently in progress, outermost first.
+    call_stack: Vec<Frame>,
+    /// Snapshot of `call_stack`, innermost first, taken where the current
+    /// error was raised.
+    error_backtrace: Option<Vec<Frame>>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            bindings: prelude::bindings(),
+            visitors: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_stack: Vec::new(),
+            error_backtrace: None,
+        }
+    }
+}
+
+impl Evaluator {
+    /// Binds `name` to `fun`, a function implemented by the host that programs
+    /// call like any other, with `arity` arguments.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<Identifier>,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> Result<Value, Error> + 'static,
+    ) {
+        let name = name.into();
+        let native = Native {
+            name: name.clone(),
+            arity,
+            fun: Rc::new(fun),
+        };
+        self.bindings.insert(name, Value::Native(native));
+    }
+
+    pub fn eval_stmts(&mut self, stmts: &[Stmt]) -> Result<Value, Error> {
+        self.error_backtrace = None;
+        let mut result = Ok(Value::Number(0.0));
+        for stmt in stmts {
+            result = self.eval_stmt(stmt);
+            if result.is_err() {
+                break;
+            }
+        }
+        // Let every visitor finish, even after an error.
+        for visitor in self.visitors.iter_mut() {
+            if let Err(err) = visitor.finish() {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result.map_err(|err| self.traced(err))
+    }
+
+    /// Attaches the call stack captured when `error` was raised.
+    fn traced(&mut self, error: Error) -> Error {
+        Box::new(TracedError {
+            error,
+            backtrace: self.error_backtrace.take().unwrap_or_default(),
+        })
+    }
+
+    pub fn eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_stmt(stmt).transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_stmt(stmt),
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_stmt(stmt, result);
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    fn inner_eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        match stmt {
+            Stmt::Let(ident, expr) => {
+                let value = self.eval_expr(expr)?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &value)?;
+                }
+                self.bindings.insert(ident.clone(), value.clone());
+                Ok(value)
+            }
+            Stmt::Fun(ident, function) => {
+                let closure =
+                    Value::Closure(ident.clone(), Rc::clone(function), self.bindings.clone());
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &closure)?;
+                }
+                self.bindings.insert(ident.to_owned(), closure.clone());
+                Ok(closure)
+            }
+        }
+    }
+
+    pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_expr(expr).transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_expr(expr),
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_expr(expr, result);
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    fn inner_eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        match expr {
+            Expr::Factor(f) => self.eval_factor(f),
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_expr(lhs)?;
+                let rhs = self.eval_expr(rhs)?;
+                match op {
+                    TermBinaryOp::Add => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+                        _ => Err("Addition only supported for numbers".into()),
+                    },
+                    TermBinaryOp::Sub => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+                        _ => Err("Subtraction only supported for numbers".into()),
+                    },
+                }
+            }
+        }
+    }
+
+    fn eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_factor(factor).transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_factor(factor),
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_factor(factor, result);
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    fn inner_eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        match factor {
+            Factor::Literal(x) => Ok(Value::Number(*x)),
+            Factor::Variable(ident) => {
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_lookup(ident)?;
+                }
+                self.bindings
+                    .get(ident)
+                    .cloned()
+                    .ok_or_else(|| format!("Variable not found in bindings: {}", ident).into())
+            }
+            Factor::Group(expr) => self.eval_expr(expr),
+            Factor::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_factor(lhs)?;
+                let rhs = self.eval_factor(rhs)?;
+                match op {
+                    FactorBinaryOp::Mul => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+                        _ => Err("Multiplication only supported for numbers".into()),
+                    },
+                    FactorBinaryOp::Div => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+                        _ => Err("Division only supported for numbers".into()),
+                    },
+                }
+            }
+            Factor::Call(fun, args) => {
+                let (name, fun, env) = match self.eval_factor(fun)? {
+                    Value::Closure(name, fun, env) => (name, fun, env),
+                    Value::Native(native) => return self.call_native(&native, args),
+                    Value::Number(_) => return Err("Expected function".into()),
+                };
+                let mut body_env = env.clone();
+                let mut frame = Frame {
+                    name,
+                    args: Vec::with_capacity(args.len()),
+                };
+                for arg in args.iter().take(fun.params.len()) {
+                    frame.args.push(self.eval_expr(arg)?);
+                }
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_call(&frame.name, &frame.args, &env)?;
+                }
+                for (param, value) in fun.params.iter().zip(&frame.args) {
+                    for visitor in self.visitors.iter_mut() {
+                        visitor.on_bind(param, value)?;
+                    }
+                    body_env.insert(param.clone(), value.clone());
+                }
+                self.call_stack.push(frame);
+                if self.call_stack.len() > self.max_call_depth {
+                    let err = StackOverflow {
+                        max_depth: self.max_call_depth,
+                    };
+                    self.call_stack.pop();
+                    return Err(err.into());
+                }
+                let current_bindings = std::mem::replace(&mut self.bindings, body_env);
+                let result = self.eval_expr(&fun.body);
+                self.bindings = current_bindings;
+                if result.is_err() && self.error_backtrace.is_none() {
+                    self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+                }
+                self.call_stack.pop();
+                let value = result?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_return(&value)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Calls a function implemented by the host with the values of `args`.
+    fn call_native(&mut self, native: &Native, args: &[Expr]) -> Result<Value, Error> {
+        if args.len() != native.arity {
+            return Err(format!(
+                "Number of function parameters differs from arguments: expected {}, found {}",
+                native.arity,
+                args.len()
+            )
+            .into());
+        }
+        let mut frame = Frame {
+            name: native.name.clone(),
+            args: Vec::with_capacity(args.len()),
+        };
+        for arg in args {
+            frame.args.push(self.eval_expr(arg)?);
+        }
+        for visitor in self.visitors.iter_mut() {
+            visitor.on_call(&frame.name, &frame.args, &Env::new())?;
+        }
+        self.call_stack.push(frame);
+        let frame = self.call_stack.last().expect("pushed above");
+        let result = (native.fun)(&frame.args);
+        if result.is_err() && self.error_backtrace.is_none() {
+            self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+        }
+        self.call_stack.pop();
+        let value = result?;
+        for visitor in self.visitors.iter_mut() {
+            visitor.on_return(&value)?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutable_self::ast::Function;
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        Expr::Factor(Box::new(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        )))
+    }
+
+    fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Rc::new(Function {
+                params: params.iter().map(|p| p.to_string()).collect(),
+                body,
+            }),
+        )
+    }
+
+    #[test]
+    fn spawned_stack_overflow() {
+        // Test threads have a 2 MiB stack, smaller than the main thread's.
+        let (overflowed, depth) = std::thread::spawn(|| {
+            let stmts = vec![
+                fun("omega", &["g"], call("g", vec![var("g")])),
+                Stmt::Let("x".to_owned(), Box::new(call("omega", vec![var("omega")]))),
+            ];
+            let mut evaluator = Evaluator::default();
+            let err = evaluator.eval_stmts(&stmts).unwrap_err();
+            let traced = err.downcast_ref::<TracedError>().unwrap();
+            (traced.error.is::<StackOverflow>(), traced.backtrace.len())
+        })
+        .join()
+        .unwrap();
+
+        assert!(overflowed);
+        assert_eq!(depth, DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    #[test]
+    fn stack_overflow_trace() {
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let("x".to_owned(), Box::new(call("omega", vec![var("omega")]))),
+        ];
+        let mut evaluator = Evaluator {
+            max_call_depth: 2,
+            ..Evaluator::default()
+        };
+        let err = evaluator.eval_stmts(&stmts).unwrap_err();
+
+        // The calls are only listed once, in the trace.
+        assert_eq!(
+            err.to_string(),
+            "Stack overflow: maximum call depth of 2 exceeded\n\
+             Stack trace:\n  \
+             at omega(<fun omega>)\n  \
+             at omega(<fun omega>)"
+        );
+    }
+
+    /// `f` defined `depth` times, each calling the one before, and a call of
+    /// the last, so that calls nest exactly `depth` deep.
+    fn nested_calls(depth: usize) -> Vec<Stmt> {
+        let mut stmts = vec![fun("f", &["x"], var("x"))];
+        for _ in 1..depth {
+            stmts.push(fun("f", &["x"], call("f", vec![var("x")])));
+        }
+        stmts.push(Stmt::Let(
+            "y".to_owned(),
+            Box::new(call("f", vec![num(1.0)])),
+        ));
+        stmts
+    }
+
+    #[test]
+    fn calls_nest_up_to_the_limit() {
+        let mut evaluator = Evaluator::default();
+        let value = evaluator.eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH));
+        assert!(matches!(value, Ok(Value::Number(y)) if y == 1.0));
+
+        let mut evaluator = Evaluator::default();
+        let err = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH + 1))
+            .unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    /// Records the targeted hooks it's called with.
+    #[derive(Debug)]
+    struct Record(Rc<std::cell::RefCell<Vec<String>>>);
+
+    impl Record {
+        fn log(&self, event: String) -> Result<(), Error> {
+            self.0.borrow_mut().push(event);
+            Ok(())
+        }
+    }
+
+    impl Visit for Record {
+        fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+            let args = args.iter().map(Value::to_string).collect::<Vec<_>>();
+            let mut env = env
+                .iter()
+                .filter(|(name, value)| !value.is_prelude(name))
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            env.sort();
+            self.log(format!("call {fun}({}) in {env:?}", args.join(", ")))
+        }
+
+        fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+            self.log(format!("return {value}"))
+        }
+
+        fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+            self.log(format!("bind {name} {value}"))
+        }
+
+        fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+            self.log(format!("lookup {name}"))
+        }
+    }
+
+    #[test]
+    fn hooks_run_in_evaluation_order() {
+        let stmts = vec![
+            Stmt::Let("k".to_owned(), Box::new(num(1.0))),
+            fun(
+                "add",
+                &["a", "b"],
+                Expr::BinaryOp {
+                    op: TermBinaryOp::Add,
+                    lhs: Box::new(var("a")),
+                    rhs: Box::new(var("b")),
+                },
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("add", vec![var("k"), num(2.0)])),
+            ),
+            Stmt::Let("y".to_owned(), Box::new(call("abs", vec![var("x")]))),
+        ];
+        let events = Rc::default();
+        let mut evaluator = Evaluator {
+            ..Evaluator::default()
+        };
+        evaluator
+            .visitors
+            .push(Box::new(Record(Rc::clone(&events))));
+        evaluator.eval_stmts(&stmts).unwrap();
+
+        assert_eq!(
+            *events.borrow_mut(),
+            [
+                "bind k 1",
+                "bind add <fun add>",
+                // The function, then the arguments, are evaluated before the call.
+                "lookup add",
+                "lookup k",
+                "call add(1, 2) in [\"k\"]",
+                "bind a 1",
+                "bind b 2",
+                "lookup a",
+                "lookup b",
+                "return 3",
+                "bind x 3",
+                "lookup abs",
+                "lookup x",
+                // Natives are called with no environment.
+                "call abs(3) in []",
+                "return 3",
+                "bind y 3",
+            ]
+        );
+    }
+}
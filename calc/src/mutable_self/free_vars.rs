@@ -0,0 +1,155 @@
+//! Collects variables that are used where no binding is in scope.
+use std::collections::{BTreeSet, HashMap};
+
+use crate::mutable_self::{
+    analysis::Analysis,
+    ast::{Factor, Identifier, Stmt},
+    prelude,
+    runtime::Error,
+};
+
+#[derive(Debug)]
+pub struct FreeVars {
+    /// Number of bindings in scope for each name.
+    bound: HashMap<Identifier, usize>,
+    pub free: BTreeSet<Identifier>,
+}
+
+impl Default for FreeVars {
+    fn default() -> Self {
+        // The built-ins are always in scope.
+        let bound = prelude::bindings()
+            .into_keys()
+            .map(|name| (name, 1))
+            .collect();
+        Self {
+            bound,
+            free: BTreeSet::new(),
+        }
+    }
+}
+
+impl FreeVars {
+    fn bind(&mut self, name: &Identifier) {
+        *self.bound.entry(name.clone()).or_default() += 1;
+    }
+
+    fn unbind(&mut self, name: &Identifier) {
+        if let Some(count) = self.bound.get_mut(name) {
+            *count -= 1;
+        }
+    }
+}
+
+impl Analysis for FreeVars {
+    fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        if let Stmt::Fun(_, fun) = stmt {
+            for param in &fun.params {
+                self.bind(param);
+            }
+        }
+        Ok(())
+    }
+
+    fn post_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
+            Stmt::Let(name, _) => self.bind(name),
+            Stmt::Fun(name, fun) => {
+                for param in &fun.params {
+                    self.unbind(param);
+                }
+                self.bind(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        if let Factor::Variable(name) = factor {
+            if self.bound.get(name).copied().unwrap_or_default() == 0 {
+                self.free.insert(name.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mutable_self::{
+        analysis::Driver,
+        ast::{Expr, Function},
+    };
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Rc::new(Function {
+                params: params.iter().map(|p| p.to_string()).collect(),
+                body,
+            }),
+        )
+    }
+
+    fn free(stmts: &[Stmt]) -> Vec<Identifier> {
+        let mut free_vars = FreeVars::default();
+        Driver {
+            analyses: vec![&mut free_vars],
+        }
+        .run_stmts(stmts)
+        .unwrap();
+        free_vars.free.into_iter().collect()
+    }
+
+    #[test]
+    fn parameters_are_bound_only_in_the_body() {
+        let stmts = [
+            fun("f", &["a"], var("a")),
+            Stmt::Let("x".to_owned(), Box::new(var("a"))),
+        ];
+        assert_eq!(free(&stmts), ["a"]);
+    }
+
+    #[test]
+    fn parameters_shadow_outer_bindings() {
+        // A parameter that shadows `x` leaves `x` bound after the function.
+        let stmts = [
+            Stmt::Let("x".to_owned(), Box::new(var("pi"))),
+            fun("f", &["x"], var("x")),
+            Stmt::Let("y".to_owned(), Box::new(var("x"))),
+        ];
+        assert!(free(&stmts).is_empty());
+
+        // And one that shadows a built-in leaves it bound too.
+        let stmts = [
+            fun("f", &["sqrt"], var("sqrt")),
+            Stmt::Let("y".to_owned(), Box::new(var("sqrt"))),
+        ];
+        assert!(free(&stmts).is_empty());
+    }
+
+    #[test]
+    fn names_are_not_bound_in_their_own_definitions() {
+        let stmts = [
+            Stmt::Let("x".to_owned(), Box::new(var("x"))),
+            fun("f", &["a"], var("f")),
+        ];
+        assert_eq!(free(&stmts), ["f", "x"]);
+    }
+
+    #[test]
+    fn unused_bindings_are_not_free() {
+        let stmts = [
+            Stmt::Let("unused".to_owned(), Box::new(var("e"))),
+            fun("g", &["unused_param"], var("e")),
+        ];
+        assert!(free(&stmts).is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Warns about suspicious code that still evaluates.
+use crate::mutable_self::{
+    analysis::Analysis,
+    ast::{Factor, FactorBinaryOp, Stmt},
+    runtime::Error,
+};
+
+#[derive(Debug, Default)]
+pub struct Lint {
+    pub warnings: Vec<String>,
+}
+
+impl Analysis for Lint {
+    fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        if let Stmt::Fun(name, fun) = stmt {
+            for (i, param) in fun.params.iter().enumerate() {
+                if fun.params[..i].contains(param) {
+                    self.warnings
+                        .push(format!("Duplicate parameter {param} in function {name}"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        if let Factor::BinaryOp {
+            op: FactorBinaryOp::Div,
+            lhs: _,
+            rhs,
+        } = factor
+        {
+            if matches!(rhs.as_ref(), Factor::Literal(x) if *x == 0.0) {
+                self.warnings.push("Division by zero".to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mutable_self::{
+        analysis::Driver,
+        ast::{Expr, Function},
+    };
+
+    fn lint(stmts: &[Stmt]) -> Vec<String> {
+        let mut lint = Lint::default();
+        Driver {
+            analyses: vec![&mut lint],
+        }
+        .run_stmts(stmts)
+        .unwrap();
+        lint.warnings
+    }
+
+    fn fun(name: &str, params: &[&str], body: Factor) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Rc::new(Function {
+                params: params.iter().map(|p| p.to_string()).collect(),
+                body: Expr::Factor(Box::new(body)),
+            }),
+        )
+    }
+
+    fn divide(lhs: Factor, rhs: Factor) -> Factor {
+        Factor::BinaryOp {
+            op: FactorBinaryOp::Div,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    #[test]
+    fn duplicate_parameters() {
+        let stmts = [fun("f", &["a", "b", "a", "a"], Factor::Literal(1.0))];
+        assert_eq!(
+            lint(&stmts),
+            [
+                "Duplicate parameter a in function f",
+                "Duplicate parameter a in function f"
+            ]
+        );
+    }
+
+    #[test]
+    fn division_by_a_literal_zero() {
+        let a = || Factor::Variable("a".to_owned());
+        let stmts = [
+            fun("f", &["a"], divide(a(), Factor::Literal(0.0))),
+            // Only literal zeros are caught, and only as divisors.
+            fun("g", &["a"], divide(Factor::Literal(0.0), a())),
+            fun("h", &["a"], divide(a(), a())),
+        ];
+        assert_eq!(lint(&stmts), ["Division by zero"]);
+    }
+
+    #[test]
+    fn shadowing_and_unused_bindings_are_fine() {
+        let a = || Factor::Variable("a".to_owned());
+        let stmts = [
+            Stmt::Let(
+                "a".to_owned(),
+                Box::new(Expr::Factor(Box::new(Factor::Literal(1.0)))),
+            ),
+            fun("f", &["a", "unused"], a()),
+            Stmt::Let("a".to_owned(), Box::new(Expr::Factor(Box::new(a())))),
+        ];
+        assert!(lint(&stmts).is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+//! Examples of dynamic analyses.
+use std::io::Write;
+
+use serde_json::json;
+
+use crate::mutable_self::{
+    ast::{Expr, Factor, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, Error, Value},
+    unparser::unparse,
+};
+
+/// Prints each node entered and left, indented by nesting, to stdout unless
+/// given another writer.
+pub struct Print {
+    pub indent: usize,
+    out: Box<dyn Write>,
+}
+
+const INDENT_WIDTH: usize = 2;
+
+impl Print {
+    /// Prints to `out` instead of stdout.
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            indent: 0,
+            out: Box::new(out),
+        }
+    }
+}
+
+impl Default for Print {
+    fn default() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+impl std::fmt::Debug for Print {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Print")
+            .field("indent", &self.indent)
+            .finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for Print {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        let indent = self.indent * INDENT_WIDTH;
+        // Entered even if writing fails, since the post-visit still runs.
+        self.indent += 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval stmt={stmt:?}",
+            "",
+            indent = indent,
+            stmt = stmt
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent -= 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval stmt={stmt:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            stmt = stmt,
+            result = &result
+        )?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let indent = self.indent * INDENT_WIDTH;
+        self.indent += 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval expr={expr:?}",
+            "",
+            indent = indent,
+            expr = expr
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent -= 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval expr={expr:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            expr = expr,
+            result = &result
+        )?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let indent = self.indent * INDENT_WIDTH;
+        self.indent += 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval factor={factor:?}",
+            "",
+            indent = indent,
+            factor = factor
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent -= 1;
+        writeln!(
+            self.out,
+            "{:indent$}Eval factor={factor:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            factor = factor,
+            result = &result
+        )?;
+        result
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Logs one JSON object per line for each node entered and left, for tools
+/// to consume instead of [`Print`]'s output.
+///
+/// Each event has the `phase` (`pre` or `post`), the node `kind`, its `id`
+/// and `line` (see [`NodeIds`]), the `depth` of nesting, and the unparsed
+/// `source` of the node.  `post` events also have the resulting `value` or
+/// `error`.
+pub struct JsonLines {
+    out: Box<dyn Write>,
+    ids: NodeIds,
+    depth: usize,
+}
+
+impl JsonLines {
+    /// Logs the evaluation of `stmts` to `out`.
+    pub fn new(out: impl Write + 'static, stmts: &[Stmt]) -> Self {
+        Self {
+            out: Box::new(out),
+            ids: NodeIds::new(stmts),
+            depth: 0,
+        }
+    }
+
+    fn emit(
+        &mut self,
+        phase: &str,
+        depth: usize,
+        kind: &str,
+        location: Option<Location>,
+        source: String,
+        result: Option<&Result<Value, Error>>,
+    ) -> Result<(), Error> {
+        let mut event = json!({
+            "phase": phase,
+            "kind": kind,
+            "id": location.map(|location| location.id),
+            "line": location.map(|location| location.line),
+            "depth": depth,
+            "source": source,
+        });
+        match result {
+            Some(Ok(Value::Number(n))) => event["value"] = json!(n),
+            Some(Ok(value)) => event["value"] = json!(value.to_string()),
+            Some(Err(error)) => event["error"] = json!(error.to_string()),
+            None => {}
+        }
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for JsonLines {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonLines")
+            .field("depth", &self.depth)
+            .finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for JsonLines {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_stmt(stmt));
+        let depth = self.depth;
+        // Entered even if writing fails, since the post-visit still runs.
+        self.depth += 1;
+        let location = self.ids.stmt(stmt);
+        self.emit("pre", depth, stmt.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.depth -= 1;
+        let source = unparse(|unparser| unparser.unparse_stmt(stmt));
+        let location = self.ids.stmt(stmt);
+        let kind = stmt.kind();
+        self.emit("post", self.depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_expr(expr));
+        let depth = self.depth;
+        self.depth += 1;
+        let location = self.ids.expr(expr);
+        self.emit("pre", depth, expr.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.depth -= 1;
+        let source = unparse(|unparser| unparser.unparse_expr(expr));
+        let location = self.ids.expr(expr);
+        let kind = expr.kind();
+        self.emit("post", self.depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_factor(factor));
+        let depth = self.depth;
+        self.depth += 1;
+        let location = self.ids.factor(factor);
+        self.emit("pre", depth, factor.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.depth -= 1;
+        let source = unparse(|unparser| unparser.unparse_factor(factor));
+        let location = self.ids.factor(factor);
+        let kind = factor.kind();
+        self.emit("post", self.depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+
+    use serde_json::Value as Json;
+
+    use super::*;
+    use crate::mutable_self::{ast::Function, evaluator::Evaluator};
+
+    /// A writer whose output the test can still read after evaluation.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            let out = String::from_utf8(self.0.borrow().clone()).unwrap();
+            out.lines().map(str::to_owned).collect()
+        }
+    }
+
+    fn keys(event: &Json) -> BTreeSet<&str> {
+        let object = event.as_object().unwrap();
+        object.keys().map(String::as_str).collect()
+    }
+
+    fn factor(factor: Factor) -> Expr {
+        Expr::Factor(Box::new(factor))
+    }
+
+    /// `fun f(a) = a`, `let x = f(2)` and `let y = z`, which fails.
+    fn program() -> Vec<Stmt> {
+        let var = |name: &str| factor(Factor::Variable(name.to_owned()));
+        vec![
+            Stmt::Fun(
+                "f".to_owned(),
+                Rc::new(Function {
+                    params: vec!["a".to_owned()],
+                    body: var("a"),
+                }),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(factor(Factor::Call(
+                    Box::new(Factor::Variable("f".to_owned())),
+                    vec![factor(Factor::Literal(2.0))],
+                ))),
+            ),
+            Stmt::Let("y".to_owned(), Box::new(var("z"))),
+        ]
+    }
+
+    #[test]
+    fn json_lines_schema() {
+        let stmts = program();
+        let out = Buffer::default();
+        let mut evaluator = Evaluator::default();
+        evaluator
+            .visitors
+            .push(Box::new(JsonLines::new(out.clone(), &stmts)));
+        evaluator.eval_stmts(&stmts).unwrap_err();
+        let events = out
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str::<Json>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let common = ["depth", "id", "kind", "line", "phase", "source"];
+        let mut open = Vec::new();
+        for event in &events {
+            let mut expected = BTreeSet::from(common);
+            match event["phase"].as_str().unwrap() {
+                "pre" => open.push(event),
+                "post" => {
+                    let pre = open.pop().unwrap();
+                    for key in ["kind", "id", "line", "depth", "source"] {
+                        assert_eq!(event[key], pre[key], "{key} of {event}");
+                    }
+                    let outcome = if event.get("error").is_some() {
+                        "error"
+                    } else {
+                        "value"
+                    };
+                    expected.insert(outcome);
+                }
+                phase => panic!("unexpected phase {phase}"),
+            }
+            assert_eq!(keys(event), expected, "{event}");
+            assert_eq!(
+                event["depth"],
+                open.len() - (event["phase"] == "pre") as usize
+            );
+        }
+        assert!(open.is_empty());
+
+        assert_eq!(
+            events[0],
+            serde_json::json!({
+                "phase": "pre",
+                "kind": "Stmt::Fun",
+                "id": 0,
+                "line": 1,
+                "depth": 0,
+                "source": "fun f(a) = a",
+            })
+        );
+        let body = events
+            .iter()
+            .find(|event| event["phase"] == "post" && event["source"] == "a")
+            .unwrap();
+        // The body of `f`, evaluated inside the call on line 2.
+        assert_eq!(body["line"], 1);
+        assert_eq!(body["value"], 2.0);
+        assert!(body["depth"].as_u64().unwrap() > 2);
+        let last = events.last().unwrap();
+        assert_eq!(last["kind"], "Stmt::Let");
+        assert_eq!(last["line"], 3);
+        assert_eq!(last["error"], "Variable not found in bindings: z");
+    }
+
+    #[test]
+    fn print_indents_by_nesting() {
+        let stmts = program();
+        let out = Buffer::default();
+        let mut evaluator = Evaluator::default();
+        evaluator.visitors.push(Box::new(Print::new(out.clone())));
+        evaluator.eval_stmts(&stmts).unwrap_err();
+        let lines = out.lines();
+
+        let mut open = Vec::new();
+        for line in &lines {
+            let text = line.trim_start();
+            let indent = line.len() - text.len();
+            if text.contains(" result=") {
+                let (pre_indent, pre) = open.pop().unwrap();
+                assert_eq!(indent, pre_indent, "{line}");
+                assert!(text.starts_with(pre), "{line}");
+            } else {
+                assert_eq!(indent, open.len() * INDENT_WIDTH, "{line}");
+                open.push((indent, text));
+            }
+        }
+        assert!(open.is_empty());
+        assert!(lines[0].starts_with("Eval stmt=Fun("));
+        assert!(lines
+            .last()
+            .unwrap()
+            .ends_with("result=Err(\"Variable not found in bindings: z\")"));
+    }
+}
@@ -0,0 +1,100 @@
+//! Stable ids for AST nodes.
+//!
+//! Nodes are numbered in pre-order, so ids don't change between runs of the
+//! same program.  Dynamic analyses see nodes by reference, so they are looked
+//! up by address; closures share their function with the AST, so nodes in
+//! function bodies resolve too.  A node shared by several statements, such as
+//! the body of a function they define, keeps the id from where it first
+//! appears.
+use std::collections::HashMap;
+
+use crate::mutable_self::{
+    analysis::{Analysis, Driver},
+    ast::{Expr, Factor, Stmt},
+    runtime::Error,
+};
+
+/// Where a node is in the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Pre-order index of the node.
+    pub id: usize,
+    /// 1-based line of the node's statement in the unparsed program.
+    pub line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct NodeIds {
+    stmts: HashMap<usize, Location>,
+    exprs: HashMap<usize, Location>,
+    factors: HashMap<usize, Location>,
+    line: usize,
+}
+
+impl NodeIds {
+    pub fn new(stmts: &[Stmt]) -> Self {
+        let mut ids = Self::default();
+        let mut driver = Driver::default();
+        driver.analyses.push(&mut ids);
+        driver
+            .run_stmts(stmts)
+            .expect("numbering nodes never fails");
+        ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.stmts.len() + self.exprs.len() + self.factors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stmt(&self, stmt: &Stmt) -> Option<Location> {
+        self.stmts.get(&(stmt as *const Stmt as usize)).copied()
+    }
+
+    pub fn expr(&self, expr: &Expr) -> Option<Location> {
+        self.exprs.get(&(expr as *const Expr as usize)).copied()
+    }
+
+    pub fn factor(&self, factor: &Factor) -> Option<Location> {
+        self.factors
+            .get(&(factor as *const Factor as usize))
+            .copied()
+    }
+
+    fn next(&self) -> Location {
+        Location {
+            id: self.len(),
+            line: self.line,
+        }
+    }
+}
+
+impl Analysis for NodeIds {
+    fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        self.line += 1;
+        let location = self.next();
+        self.stmts
+            .entry(stmt as *const Stmt as usize)
+            .or_insert(location);
+        Ok(())
+    }
+
+    fn pre_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+        let location = self.next();
+        self.exprs
+            .entry(expr as *const Expr as usize)
+            .or_insert(location);
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        let location = self.next();
+        self.factors
+            .entry(factor as *const Factor as usize)
+            .or_insert(location);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! A dynamic analysis that exports evaluations for visualization.
+//!
+//! Writes [Chrome Trace Event](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU)
+//! JSON, viewable in Perfetto or `about:tracing`, and folded stacks for
+//! flamegraph tools.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+use crate::mutable_self::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    runtime::{self, Env, Error, Value},
+};
+
+#[derive(Debug)]
+struct Event {
+    name: String,
+    cat: &'static str,
+    /// Chrome trace phase, e.g. `B` for begin and `E` for end.
+    ph: char,
+    ts: Duration,
+    /// Identifies the async span that `b` and `e` events belong to.
+    id: Option<u64>,
+}
+
+/// A node or function call that hasn't ended yet.
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    cat: &'static str,
+    /// Address of the AST node, or `None` for a function call.
+    node: Option<usize>,
+    start: Duration,
+    children: Duration,
+}
+
+#[derive(Debug)]
+pub struct Trace {
+    chrome_path: PathBuf,
+    folded_path: PathBuf,
+    epoch: Instant,
+    events: Vec<Event>,
+    stack: Vec<Frame>,
+    /// Exclusive nanoseconds per semicolon-separated stack.
+    folded: BTreeMap<String, u128>,
+}
+
+impl Trace {
+    /// Records a trace that's written to the given files when evaluation
+    /// finishes.
+    pub fn new(chrome_path: impl Into<PathBuf>, folded_path: impl Into<PathBuf>) -> Self {
+        Self {
+            chrome_path: chrome_path.into(),
+            folded_path: folded_path.into(),
+            epoch: Instant::now(),
+            events: Vec::new(),
+            stack: Vec::new(),
+            folded: BTreeMap::new(),
+        }
+    }
+
+    fn begin(&mut self, name: String, cat: &'static str, node: Option<usize>) {
+        let ts = self.epoch.elapsed();
+        self.events.push(Event {
+            name: name.clone(),
+            cat,
+            ph: 'B',
+            ts,
+            id: None,
+        });
+        self.stack.push(Frame {
+            name,
+            cat,
+            node,
+            start: ts,
+            children: Duration::ZERO,
+        });
+    }
+
+    fn end(&mut self) -> Option<Frame> {
+        let frame = self.stack.pop()?;
+        let ts = self.epoch.elapsed();
+        let elapsed = ts.saturating_sub(frame.start);
+        let mut key = String::new();
+        for outer in &self.stack {
+            key.push_str(&outer.name);
+            key.push(';');
+        }
+        key.push_str(&frame.name);
+        *self.folded.entry(key).or_default() += elapsed.saturating_sub(frame.children).as_nanos();
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+        self.events.push(Event {
+            name: frame.name.clone(),
+            cat: frame.cat,
+            ph: 'E',
+            ts,
+            id: None,
+        });
+        Some(frame)
+    }
+
+    /// Ends the frame of `node` and any frames above it, like calls that
+    /// failed and never returned.
+    fn end_node(&mut self, node: usize) {
+        if !self.stack.iter().any(|frame| frame.node == Some(node)) {
+            return;
+        }
+        while let Some(frame) = self.end() {
+            if frame.node == Some(node) {
+                break;
+            }
+        }
+    }
+
+    pub fn write_chrome_trace(&self, mut w: impl Write) -> io::Result<()> {
+        let events = self
+            .events
+            .iter()
+            .map(|event| {
+                let mut json = json!({
+                    "name": event.name,
+                    "cat": event.cat,
+                    "ph": event.ph.to_string(),
+                    "ts": event.ts.as_secs_f64() * 1e6,
+                    "pid": 1,
+                    "tid": 1,
+                });
+                if let Some(id) = event.id {
+                    json["id"] = id.into();
+                }
+                json
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer(&mut w, &json!({ "traceEvents": events }))?;
+        w.flush()
+    }
+
+    pub fn write_folded(&self, mut w: impl Write) -> io::Result<()> {
+        for (stack, nanos) in &self.folded {
+            writeln!(w, "{stack} {nanos}")?;
+        }
+        w.flush()
+    }
+}
+
+fn address<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+impl runtime::Visit for Trace {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.begin(stmt.kind().to_owned(), "stmt", Some(address(stmt)));
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(stmt));
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.begin(expr.kind().to_owned(), "expr", Some(address(expr)));
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(expr));
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        self.begin(factor.kind().to_owned(), "factor", Some(address(factor)));
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(factor));
+        result
+    }
+
+    fn on_call(&mut self, fun: &Identifier, _args: &[Value], _env: &Env) -> Result<(), Error> {
+        self.begin(fun.clone(), "call", None);
+        Ok(())
+    }
+
+    fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        if matches!(self.stack.last(), Some(frame) if frame.node.is_none()) {
+            self.end();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        while self.end().is_some() {}
+        self.write_chrome_trace(BufWriter::new(File::create(&self.chrome_path)?))?;
+        self.write_folded(BufWriter::new(File::create(&self.folded_path)?))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! An example of a static analysis.
+use std::fmt::Write;
+
+use crate::mutable_self::{
+    analysis::Analysis,
+    ast::{Expr, Factor, FactorBinaryOp, Stmt, TermBinaryOp},
+    runtime::Error,
+};
+
+#[derive(Debug, Default)]
+pub struct Unparser {
+    indent: usize,
+    out: String,
+}
+
+const INDENT_WIDTH: usize = 2;
+
+/// Unparses a single node, without the trailing newline of a statement.
+pub fn unparse(f: impl FnOnce(&mut Unparser) -> std::fmt::Result) -> String {
+    let mut unparser = Unparser::default();
+    f(&mut unparser).expect("unparsing to a string never fails");
+    unparser.output().trim_end().to_owned()
+}
+
+impl Unparser {
+    pub fn output(&self) -> &str {
+        &self.out
+    }
+
+    pub fn unparse_stmts(&mut self, stmts: &[Stmt]) -> Result<(), std::fmt::Error> {
+        for stmt in stmts {
+            self.unparse_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    pub fn unparse_stmt(&mut self, stmt: &Stmt) -> Result<(), std::fmt::Error> {
+        self.unparse_stmt_head(stmt)?;
+        match stmt {
+            Stmt::Let(_, expr) => self.unparse_expr(expr)?,
+            Stmt::Fun(_, fun) => self.unparse_expr(&fun.body)?,
+        }
+        writeln!(self.out)
+    }
+
+    /// Everything before the expression of a statement.
+    fn unparse_stmt_head(&mut self, stmt: &Stmt) -> Result<(), std::fmt::Error> {
+        match stmt {
+            Stmt::Let(name, _) => {
+                write!(
+                    self.out,
+                    "{:indent$}let {name} = ",
+                    "",
+                    indent = self.indent * INDENT_WIDTH,
+                    name = name
+                )?;
+            }
+            Stmt::Fun(name, fun) => {
+                write!(
+                    self.out,
+                    "{:indent$}fun {name}(",
+                    "",
+                    indent = self.indent * INDENT_WIDTH,
+                    name = name
+                )?;
+                for (i, param) in fun.params.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    write!(self.out, "{}", param)?;
+                }
+                write!(self.out, ") = ")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unparse_expr(&mut self, expr: &Expr) -> Result<(), std::fmt::Error> {
+        match expr {
+            Expr::Factor(factor) => self.unparse_factor(factor)?,
+            Expr::BinaryOp { op, lhs, rhs } => {
+                self.unparse_expr(lhs)?;
+                write!(self.out, " ")?;
+                self.unparse_term_binary_op(op)?;
+                write!(self.out, " ")?;
+                self.unparse_expr(rhs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unparse_term_binary_op(&mut self, op: &TermBinaryOp) -> Result<(), std::fmt::Error> {
+        match op {
+            TermBinaryOp::Add => write!(self.out, "+"),
+            TermBinaryOp::Sub => write!(self.out, "-"),
+        }
+    }
+
+    pub fn unparse_factor(&mut self, factor: &Factor) -> Result<(), std::fmt::Error> {
+        match factor {
+            Factor::Literal(value) => write!(self.out, "{}", value)?,
+            Factor::Variable(name) => write!(self.out, "{}", name)?,
+            Factor::Group(expr) => {
+                write!(self.out, "(")?;
+                self.unparse_expr(expr)?;
+                write!(self.out, ")")?;
+            }
+            Factor::BinaryOp { op, lhs, rhs } => {
+                self.unparse_factor(lhs)?;
+                write!(self.out, " ")?;
+                self.unparse_factor_binary_op(op)?;
+                write!(self.out, " ")?;
+                self.unparse_factor(rhs)?;
+            }
+            Factor::Call(fun, args) => {
+                self.unparse_factor(fun)?;
+                write!(self.out, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.unparse_expr(arg)?;
+                }
+                write!(self.out, ")")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unparse_factor_binary_op(&mut self, op: &FactorBinaryOp) -> Result<(), std::fmt::Error> {
+        match op {
+            FactorBinaryOp::Mul => write!(self.out, "*"),
+            FactorBinaryOp::Div => write!(self.out, "/"),
+        }
+    }
+}
+
+/// The same output as [`Unparser::unparse_stmts`], driven by an
+/// [`analysis::Driver`](crate::mutable_self::analysis::Driver).
+impl Analysis for Unparser {
+    fn pre_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        Ok(self.unparse_stmt_head(stmt)?)
+    }
+
+    fn post_stmt(&mut self, _stmt: &Stmt) -> Result<(), Error> {
+        Ok(writeln!(self.out)?)
+    }
+
+    fn between_expr(&mut self, expr: &Expr, _child: usize) -> Result<(), Error> {
+        if let Expr::BinaryOp { op, .. } = expr {
+            write!(self.out, " ")?;
+            self.unparse_term_binary_op(op)?;
+            write!(self.out, " ")?;
+        }
+        Ok(())
+    }
+
+    fn pre_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        match factor {
+            Factor::Literal(value) => write!(self.out, "{}", value)?,
+            Factor::Variable(name) => write!(self.out, "{}", name)?,
+            Factor::Group(_) => write!(self.out, "(")?,
+            Factor::BinaryOp { .. } | Factor::Call(..) => {}
+        }
+        Ok(())
+    }
+
+    fn between_factor(&mut self, factor: &Factor, child: usize) -> Result<(), Error> {
+        match factor {
+            Factor::BinaryOp { op, .. } => {
+                write!(self.out, " ")?;
+                self.unparse_factor_binary_op(op)?;
+                write!(self.out, " ")?;
+            }
+            Factor::Call(..) if child == 1 => write!(self.out, "(")?,
+            Factor::Call(..) => write!(self.out, ", ")?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn post_factor(&mut self, factor: &Factor) -> Result<(), Error> {
+        match factor {
+            Factor::Group(_) => write!(self.out, ")")?,
+            // Without arguments, the opening paren was never written.
+            Factor::Call(_, args) if args.is_empty() => write!(self.out, "()")?,
+            Factor::Call(..) => write!(self.out, ")")?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+//! Async evaluation, with timeouts, yields and record-and-replay.
+//!
+//! `coverage`, `debugger`, `log`, `node_id` and `profile` are built from the
+//! same source files in both async designs, each against its own AST.
+pub mod ast;
+#[path = "shared/coverage.rs"]
+pub mod coverage;
+#[path = "shared/debugger.rs"]
+pub mod debugger;
+pub mod evaluator;
+#[path = "shared/log.rs"]
+pub mod log;
+#[path = "shared/node_id.rs"]
+pub mod node_id;
+pub mod prelude;
+#[path = "shared/profile.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod profile;
+pub mod replay;
+pub mod runtime;
+pub mod trace;
+pub mod unparser;
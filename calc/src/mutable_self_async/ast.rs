@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+pub type Identifier = String;
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Let(Identifier, Box<Expr>),
+    Fun(Identifier, Arc<Function>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub params: Params,
+    pub body: Expr,
+}
+
+pub type Params = Vec<Identifier>;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Factor(Box<Factor>),
+    BinaryOp {
+        op: TermBinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TermBinaryOp {
+    Add,
+    #[allow(dead_code)]
+    Sub,
+}
+
+#[derive(Debug, Clone)]
+pub enum Factor {
+    Timeout {
+        milliseconds: u64,
+        expr: Box<Expr>,
+    },
+    Yield(Box<Expr>),
+    Literal(f64),
+    Variable(Identifier),
+    #[allow(dead_code)]
+    Group(Box<Expr>),
+    #[allow(dead_code)]
+    BinaryOp {
+        op: FactorBinaryOp,
+        lhs: Box<Factor>,
+        rhs: Box<Factor>,
+    },
+    Call(Box<Factor>, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FactorBinaryOp {
+    #[allow(dead_code)]
+    Mul,
+    #[allow(dead_code)]
+    Div,
+}
+
+crate::common::node_kinds! {
+    Stmt { Let, Fun }
+    Expr { Factor, BinaryOp }
+    Factor { Literal, Variable, Group, BinaryOp, Call, Timeout, Yield }
+}
+
+impl Stmt {
+    /// The expression in the statement: the value of a `let`, or the body of
+    /// a function.
+    pub fn expr(&self) -> &Expr {
+        match self {
+            Stmt::Let(_, expr) => expr,
+            Stmt::Fun(_, fun) => &fun.body,
+        }
+    }
+}
+
+/// A child of an AST node.
+#[derive(Debug, Clone, Copy)]
+pub enum Child<'a> {
+    Expr(&'a Expr),
+    Factor(&'a Factor),
+}
+
+impl Factor {
+    /// The factor's children, in the order they're evaluated.
+    pub fn children(&self) -> Vec<Child<'_>> {
+        match self {
+            Factor::Literal(_) | Factor::Variable(_) => Vec::new(),
+            Factor::Group(expr) | Factor::Timeout { expr, .. } | Factor::Yield(expr) => {
+                vec![Child::Expr(expr)]
+            }
+            Factor::BinaryOp { op: _, lhs, rhs } => vec![Child::Factor(lhs), Child::Factor(rhs)],
+            Factor::Call(fun, args) => {
+                let args = args.iter().map(Child::Expr);
+                std::iter::once(Child::Factor(fun)).chain(args).collect()
+            }
+        }
+    }
+}
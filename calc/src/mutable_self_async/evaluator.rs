@@ -0,0 +1,1072 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_recursion::async_recursion;
+use tokio::sync::watch;
+
+use crate::mutable_self_async::{
+    ast::{Expr, Factor, FactorBinaryOp, Identifier, Stmt, TermBinaryOp},
+    prelude,
+    replay::{Recording, Replayer},
+    runtime::{AsyncVisit, Env, Error, Frame, Native, StackOverflow, TimedOut, TracedError, Value},
+};
+
+pub use crate::common::DEFAULT_MAX_CALL_DEPTH;
+
+/// Default time that each `+` and `-` sleeps for.
+pub const DEFAULT_OP_DELAY: Duration = Duration::from_millis(1);
+
+#[derive(Debug)]
+pub struct Evaluator {
+    /// Starts out with the built-ins from [`prelude`].
+    pub bindings: Env,
+    pub visitors: Vec<Box<dyn AsyncVisit>>,
+    /// Calls nested deeper than this fail with a [`StackOverflow`] error.
+    pub max_call_depth: usize,
+    /// Time that each `+` and `-` sleeps for, so that timeouts reliably
+    /// trigger.  Zero doesn't sleep at all.
+    pub op_delay: Duration,
+    /// Calls currently in progress, outermost first.
+    call_stack: Vec<Frame>,
+    /// Snapshot of `call_stack`, innermost first, taken where the current
+    /// error was raised.
+    error_backtrace: Option<Vec<Frame>>,
+    /// Number of timeouts being evaluated.
+    timeouts: usize,
+    /// When replaying, the nesting level of the timeout to expire next.
+    replay: Option<watch::Receiver<Option<usize>>>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            bindings: prelude::bindings(),
+            visitors: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            op_delay: DEFAULT_OP_DELAY,
+            call_stack: Vec::new(),
+            error_backtrace: None,
+            timeouts: 0,
+            replay: None,
+        }
+    }
+}
+
+impl Evaluator {
+    /// Makes evaluating `stmts` repeat a recorded evaluation of them.  Timeouts
+    /// expire at the same points as in the recording regardless of the clock,
+    /// and evaluation fails if it diverges from the recording.
+    ///
+    /// The recording must have been made by a [`Recorder`](crate::mutable_self_async::replay::Recorder)
+    /// that was the first visitor, and any visitors added since will see the
+    /// same events as the recorder did.
+    pub fn replay(&mut self, recording: Recording, stmts: &[Stmt]) {
+        let (replayer, cancel) = Replayer::new(recording, stmts);
+        self.visitors.insert(0, Box::new(replayer));
+        self.replay = Some(cancel);
+    }
+
+    /// Binds `name` to `fun`, a function implemented by the host that programs
+    /// call like any other, with `arity` arguments.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<Identifier>,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    ) {
+        self.register_async_fn(name, arity, move |args| std::future::ready(fun(&args)));
+    }
+
+    /// Binds `name` to `fun`, an async function implemented by the host that
+    /// programs call like any other, with `arity` arguments.
+    ///
+    /// Evaluation awaits the future `fun` returns.  If a surrounding `timeout`
+    /// expires first, the future is dropped, which cancels it at the point it
+    /// was waiting.
+    pub fn register_async_fn<F>(
+        &mut self,
+        name: impl Into<Identifier>,
+        arity: usize,
+        fun: impl Fn(Vec<Value>) -> F + Send + Sync + 'static,
+    ) where
+        F: Future<Output = Result<Value, Error>> + Send + 'static,
+    {
+        let name = name.into();
+        let native = Native {
+            name: name.clone(),
+            arity,
+            fun: Arc::new(move |args| Box::pin(fun(args))),
+        };
+        self.bindings.insert(name, Value::Native(native));
+    }
+
+    pub async fn eval_stmts(&mut self, stmts: &[Stmt]) -> Result<Value, Error> {
+        self.error_backtrace = None;
+        let mut result = Ok(Value::Number(0.0));
+        for stmt in stmts {
+            result = self.eval_stmt(stmt).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        // Let every visitor finish, even after an error.
+        for visitor in self.visitors.iter_mut() {
+            if let Err(err) = visitor.finish().await {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result.map_err(|err| self.traced(err))
+    }
+
+    /// Attaches the call stack captured when `error` was raised.
+    fn traced(&mut self, error: Error) -> Error {
+        Box::new(TracedError {
+            error,
+            backtrace: self.error_backtrace.take().unwrap_or_default(),
+        })
+    }
+
+    pub async fn eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_stmt(stmt).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_stmt(stmt).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_stmt(stmt, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        match stmt {
+            Stmt::Let(ident, expr) => {
+                let value = self.eval_expr(expr).await?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &value).await?;
+                }
+                self.bindings.insert(ident.clone(), value.clone());
+                Ok(value)
+            }
+            Stmt::Fun(ident, function) => {
+                let closure =
+                    Value::Closure(ident.clone(), Arc::clone(function), self.bindings.clone());
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &closure).await?;
+                }
+                self.bindings.insert(ident.to_owned(), closure.clone());
+                Ok(closure)
+            }
+        }
+    }
+
+    #[async_recursion]
+    pub async fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_expr(expr).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_expr(expr).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_expr(expr, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        match expr {
+            Expr::Factor(f) => self.eval_factor(f).await,
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_expr(lhs).await?;
+                let rhs = self.eval_expr(rhs).await?;
+
+                // Sleep to reliably trigger a timeout.
+                if !self.op_delay.is_zero() {
+                    tokio::time::sleep(self.op_delay).await;
+                }
+
+                match op {
+                    TermBinaryOp::Add => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+                        _ => Err("Addition only supported for numbers".into()),
+                    },
+                    TermBinaryOp::Sub => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+                        _ => Err("Subtraction only supported for numbers".into()),
+                    },
+                }
+            }
+        }
+    }
+
+    #[async_recursion]
+    async fn eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_factor(factor).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_factor(factor).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_factor(factor, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        match factor {
+            Factor::Timeout { milliseconds, expr } => {
+                let duration = std::time::Duration::from_millis(*milliseconds);
+                let depth = self.call_stack.len();
+                let bindings = self.bindings.clone();
+                let level = self.timeouts;
+                self.timeouts += 1;
+                let result = match self.replay.clone() {
+                    None => tokio::time::timeout(duration, self.eval_expr(expr))
+                        .await
+                        .ok(),
+                    // Ignore the clock and expire only where the recording did.
+                    Some(mut cancel) => tokio::select! {
+                        result = self.eval_expr(expr) => Some(result),
+                        Ok(_) = cancel.wait_for(|target| *target == Some(level)) => None,
+                    },
+                };
+                self.timeouts = level;
+                // Calls cancelled by the timeout never pop their frames, or
+                // restore the caller's bindings.
+                self.call_stack.truncate(depth);
+                if result.is_none() {
+                    self.bindings = bindings;
+                }
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_timeout(factor, result.is_none()).await?;
+                }
+                result.ok_or(TimedOut {
+                    milliseconds: *milliseconds,
+                })?
+            }
+            Factor::Yield(expr) => {
+                tokio::task::yield_now().await;
+                self.eval_expr(expr).await
+            }
+            Factor::Literal(x) => Ok(Value::Number(*x)),
+            Factor::Variable(ident) => {
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_lookup(ident).await?;
+                }
+                self.bindings
+                    .get(ident)
+                    .cloned()
+                    .ok_or_else(|| format!("Variable not found in bindings: {}", ident).into())
+            }
+            Factor::Group(expr) => self.eval_expr(expr).await,
+            Factor::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_factor(lhs).await?;
+                let rhs = self.eval_factor(rhs).await?;
+                match op {
+                    FactorBinaryOp::Mul => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+                        _ => Err("Multiplication only supported for numbers".into()),
+                    },
+                    FactorBinaryOp::Div => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+                        _ => Err("Division only supported for numbers".into()),
+                    },
+                }
+            }
+            Factor::Call(fun, args) => {
+                let (name, fun, env) = match self.eval_factor(fun).await? {
+                    Value::Closure(name, fun, env) => (name, fun, env),
+                    Value::Native(native) => return self.call_native(&native, args).await,
+                    Value::Number(_) => return Err("Expected function".into()),
+                };
+                let mut body_env = env.clone();
+                let mut frame = Frame {
+                    name,
+                    args: Vec::with_capacity(args.len()),
+                };
+                for arg in args.iter().take(fun.params.len()) {
+                    frame.args.push(self.eval_expr(arg).await?);
+                }
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_call(&frame.name, &frame.args, &env).await?;
+                }
+                for (param, value) in fun.params.iter().zip(&frame.args) {
+                    for visitor in self.visitors.iter_mut() {
+                        visitor.on_bind(param, value).await?;
+                    }
+                    body_env.insert(param.clone(), value.clone());
+                }
+                self.call_stack.push(frame);
+                if self.call_stack.len() > self.max_call_depth {
+                    let err = StackOverflow {
+                        max_depth: self.max_call_depth,
+                    };
+                    self.call_stack.pop();
+                    return Err(err.into());
+                }
+                let current_bindings = std::mem::replace(&mut self.bindings, body_env);
+                let result = self.eval_expr(&fun.body).await;
+                self.bindings = current_bindings;
+                if result.is_err() && self.error_backtrace.is_none() {
+                    self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+                }
+                self.call_stack.pop();
+                let value = result?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_return(&value).await?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Calls a function implemented by the host with the values of `args`.
+    async fn call_native(&mut self, native: &Native, args: &[Expr]) -> Result<Value, Error> {
+        if args.len() != native.arity {
+            return Err(format!(
+                "Number of function parameters differs from arguments: expected {}, found {}",
+                native.arity,
+                args.len()
+            )
+            .into());
+        }
+        let mut frame = Frame {
+            name: native.name.clone(),
+            args: Vec::with_capacity(args.len()),
+        };
+        for arg in args {
+            frame.args.push(self.eval_expr(arg).await?);
+        }
+        for visitor in self.visitors.iter_mut() {
+            visitor
+                .on_call(&frame.name, &frame.args, &Env::new())
+                .await?;
+        }
+        let call = (native.fun)(frame.args.clone());
+        self.call_stack.push(frame);
+        // A timeout that expires while this is pending pops the frame.
+        let result = call.await;
+        if result.is_err() && self.error_backtrace.is_none() {
+            self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+        }
+        self.call_stack.pop();
+        let value = result?;
+        for visitor in self.visitors.iter_mut() {
+            visitor.on_return(&value).await?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::mutable_self_async::{
+        ast::Function,
+        log,
+        runtime::{TracedError, Visit},
+    };
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        Expr::Factor(Box::new(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        )))
+    }
+
+    fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Arc::new(Function {
+                params: params.iter().map(|p| p.to_string()).collect(),
+                body,
+            }),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawn_concurrent_evaluations() {
+        let handles = (0..64)
+            .map(|i| {
+                let stmts = vec![
+                    Stmt::Let("x".to_owned(), Box::new(num(i as f64))),
+                    fun(
+                        "add",
+                        &["a", "b"],
+                        Expr::BinaryOp {
+                            op: TermBinaryOp::Add,
+                            lhs: Box::new(var("a")),
+                            rhs: Box::new(var("b")),
+                        },
+                    ),
+                    Stmt::Let(
+                        "y".to_owned(),
+                        Box::new(call(
+                            "add",
+                            vec![
+                                var("x"),
+                                Expr::Factor(Box::new(Factor::Yield(Box::new(var("x"))))),
+                            ],
+                        )),
+                    ),
+                ];
+                tokio::spawn(async move {
+                    let mut evaluator = Evaluator::default();
+                    evaluator
+                        .visitors
+                        .push(Box::new(log::Print::new(std::io::sink())));
+                    evaluator.eval_stmts(&stmts).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let value = handle.await.unwrap().unwrap();
+            assert!(matches!(value, Value::Number(y) if y == 2.0 * i as f64));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawned_stack_overflow() {
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let("x".to_owned(), Box::new(call("omega", vec![var("omega")]))),
+        ];
+        let result = tokio::spawn(async move {
+            let mut evaluator = Evaluator::default();
+            evaluator.eval_stmts(&stmts).await
+        })
+        .await
+        .unwrap();
+
+        let err = result.unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    #[tokio::test]
+    async fn stack_overflow_trace() {
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let("x".to_owned(), Box::new(call("omega", vec![var("omega")]))),
+        ];
+        let mut evaluator = Evaluator {
+            max_call_depth: 2,
+            ..Evaluator::default()
+        };
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+
+        // The calls are only listed once, in the trace.
+        assert_eq!(
+            err.to_string(),
+            "Stack overflow: maximum call depth of 2 exceeded\n\
+             Stack trace:\n  \
+             at omega(<fun omega>)\n  \
+             at omega(<fun omega>)"
+        );
+    }
+
+    /// Replaces calls to `add` with 42, then adds one to the result of every call.
+    #[derive(Debug)]
+    struct MockAdd;
+
+    impl Visit for MockAdd {
+        fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+            match factor {
+                Factor::Call(fun, _) if matches!(fun.as_ref(), Factor::Variable(name) if name == "add") => {
+                    Ok(Some(Value::Number(42.0)))
+                }
+                _ => Ok(None),
+            }
+        }
+
+        fn post_visit_factor(
+            &mut self,
+            factor: &Factor,
+            result: Result<Value, Error>,
+        ) -> Result<Value, Error> {
+            match (factor, result) {
+                (Factor::Call(..), Ok(Value::Number(x))) => Ok(Value::Number(x + 1.0)),
+                (_, result) => result,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn visitors_replace_and_transform_results() {
+        let stmts = vec![
+            fun(
+                "add",
+                &["a", "b"],
+                Expr::BinaryOp {
+                    op: TermBinaryOp::Add,
+                    lhs: Box::new(var("a")),
+                    rhs: Box::new(var("b")),
+                },
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("add", vec![num(1.0), num(2.0)])),
+            ),
+        ];
+        let mut evaluator = Evaluator::default();
+        evaluator.visitors.push(Box::new(MockAdd));
+        evaluator.visitors.push(Box::new(MockAdd));
+        evaluator
+            .visitors
+            .push(Box::new(log::Print::new(std::io::sink())));
+        let value = evaluator.eval_stmts(&stmts).await.unwrap();
+
+        // The first visitor mocks the call, so the second is never pre- or
+        // post-visited for it and only one is added.  `Print` is skipped too,
+        // otherwise its indentation would underflow.
+        assert!(matches!(value, Value::Number(x) if x == 43.0));
+    }
+
+    #[tokio::test]
+    async fn native_functions_are_called_and_traced() {
+        let stmts = vec![
+            fun("read", &["id"], call("sensor", vec![var("id")])),
+            Stmt::Let("x".to_owned(), Box::new(call("read", vec![num(7.0)]))),
+            Stmt::Let("y".to_owned(), Box::new(call("read", vec![num(-1.0)]))),
+        ];
+        let mut evaluator = Evaluator::default();
+        evaluator.register_fn("sensor", 1, |args| match args {
+            [Value::Number(id)] if *id >= 0.0 => Ok(Value::Number(id * 10.0)),
+            _ => Err("Sensor offline".into()),
+        });
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+
+        assert!(matches!(evaluator.bindings["x"], Value::Number(x) if x == 70.0));
+        assert_eq!(
+            err.to_string(),
+            "Sensor offline\nStack trace:\n  at sensor(-1)\n  at read(-1)"
+        );
+    }
+
+    #[tokio::test]
+    async fn native_functions_check_arity() {
+        let stmts = vec![Stmt::Let(
+            "x".to_owned(),
+            Box::new(call("max", vec![num(1.0)])),
+        )];
+        let mut evaluator = Evaluator::default();
+        evaluator.register_fn("max", 2, |_| unreachable!());
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Number of function parameters differs from arguments: expected 2, found 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn prelude_functions_and_constants() {
+        let stmts = vec![
+            Stmt::Let(
+                "r".to_owned(),
+                Box::new(call("sqrt", vec![call("pow", vec![num(3.0), num(2.0)])])),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("max", vec![var("r"), var("pi")])),
+            ),
+            // Built-ins can be shadowed like any other binding.
+            fun("abs", &["a"], var("e")),
+            Stmt::Let("y".to_owned(), Box::new(call("abs", vec![num(-1.0)]))),
+        ];
+        let mut evaluator = Evaluator::default();
+        evaluator.eval_stmts(&stmts).await.unwrap();
+
+        let number = |name: &str| match evaluator.bindings[name] {
+            Value::Number(x) => x,
+            _ => panic!("{name} isn't a number"),
+        };
+        assert_eq!(number("r"), 3.0);
+        assert_eq!(number("x"), std::f64::consts::PI);
+        assert_eq!(number("y"), std::f64::consts::E);
+
+        let stmts = vec![Stmt::Let(
+            "z".to_owned(),
+            Box::new(call("floor", vec![var("floor")])),
+        )];
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert_eq!(traced.error.to_string(), "floor only supported for numbers");
+    }
+
+    fn timeout(milliseconds: u64, expr: Expr) -> Expr {
+        Expr::Factor(Box::new(Factor::Timeout {
+            milliseconds,
+            expr: Box::new(expr),
+        }))
+    }
+
+    /// Sets its flag when dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn async_native_functions_are_awaited() {
+        let stmts = vec![Stmt::Let(
+            "x".to_owned(),
+            Box::new(timeout(1000, call("lookup", vec![num(2.0)]))),
+        )];
+        let mut evaluator = Evaluator::default();
+        evaluator.register_async_fn("lookup", 1, |args| async move {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            match args[..] {
+                [Value::Number(key)] => Ok(Value::Number(key * 2.0)),
+                _ => Err("Expected a key".into()),
+            }
+        });
+        let value = evaluator.eval_stmts(&stmts).await.unwrap();
+
+        assert!(matches!(value, Value::Number(x) if x == 4.0));
+    }
+
+    #[tokio::test]
+    async fn timeouts_cancel_async_native_functions() {
+        let stmts = vec![
+            fun("get", &["key"], call("lookup", vec![var("key")])),
+            Stmt::Let("z".to_owned(), Box::new(num(5.0))),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(timeout(10, call("get", vec![num(1.0)]))),
+            ),
+        ];
+        let dropped = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut evaluator = Evaluator::default();
+        evaluator.register_async_fn("lookup", 1, {
+            let (dropped, finished) = (Arc::clone(&dropped), Arc::clone(&finished));
+            move |args| {
+                let guard = DropFlag(Arc::clone(&dropped));
+                let finished = Arc::clone(&finished);
+                async move {
+                    let _guard = guard;
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    finished.store(true, Ordering::SeqCst);
+                    Ok(args[0].clone())
+                }
+            }
+        });
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+
+        assert_eq!(err.to_string(), "Timed out after 10 ms");
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(!finished.load(Ordering::SeqCst));
+        // The bindings are the caller's again, not those of `get`'s body.
+        assert!(matches!(evaluator.bindings["z"], Value::Number(z) if z == 5.0));
+        assert!(!evaluator.bindings.contains_key("key"));
+        assert!(evaluator.call_stack.is_empty());
+    }
+
+    /// `f` defined `depth` times, each calling the one before, and a call of
+    /// the last, so that calls nest exactly `depth` deep.
+    fn nested_calls(depth: usize) -> Vec<Stmt> {
+        let mut stmts = vec![fun("f", &["x"], var("x"))];
+        for _ in 1..depth {
+            stmts.push(fun("f", &["x"], call("f", vec![var("x")])));
+        }
+        stmts.push(Stmt::Let(
+            "y".to_owned(),
+            Box::new(call("f", vec![num(1.0)])),
+        ));
+        stmts
+    }
+
+    #[tokio::test]
+    async fn calls_nest_up_to_the_limit() {
+        let mut evaluator = Evaluator {
+            op_delay: Duration::ZERO,
+            ..Evaluator::default()
+        };
+        let value = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH))
+            .await;
+        assert!(matches!(value, Ok(Value::Number(y)) if y == 1.0));
+
+        let mut evaluator = Evaluator::default();
+        let err = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH + 1))
+            .await
+            .unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    /// A writer whose output the test can still read after evaluation.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            let out = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            out.lines().map(str::to_owned).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_are_balanced_after_a_timeout() {
+        let stmts = vec![
+            fun("get", &["key"], call("lookup", vec![var("key")])),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(timeout(10, call("get", vec![num(1.0)]))),
+            ),
+        ];
+        let (jsonl, print) = (Buffer::default(), Buffer::default());
+        let mut evaluator = Evaluator::default();
+        evaluator.register_async_fn("lookup", 1, |args| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(args[0].clone())
+        });
+        evaluator
+            .visitors
+            .push(Box::new(log::JsonLines::new(jsonl.clone(), &stmts)));
+        evaluator
+            .visitors
+            .push(Box::new(log::Print::new(print.clone())));
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+        assert_eq!(err.to_string(), "Timed out after 10 ms");
+
+        // The nodes inside the timeout were cancelled and never left, so the
+        // timeout and the nodes outside it are left at the depth they were
+        // entered at.
+        let events = jsonl
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let left = events
+            .iter()
+            .filter(|event| event["phase"] == "post")
+            .map(|event| (event["kind"].as_str().unwrap(), &event["depth"]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            left[left.len() - 3..],
+            [
+                ("Factor::Timeout", &2.into()),
+                ("Expr::Factor", &1.into()),
+                ("Stmt::Let", &0.into())
+            ]
+        );
+        let timeout = events
+            .iter()
+            .find(|event| event["phase"] == "post" && event["kind"] == "Factor::Timeout")
+            .unwrap();
+        assert_eq!(timeout["error"], "Timed out after 10 ms");
+        assert_eq!(timeout["source"], "timeout(10) { get(1) }");
+
+        let lines = print.lines();
+        let last = lines.last().unwrap();
+        assert!(last.starts_with("Eval stmt=Let("), "{last}");
+        let timeout = lines
+            .iter()
+            .filter(|line| line.trim_start().starts_with("Eval factor=Timeout"))
+            .map(|line| line.len() - line.trim_start().len())
+            .collect::<Vec<_>>();
+        assert_eq!(timeout, [4, 4]);
+    }
+
+    /// Reports each literal it's about to see, then waits for the test's
+    /// go-ahead before letting evaluation continue.
+    #[derive(Debug)]
+    struct Gate {
+        seen: tokio::sync::mpsc::UnboundedSender<f64>,
+        go: tokio::sync::mpsc::Receiver<()>,
+    }
+
+    #[async_trait::async_trait]
+    impl AsyncVisit for Gate {
+        async fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+            if let Factor::Literal(x) = factor {
+                self.seen.send(*x)?;
+                self.go.recv().await.ok_or("gate closed")?;
+            }
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn visitors_can_await() {
+        let (seen, mut seen_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (go_tx, go) = tokio::sync::mpsc::channel(1);
+        let mut evaluator = Evaluator::default();
+        evaluator.visitors.push(Box::new(Gate { seen, go }));
+        let stmts = vec![Stmt::Let(
+            "x".to_owned(),
+            Box::new(Expr::BinaryOp {
+                op: TermBinaryOp::Add,
+                lhs: Box::new(num(1.0)),
+                rhs: Box::new(num(2.0)),
+            }),
+        )];
+        let evaluation = tokio::spawn(async move { evaluator.eval_stmts(&stmts).await.unwrap() });
+
+        for x in [1.0, 2.0] {
+            assert_eq!(seen_rx.recv().await, Some(x));
+            // Evaluation is suspended in the hook until the gate opens.
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(!evaluation.is_finished());
+            go_tx.send(()).await.unwrap();
+        }
+        let value = evaluation.await.unwrap();
+        assert!(matches!(value, Value::Number(x) if x == 3.0));
+    }
+
+    /// Records the targeted hooks it's called with.
+    #[derive(Debug)]
+    struct Record(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl Record {
+        fn log(&self, event: String) -> Result<(), Error> {
+            self.0.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    impl Visit for Record {
+        fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+            let args = args.iter().map(Value::to_string).collect::<Vec<_>>();
+            let mut env = env
+                .iter()
+                .filter(|(name, value)| !value.is_prelude(name))
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            env.sort();
+            self.log(format!("call {fun}({}) in {env:?}", args.join(", ")))
+        }
+
+        fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+            self.log(format!("return {value}"))
+        }
+
+        fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+            self.log(format!("bind {name} {value}"))
+        }
+
+        fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+            self.log(format!("lookup {name}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_run_in_evaluation_order() {
+        let stmts = vec![
+            Stmt::Let("k".to_owned(), Box::new(num(1.0))),
+            fun(
+                "add",
+                &["a", "b"],
+                Expr::BinaryOp {
+                    op: TermBinaryOp::Add,
+                    lhs: Box::new(var("a")),
+                    rhs: Box::new(var("b")),
+                },
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("add", vec![var("k"), num(2.0)])),
+            ),
+            Stmt::Let("y".to_owned(), Box::new(call("abs", vec![var("x")]))),
+        ];
+        let events = Arc::default();
+        let mut evaluator = Evaluator {
+            op_delay: Duration::ZERO,
+            ..Evaluator::default()
+        };
+        evaluator
+            .visitors
+            .push(Box::new(Record(Arc::clone(&events))));
+        evaluator.eval_stmts(&stmts).await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "bind k 1",
+                "bind add <fun add>",
+                // The function, then the arguments, are evaluated before the call.
+                "lookup add",
+                "lookup k",
+                "call add(1, 2) in [\"k\"]",
+                "bind a 1",
+                "bind b 2",
+                "lookup a",
+                "lookup b",
+                "return 3",
+                "bind x 3",
+                "lookup abs",
+                "lookup x",
+                // Natives are called with no environment.
+                "call abs(3) in []",
+                "return 3",
+                "bind y 3",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn debugger_is_driven_through_its_channels() {
+        use crate::mutable_self_async::debugger::{
+            Breakpoint, Command, Controller, Debugger, Scope,
+        };
+
+        /// The line, kind, source and scopes of the next pause.
+        async fn paused(controller: &mut Controller) -> (usize, &'static str, String, Vec<Scope>) {
+            let paused = controller.paused.recv().await.unwrap();
+            (
+                paused.location.line,
+                paused.kind,
+                paused.source,
+                paused.scopes,
+            )
+        }
+
+        let stmts = vec![
+            fun(
+                "add",
+                &["a", "b"],
+                Expr::BinaryOp {
+                    op: TermBinaryOp::Add,
+                    lhs: Box::new(var("a")),
+                    rhs: Box::new(var("b")),
+                },
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("add", vec![num(1.0), num(2.0)])),
+            ),
+            Stmt::Let("y".to_owned(), Box::new(var("x"))),
+        ];
+        let mut evaluator = Evaluator {
+            op_delay: Duration::ZERO,
+            ..Evaluator::default()
+        };
+        let (debugger, mut controller) = Debugger::new(&stmts, &evaluator.bindings);
+        evaluator.visitors.push(Box::new(debugger));
+        let evaluation = tokio::spawn(async move { evaluator.eval_stmts(&stmts).await.unwrap() });
+        // It pauses before the first statement.
+        let (line, kind, source, _) = paused(&mut controller).await;
+        assert_eq!(
+            (line, kind, source.as_str()),
+            (1, "Stmt::Fun", "fun add(a, b) = a + b")
+        );
+        controller
+            .commands
+            .send(Command::SetBreakpoints(vec![Breakpoint::Function(
+                "add".to_owned(),
+            )]))
+            .unwrap();
+        controller.commands.send(Command::Continue).unwrap();
+
+        // Then at the breakpoint, in the body of the call on line 2.
+        let (line, kind, source, scopes) = paused(&mut controller).await;
+        assert_eq!(
+            (line, kind, source.as_str()),
+            (1, "Expr::BinaryOp", "a + b")
+        );
+        let call = scopes.last().unwrap();
+        assert_eq!((call.name.as_str(), call.call_line), ("add", 2));
+        assert!(matches!(call.env["b"], Value::Number(b) if b == 2.0));
+        assert!(!evaluation.is_finished());
+        controller.commands.send(Command::StepInto).unwrap();
+
+        let (_, kind, source, _) = paused(&mut controller).await;
+        assert_eq!((kind, source.as_str()), ("Expr::Factor", "a"));
+        controller.commands.send(Command::StepOut).unwrap();
+
+        // Out of `add`, at the next statement.
+        let (line, kind, _, scopes) = paused(&mut controller).await;
+        assert_eq!((line, kind, scopes.len()), (3, "Stmt::Let", 1));
+        assert!(matches!(scopes[0].env["x"], Value::Number(x) if x == 3.0));
+        controller.commands.send(Command::Continue).unwrap();
+
+        let value = evaluation.await.unwrap();
+        assert!(matches!(value, Value::Number(y) if y == 3.0));
+        // The debugger was dropped with the evaluator, without pausing again.
+        assert!(controller.paused.recv().await.is_none());
+    }
+}
@@ -0,0 +1,34 @@
+//! The math functions and constants of the [common prelude](crate::common::prelude)
+//! as values, bound in every new
+//! [`Evaluator`](crate::mutable_self_async::evaluator::Evaluator).
+use std::sync::Arc;
+
+use crate::{
+    common::prelude::{self, Call},
+    mutable_self_async::runtime::{Env, Native, Value},
+};
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn native(name: &'static str, arity: usize, call: Call) -> Value {
+    Value::Native(Native {
+        name: name.to_owned(),
+        arity,
+        fun: Arc::new(move |args| {
+            let result = prelude::numbers(name, &args, number)
+                .map(|args| Value::Number(call(&args)))
+                .map_err(Into::into);
+            Box::pin(std::future::ready(result))
+        }),
+    })
+}
+
+/// The built-in functions and constants, by name.
+pub fn bindings() -> Env {
+    prelude::bindings(native, Value::Number)
+}
@@ -0,0 +1,844 @@
+//! Record-and-replay of evaluations.
+//!
+//! Within one evaluation, the only decision that depends on timing is whether
+//! a `timeout` expires before its expression finishes; yields only let other
+//! tasks run.  A [`Recorder`] logs every event of an evaluation along with
+//! these decisions, and [`Evaluator::replay`](crate::mutable_self_async::evaluator::Evaluator::replay)
+//! makes a later evaluation repeat them exactly.  A [`TimeTravel`] steps
+//! forwards and backwards through the states of a recording.
+//!
+//! Functions registered by the host are called again when replaying, so a
+//! replay diverges if one of them returns something else the second time.
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use async_trait::async_trait;
+use serde_json::{json, Value as Json};
+use tokio::sync::watch;
+
+use crate::mutable_self_async::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, AsyncVisit, Env, Error, Value},
+};
+
+/// Something that happened during an evaluation.  Values are kept as they are
+/// displayed, which is exact for numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A node was entered.
+    Pre {
+        kind: String,
+        id: usize,
+    },
+    /// A node was left with a value or an error.
+    Post {
+        kind: String,
+        id: usize,
+        result: Result<String, String>,
+    },
+    /// A function was called with these arguments and captured environment.
+    Call {
+        fun: Identifier,
+        args: Vec<String>,
+        env: BTreeMap<Identifier, String>,
+    },
+    Return {
+        value: String,
+    },
+    Bind {
+        name: Identifier,
+        value: String,
+    },
+    Lookup {
+        name: Identifier,
+    },
+    /// The timeout with this id finished, or expired if `elapsed`.
+    Timeout {
+        id: usize,
+        elapsed: bool,
+    },
+}
+
+impl Event {
+    fn pre(kind: &str, location: Option<Location>) -> Result<Self, Error> {
+        Ok(Event::Pre {
+            kind: kind.to_owned(),
+            id: id(location)?,
+        })
+    }
+
+    fn post(
+        kind: &str,
+        location: Option<Location>,
+        result: &Result<Value, Error>,
+    ) -> Result<Self, Error> {
+        Ok(Event::Post {
+            kind: kind.to_owned(),
+            id: id(location)?,
+            result: match result {
+                Ok(value) => Ok(value.to_string()),
+                Err(error) => Err(error.to_string()),
+            },
+        })
+    }
+
+    fn call(fun: &Identifier, args: &[Value], env: &Env) -> Self {
+        Event::Call {
+            fun: fun.clone(),
+            args: args.iter().map(Value::to_string).collect(),
+            env: env
+                .iter()
+                .map(|(name, value)| (name.clone(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        match self {
+            Event::Pre { kind, id } => json!({ "event": "pre", "kind": kind, "id": id }),
+            Event::Post { kind, id, result } => {
+                let mut json = json!({ "event": "post", "kind": kind, "id": id });
+                match result {
+                    Ok(value) => json["value"] = json!(value),
+                    Err(error) => json["error"] = json!(error),
+                }
+                json
+            }
+            Event::Call { fun, args, env } => {
+                json!({ "event": "call", "fun": fun, "args": args, "env": env })
+            }
+            Event::Return { value } => json!({ "event": "return", "value": value }),
+            Event::Bind { name, value } => json!({ "event": "bind", "name": name, "value": value }),
+            Event::Lookup { name } => json!({ "event": "lookup", "name": name }),
+            Event::Timeout { id, elapsed } => {
+                json!({ "event": "timeout", "id": id, "elapsed": elapsed })
+            }
+        }
+    }
+
+    pub fn from_json(json: &Json) -> Result<Self, Error> {
+        let invalid = || format!("Invalid event: {json}");
+        let string = |key: &str| json[key].as_str().map(str::to_owned).ok_or_else(invalid);
+        let id = || {
+            json["id"]
+                .as_u64()
+                .map(|id| id as usize)
+                .ok_or_else(invalid)
+        };
+        let event = match json["event"].as_str() {
+            Some("pre") => Event::Pre {
+                kind: string("kind")?,
+                id: id()?,
+            },
+            Some("post") => Event::Post {
+                kind: string("kind")?,
+                id: id()?,
+                result: match string("value") {
+                    Ok(value) => Ok(value),
+                    Err(_) => Err(string("error")?),
+                },
+            },
+            Some("call") => Event::Call {
+                fun: string("fun")?,
+                args: serde_json::from_value(json["args"].clone()).map_err(|_| invalid())?,
+                env: serde_json::from_value(json["env"].clone()).map_err(|_| invalid())?,
+            },
+            Some("return") => Event::Return {
+                value: string("value")?,
+            },
+            Some("bind") => Event::Bind {
+                name: string("name")?,
+                value: string("value")?,
+            },
+            Some("lookup") => Event::Lookup {
+                name: string("name")?,
+            },
+            Some("timeout") => Event::Timeout {
+                id: id()?,
+                elapsed: json["elapsed"].as_bool().ok_or_else(invalid)?,
+            },
+            _ => return Err(invalid().into()),
+        };
+        Ok(event)
+    }
+}
+
+fn id(location: Option<Location>) -> Result<usize, Error> {
+    location
+        .map(|location| location.id)
+        .ok_or_else(|| "Node is not part of the recorded program".into())
+}
+
+/// The events of an evaluation, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    /// Reads a recording written by a [`Recorder`], one event per line.
+    pub fn read(input: impl BufRead) -> Result<Self, Error> {
+        let mut events = Vec::new();
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(Event::from_json(&serde_json::from_str(&line)?)?);
+        }
+        Ok(Self { events })
+    }
+}
+
+/// Writes every event of an evaluation to `out` as a line of JSON.
+///
+/// Add it as the first visitor so it records results as the evaluator sees
+/// them, after every other visitor has transformed them.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    ids: NodeIds,
+}
+
+impl Recorder {
+    /// Records the evaluation of `stmts` to `out`.
+    pub fn new(out: impl Write + Send + 'static, stmts: &[Stmt]) -> Self {
+        Self {
+            out: Box::new(out),
+            ids: NodeIds::new(stmts),
+        }
+    }
+
+    fn write(&mut self, event: Event) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.out, &event.to_json())?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder").finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for Recorder {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.write(Event::pre(stmt.kind(), self.ids.stmt(stmt))?)?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.write(Event::post(stmt.kind(), self.ids.stmt(stmt), &result)?)?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.write(Event::pre(expr.kind(), self.ids.expr(expr))?)?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.write(Event::post(expr.kind(), self.ids.expr(expr), &result)?)?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        self.write(Event::pre(factor.kind(), self.ids.factor(factor))?)?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let location = self.ids.factor(factor);
+        self.write(Event::post(factor.kind(), location, &result)?)?;
+        result
+    }
+
+    fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+        self.write(Event::call(fun, args, env))
+    }
+
+    fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+        self.write(Event::Return {
+            value: value.to_string(),
+        })
+    }
+
+    fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+        self.write(Event::Bind {
+            name: name.clone(),
+            value: value.to_string(),
+        })
+    }
+
+    fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+        self.write(Event::Lookup { name: name.clone() })
+    }
+
+    fn on_timeout(&mut self, factor: &Factor, elapsed: bool) -> Result<(), Error> {
+        let id = id(self.ids.factor(factor))?;
+        self.write(Event::Timeout { id, elapsed })
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Checks each event of an evaluation against a recording, and expires
+/// timeouts where the recording did.  Added by `Evaluator::replay()`.
+#[derive(Debug)]
+pub struct Replayer {
+    events: Vec<Event>,
+    /// Index of the next event expected.
+    next: usize,
+    ids: NodeIds,
+    /// Tells the evaluator the nesting level of the timeout to expire.
+    cancel: watch::Sender<Option<usize>>,
+    /// Ids of the timeouts being evaluated, outermost first.
+    timeouts: Vec<usize>,
+}
+
+impl Replayer {
+    pub fn new(recording: Recording, stmts: &[Stmt]) -> (Self, watch::Receiver<Option<usize>>) {
+        let (cancel, receiver) = watch::channel(None);
+        let replayer = Self {
+            events: recording.events,
+            next: 0,
+            ids: NodeIds::new(stmts),
+            cancel,
+            timeouts: Vec::new(),
+        };
+        (replayer, receiver)
+    }
+
+    fn diverged(&self, event: &Event) -> Error {
+        let expected = match self.events.get(self.next) {
+            Some(expected) => expected.to_json().to_string(),
+            None => "the end of the recording".to_owned(),
+        };
+        format!(
+            "Replay diverged at event {}: expected {expected}, got {}",
+            self.next,
+            event.to_json()
+        )
+        .into()
+    }
+
+    async fn expect(&mut self, event: Event) -> Result<(), Error> {
+        match self.events.get(self.next) {
+            Some(expected) if *expected == event => {
+                self.next += 1;
+                Ok(())
+            }
+            // The recorded timeout expired before this event happened.
+            Some(Event::Timeout { elapsed: true, .. })
+                if !matches!(event, Event::Timeout { .. }) =>
+            {
+                self.expire().await
+            }
+            _ => Err(self.diverged(&event)),
+        }
+    }
+
+    /// Like `expect()`, but for post-order hooks, which run after those of
+    /// later visitors.  Expires a timeout right after the recorded event, so
+    /// later visitors don't see the post-order event that would come next.
+    async fn expect_post(&mut self, event: Event) -> Result<(), Error> {
+        self.expect(event).await?;
+        if let Some(Event::Timeout { elapsed: true, .. }) = self.events.get(self.next) {
+            self.expire().await?;
+        }
+        Ok(())
+    }
+
+    /// Cancels evaluation up to the timeout expected next.  The evaluator
+    /// drops the future of this hook instead of resuming it.
+    async fn expire(&mut self) -> Result<(), Error> {
+        let Some(&Event::Timeout { id, .. }) = self.events.get(self.next) else {
+            unreachable!("only called when a timeout is expected next");
+        };
+        let Some(level) = self.timeouts.iter().rposition(|timeout| *timeout == id) else {
+            return Err(format!(
+                "Replay diverged at event {}: timeout {id} is not being evaluated",
+                self.next
+            )
+            .into());
+        };
+        self.cancel.send_replace(Some(level));
+        std::future::pending().await
+    }
+}
+
+#[async_trait]
+impl AsyncVisit for Replayer {
+    async fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.expect(Event::pre(stmt.kind(), self.ids.stmt(stmt))?)
+            .await?;
+        Ok(None)
+    }
+
+    async fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.expect_post(Event::post(stmt.kind(), self.ids.stmt(stmt), &result)?)
+            .await?;
+        result
+    }
+
+    async fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.expect(Event::pre(expr.kind(), self.ids.expr(expr))?)
+            .await?;
+        Ok(None)
+    }
+
+    async fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.expect_post(Event::post(expr.kind(), self.ids.expr(expr), &result)?)
+            .await?;
+        result
+    }
+
+    async fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let location = self.ids.factor(factor);
+        self.expect(Event::pre(factor.kind(), location)?).await?;
+        if let Factor::Timeout { .. } = factor {
+            self.timeouts.push(id(location)?);
+        }
+        Ok(None)
+    }
+
+    async fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let location = self.ids.factor(factor);
+        if let Factor::Timeout { .. } = factor {
+            self.timeouts.pop();
+        }
+        self.expect_post(Event::post(factor.kind(), location, &result)?)
+            .await?;
+        result
+    }
+
+    async fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+        self.expect(Event::call(fun, args, env)).await
+    }
+
+    async fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+        let value = value.to_string();
+        self.expect(Event::Return { value }).await
+    }
+
+    async fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+        let name = name.clone();
+        let value = value.to_string();
+        self.expect(Event::Bind { name, value }).await
+    }
+
+    async fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+        let name = name.clone();
+        self.expect(Event::Lookup { name }).await
+    }
+
+    async fn on_timeout(&mut self, factor: &Factor, elapsed: bool) -> Result<(), Error> {
+        let id = id(self.ids.factor(factor))?;
+        // Timeouts inside this one were cancelled along with it.
+        if let Some(level) = self.timeouts.iter().rposition(|timeout| *timeout == id) {
+            self.timeouts.truncate(level + 1);
+        }
+        self.cancel.send_replace(None);
+        self.expect(Event::Timeout { id, elapsed }).await
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        if self.next < self.events.len() {
+            return Err(format!(
+                "Replay ended at event {} of {}",
+                self.next,
+                self.events.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The bindings of a call in progress, or of the top level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub name: Identifier,
+    pub bindings: BTreeMap<Identifier, String>,
+}
+
+/// The state of a recorded evaluation after some of its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State<'a> {
+    /// Number of events that happened.
+    pub index: usize,
+    /// The last event that happened.
+    pub event: Option<&'a Event>,
+    /// Ids of the nodes being evaluated, outermost first.
+    pub nodes: Vec<usize>,
+    /// The top level first, then each call in progress.
+    pub scopes: Vec<Scope>,
+}
+
+/// Steps forwards and backwards through a recording.
+#[derive(Debug)]
+pub struct TimeTravel {
+    recording: Recording,
+    /// Number of events that happened.
+    cursor: usize,
+}
+
+impl TimeTravel {
+    /// Starts before the first event.
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            cursor: 0,
+        }
+    }
+
+    pub fn step_forward(&mut self) -> Option<State<'_>> {
+        if self.cursor == self.recording.events.len() {
+            return None;
+        }
+        self.cursor += 1;
+        Some(self.state())
+    }
+
+    pub fn step_back(&mut self) -> Option<State<'_>> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        Some(self.state())
+    }
+
+    /// Rebuilds the current state by playing the events up to the cursor.
+    pub fn state(&self) -> State<'_> {
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut scopes = vec![Scope {
+            name: "<top level>".to_owned(),
+            bindings: BTreeMap::new(),
+        }];
+        // Closes open nodes until `done` says to stop.
+        let close = |open: &mut Vec<(usize, usize)>,
+                     scopes: &mut Vec<Scope>,
+                     done: &dyn Fn(usize) -> bool| {
+            while let Some(&(node, depth)) = open.last() {
+                if done(node) {
+                    break;
+                }
+                open.pop();
+                scopes.truncate(depth);
+            }
+        };
+        for event in &self.recording.events[..self.cursor] {
+            match event {
+                Event::Pre { id, .. } => open.push((*id, scopes.len())),
+                Event::Post { id, .. } => {
+                    close(&mut open, &mut scopes, &|node| node == *id);
+                    if let Some((_, depth)) = open.pop() {
+                        scopes.truncate(depth);
+                    }
+                }
+                Event::Call { fun, env, .. } => scopes.push(Scope {
+                    name: fun.clone(),
+                    bindings: env.clone(),
+                }),
+                Event::Bind { name, value } => {
+                    let scope = scopes.last_mut().expect("the top level is never closed");
+                    scope.bindings.insert(name.clone(), value.clone());
+                }
+                // Nodes inside the timeout were cancelled without being left.
+                Event::Timeout { id, .. } => close(&mut open, &mut scopes, &|node| node == *id),
+                Event::Return { .. } | Event::Lookup { .. } => {}
+            }
+        }
+        State {
+            index: self.cursor,
+            event: self
+                .cursor
+                .checked_sub(1)
+                .map(|i| &self.recording.events[i]),
+            nodes: open.into_iter().map(|(node, _)| node).collect(),
+            scopes,
+        }
+    }
+}
+
+const HELP: &str = "\
+next, n              go to the next event
+back, b              go to the previous event
+quit, q              stop
+";
+
+fn print_state(output: &mut impl Write, state: &State, len: usize) -> Result<(), Error> {
+    match state.event {
+        Some(event) => writeln!(output, "Event {}/{len}: {}", state.index, event.to_json())?,
+        None => writeln!(output, "Event 0/{len}: start")?,
+    }
+    let nodes = state.nodes.iter().map(usize::to_string).collect::<Vec<_>>();
+    writeln!(output, "  nodes: {}", nodes.join(" > "))?;
+    for scope in state.scopes.iter().rev() {
+        let bindings = scope
+            .bindings
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect::<Vec<_>>();
+        writeln!(output, "  in {}: {}", scope.name, bindings.join(", "))?;
+    }
+    Ok(())
+}
+
+/// A command-line front end for stepping through `recording`.  Returns when
+/// `input` is closed or the user quits.
+pub fn travel(
+    recording: Recording,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), Error> {
+    let len = recording.events.len();
+    let mut travel = TimeTravel::new(recording);
+    print_state(&mut output, &travel.state(), len)?;
+    loop {
+        write!(output, "(travel) ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let state = match line.trim() {
+            "next" | "n" => travel.step_forward(),
+            "back" | "b" => travel.step_back(),
+            "quit" | "q" => return Ok(()),
+            "help" | "h" => {
+                write!(output, "{HELP}")?;
+                continue;
+            }
+            other => {
+                writeln!(output, "Unknown command: {other}.  Type `help` for a list.")?;
+                continue;
+            }
+        };
+        match state {
+            Some(state) => print_state(&mut output, &state, len)?,
+            None => writeln!(output, "No more events that way")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::mutable_self_async::{
+        ast::{Function, TermBinaryOp},
+        evaluator::Evaluator,
+        runtime::{TimedOut, TracedError},
+    };
+
+    /// A sink whose contents outlive the evaluator.
+    #[derive(Debug, Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn recording(&self) -> Recording {
+            Recording::read(&self.0.lock().unwrap()[..]).unwrap()
+        }
+    }
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn add(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::BinaryOp {
+            op: TermBinaryOp::Add,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    /// `let x = 1; fun inc(a) = a + 1; let y = timeout(ms) { inc(x) + ... }`
+    /// with `calls` calls to `inc`, each sleeping for a millisecond.
+    fn program(milliseconds: u64, calls: usize) -> Vec<Stmt> {
+        let call = || {
+            Expr::Factor(Box::new(Factor::Call(
+                Box::new(Factor::Variable("inc".to_owned())),
+                vec![var("x")],
+            )))
+        };
+        let sum = (1..calls).fold(call(), |sum, _| add(sum, call()));
+        vec![
+            Stmt::Let("x".to_owned(), Box::new(num(1.0))),
+            Stmt::Fun(
+                "inc".to_owned(),
+                Arc::new(Function {
+                    params: vec!["a".to_owned()],
+                    body: add(var("a"), num(1.0)),
+                }),
+            ),
+            Stmt::Let(
+                "y".to_owned(),
+                Box::new(Expr::Factor(Box::new(Factor::Timeout {
+                    milliseconds,
+                    expr: Box::new(sum),
+                }))),
+            ),
+        ]
+    }
+
+    async fn record(stmts: &[Stmt]) -> (Result<Value, Error>, Recording) {
+        let sink = Shared::default();
+        let mut evaluator = Evaluator::default();
+        evaluator
+            .visitors
+            .push(Box::new(Recorder::new(sink.clone(), stmts)));
+        let result = evaluator.eval_stmts(stmts).await;
+        (result, sink.recording())
+    }
+
+    fn timed_out(result: &Result<Value, Error>) -> bool {
+        match result {
+            Err(err) => err
+                .downcast_ref::<TracedError>()
+                .unwrap()
+                .error
+                .is::<TimedOut>(),
+            Ok(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_expires_timeout_at_the_same_event() {
+        let stmts = program(10, 50);
+        let (result, recording) = record(&stmts).await;
+        assert!(timed_out(&result));
+        assert!(matches!(
+            recording.events.last(),
+            Some(Event::Post { result: Err(_), .. })
+        ));
+
+        // Replay while recording again.
+        let sink = Shared::default();
+        let mut evaluator = Evaluator::default();
+        evaluator
+            .visitors
+            .push(Box::new(Recorder::new(sink.clone(), &stmts)));
+        evaluator.replay(recording.clone(), &stmts);
+        let replayed = evaluator.eval_stmts(&stmts).await;
+        assert!(timed_out(&replayed));
+        assert_eq!(sink.recording(), recording);
+    }
+
+    #[tokio::test]
+    async fn replay_finishes_timeout_that_finished() {
+        let stmts = program(10_000, 2);
+        let (result, recording) = record(&stmts).await;
+        assert!(matches!(result, Ok(Value::Number(y)) if y == 4.0));
+        assert!(recording
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::Timeout { elapsed: false, .. })));
+
+        let mut evaluator = Evaluator::default();
+        evaluator.replay(recording, &stmts);
+        let replayed = evaluator.eval_stmts(&stmts).await;
+        assert!(matches!(replayed, Ok(Value::Number(y)) if y == 4.0));
+    }
+
+    #[tokio::test]
+    async fn replay_fails_when_program_changes() {
+        let (_, recording) = record(&program(10_000, 2)).await;
+        let mut stmts = program(10_000, 2);
+        stmts[0] = Stmt::Let("x".to_owned(), Box::new(num(2.0)));
+
+        let mut evaluator = Evaluator::default();
+        evaluator.replay(recording, &stmts);
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("Replay diverged at event 3:"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn travel_steps_back_through_calls() {
+        let (_, recording) = record(&program(10_000, 1)).await;
+        let len = recording.events.len();
+        let mut travel = TimeTravel::new(recording);
+
+        // Step forward to where `inc` binds its parameter.
+        let call_start = loop {
+            let state = travel.step_forward().unwrap();
+            if let Some(Event::Bind { name, .. }) = state.event {
+                if name == "a" {
+                    break state.index;
+                }
+            }
+        };
+        let state = travel.state();
+        assert_eq!(state.scopes.len(), 2);
+        assert_eq!(state.scopes[1].name, "inc");
+        assert_eq!(state.scopes[1].bindings["a"], "1");
+        assert_eq!(state.scopes[1].bindings["x"], "1");
+
+        // Step back before the call.
+        let state = travel.step_back().unwrap();
+        assert_eq!(state.index, call_start - 1);
+        assert!(matches!(state.event, Some(Event::Call { .. })));
+        assert!(!state.scopes[1].bindings.contains_key("a"));
+        let state = travel.step_back().unwrap();
+        assert_eq!(state.scopes.len(), 1);
+
+        // Everything is closed again at the end.
+        while travel.step_forward().is_some() {}
+        let state = travel.state();
+        assert_eq!(state.index, len);
+        assert!(state.nodes.is_empty());
+        assert_eq!(state.scopes.len(), 1);
+        assert_eq!(state.scopes[0].bindings["y"], "2");
+        assert!(travel.step_forward().is_none());
+    }
+}
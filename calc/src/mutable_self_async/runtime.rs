@@ -0,0 +1,319 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    common::prelude,
+    mutable_self_async::ast::{Expr, Factor, Function, Identifier, Stmt},
+};
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Env = HashMap<Identifier, Value>;
+
+#[derive(Clone)]
+pub enum Value {
+    Number(f64),
+    /// A function, the name it was defined with, and its captured environment.
+    Closure(Identifier, Arc<Function>, Env),
+    /// A function implemented by the host.
+    Native(Native),
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(x) => f.debug_tuple("Number").field(x).finish(),
+            Value::Closure(name, fun, env) => {
+                // Sorted, so that the output is the same from run to run, and
+                // without the prelude that every closure captures.
+                let env = env
+                    .iter()
+                    .filter(|(name, value)| !value.is_prelude(name))
+                    .collect::<BTreeMap<_, _>>();
+                f.debug_tuple("Closure")
+                    .field(name)
+                    .field(fun)
+                    .field(&env)
+                    .finish()
+            }
+            Value::Native(native) => f.debug_tuple("Native").field(native).finish(),
+        }
+    }
+}
+
+impl Value {
+    /// Whether this is the [`prelude`]'s binding of `name`, as in a new
+    /// `Evaluator`.
+    pub fn is_prelude(&self, name: &str) -> bool {
+        match self {
+            Value::Number(x) => prelude::is_constant(name, *x),
+            Value::Native(native) => native.name == name && prelude::is_function(name),
+            Value::Closure(..) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(x) => write!(f, "{x}"),
+            Value::Closure(name, _, _) => write!(f, "<fun {name}>"),
+            Value::Native(native) => write!(f, "<native fun {}>", native.name),
+        }
+    }
+}
+
+/// What calling a function implemented by the host returns.
+pub type NativeFuture = Pin<Box<dyn Future<Output = Result<Value, Error>> + Send>>;
+
+/// The type of the functions the host implements, given the argument values.
+/// Synchronous ones return a future that's already ready.
+pub type NativeFn = dyn Fn(Vec<Value>) -> NativeFuture + Send + Sync;
+
+/// A function implemented by the host, bound with
+/// [`Evaluator::register_fn`](crate::mutable_self_async::evaluator::Evaluator::register_fn)
+/// or [`Evaluator::register_async_fn`](crate::mutable_self_async::evaluator::Evaluator::register_async_fn).
+#[derive(Clone)]
+pub struct Native {
+    pub name: Identifier,
+    pub arity: usize,
+    pub fun: Arc<NativeFn>,
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+pub use crate::common::StackOverflow;
+
+/// A function call that's in progress.
+pub type Frame = crate::common::Frame<Value>;
+
+/// An error that escaped evaluation, with the calls that led to it.
+pub type TracedError = crate::common::TracedError<Error, Value>;
+
+/// A timeout expired before its expression finished.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub milliseconds: u64,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out after {} ms", self.milliseconds)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Hooks run by the evaluator as it evaluates each node.
+///
+/// Pre-order hooks run in the order visitors were added.  Returning a value or
+/// an error from one skips evaluating the node and skips the pre-order hooks
+/// of later visitors.  Post-order hooks then run in reverse order, but only for
+/// the visitors whose pre-order hook ran.  Each receives the result of the one
+/// before it, starting from the evaluated (or replaced) result, and returns
+/// the result to use.
+pub trait Visit: std::fmt::Debug {
+    fn pre_visit_stmt(&mut self, _stmt: &Stmt) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    fn post_visit_stmt(
+        &mut self,
+        _stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    fn pre_visit_expr(&mut self, _expr: &Expr) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    fn post_visit_expr(
+        &mut self,
+        _expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    fn pre_visit_factor(&mut self, _factor: &Factor) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    fn post_visit_factor(
+        &mut self,
+        _factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    /// Called with the evaluated arguments and the closure's captured
+    /// environment just before the parameters are bound.
+    fn on_call(&mut self, _fun: &Identifier, _args: &[Value], _env: &Env) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called when a function body evaluates successfully.
+    fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called when a name is bound by `let`, `fun` or a function parameter.
+    fn on_bind(&mut self, _name: &Identifier, _value: &Value) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called before a variable is looked up.
+    fn on_lookup(&mut self, _name: &Identifier) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called when the expression of `timeout` finishes, or when the timeout
+    /// expires and cancels it.  Replaying a recorded run repeats these
+    /// decisions.
+    fn on_timeout(&mut self, _factor: &Factor, _elapsed: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called when `eval_stmts()` is done, whether or not it succeeded.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A visitor whose hooks can await, e.g. to stream events or wait for a
+/// debugger command.
+///
+/// Hooks are run in the same order as [`Visit`], and every synchronous `Visit`
+/// is also an `AsyncVisit`.
+#[async_trait]
+pub trait AsyncVisit: std::fmt::Debug + Send {
+    async fn pre_visit_stmt(&mut self, _stmt: &Stmt) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    async fn post_visit_stmt(
+        &mut self,
+        _stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    async fn pre_visit_expr(&mut self, _expr: &Expr) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    async fn post_visit_expr(
+        &mut self,
+        _expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    async fn pre_visit_factor(&mut self, _factor: &Factor) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+    async fn post_visit_factor(
+        &mut self,
+        _factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        result
+    }
+
+    async fn on_call(
+        &mut self,
+        _fun: &Identifier,
+        _args: &[Value],
+        _env: &Env,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn on_bind(&mut self, _name: &Identifier, _value: &Value) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn on_lookup(&mut self, _name: &Identifier) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn on_timeout(&mut self, _factor: &Factor, _elapsed: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Visit + Send> AsyncVisit for T {
+    async fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        Visit::pre_visit_stmt(self, stmt)
+    }
+
+    async fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        Visit::post_visit_stmt(self, stmt, result)
+    }
+
+    async fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        Visit::pre_visit_expr(self, expr)
+    }
+
+    async fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        Visit::post_visit_expr(self, expr, result)
+    }
+
+    async fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        Visit::pre_visit_factor(self, factor)
+    }
+
+    async fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        Visit::post_visit_factor(self, factor, result)
+    }
+
+    async fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+        Visit::on_call(self, fun, args, env)
+    }
+
+    async fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+        Visit::on_return(self, value)
+    }
+
+    async fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+        Visit::on_bind(self, name, value)
+    }
+
+    async fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+        Visit::on_lookup(self, name)
+    }
+
+    async fn on_timeout(&mut self, factor: &Factor, elapsed: bool) -> Result<(), Error> {
+        Visit::on_timeout(self, factor, elapsed)
+    }
+
+    async fn finish(&mut self) -> Result<(), Error> {
+        Visit::finish(self)
+    }
+}
@@ -0,0 +1,456 @@
+//! A dynamic analysis that exports evaluations for visualization.
+//!
+//! Writes [Chrome Trace Event](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU)
+//! JSON, viewable in Perfetto or `about:tracing`, and folded stacks for
+//! flamegraph tools.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::mutable_self_async::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    runtime::{self, Env, Error, Value},
+};
+
+#[derive(Debug)]
+struct Event {
+    name: String,
+    cat: &'static str,
+    /// Chrome trace phase, e.g. `B` for begin and `E` for end.
+    ph: char,
+    ts: Duration,
+    /// Identifies the async span that `b` and `e` events belong to.
+    id: Option<u64>,
+}
+
+/// A node or function call that hasn't ended yet.
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    cat: &'static str,
+    /// Address of the AST node, or `None` for a function call.  Nodes are
+    /// matched by address since those cancelled by a timeout are never
+    /// post-visited.
+    node: Option<usize>,
+    start: Duration,
+    children: Duration,
+}
+
+#[derive(Debug)]
+pub struct Trace {
+    chrome_path: PathBuf,
+    folded_path: PathBuf,
+    epoch: Instant,
+    events: Vec<Event>,
+    stack: Vec<Frame>,
+    /// Exclusive nanoseconds per semicolon-separated stack.
+    folded: BTreeMap<String, u128>,
+    /// Ids of the async spans of the timeouts and yields being evaluated.
+    open_async: Vec<(usize, u64, String)>,
+    next_async_id: u64,
+}
+
+impl Trace {
+    /// Records a trace that's written to the given files when evaluation
+    /// finishes.
+    pub fn new(chrome_path: impl Into<PathBuf>, folded_path: impl Into<PathBuf>) -> Self {
+        Self {
+            chrome_path: chrome_path.into(),
+            folded_path: folded_path.into(),
+            epoch: Instant::now(),
+            events: Vec::new(),
+            stack: Vec::new(),
+            folded: BTreeMap::new(),
+            open_async: Vec::new(),
+            next_async_id: 0,
+        }
+    }
+
+    fn begin(&mut self, name: String, cat: &'static str, node: Option<usize>) {
+        let ts = self.epoch.elapsed();
+        self.events.push(Event {
+            name: name.clone(),
+            cat,
+            ph: 'B',
+            ts,
+            id: None,
+        });
+        self.stack.push(Frame {
+            name,
+            cat,
+            node,
+            start: ts,
+            children: Duration::ZERO,
+        });
+    }
+
+    fn end(&mut self) -> Option<Frame> {
+        let frame = self.stack.pop()?;
+        let ts = self.epoch.elapsed();
+        let elapsed = ts.saturating_sub(frame.start);
+        let mut key = String::new();
+        for outer in &self.stack {
+            key.push_str(&outer.name);
+            key.push(';');
+        }
+        key.push_str(&frame.name);
+        *self.folded.entry(key).or_default() += elapsed.saturating_sub(frame.children).as_nanos();
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+        self.events.push(Event {
+            name: frame.name.clone(),
+            cat: frame.cat,
+            ph: 'E',
+            ts,
+            id: None,
+        });
+        Some(frame)
+    }
+
+    /// Ends the frame of `node` and any frames above it, like calls that
+    /// failed and never returned.
+    fn end_node(&mut self, node: usize) {
+        if !self.stack.iter().any(|frame| frame.node == Some(node)) {
+            return;
+        }
+        while let Some(frame) = self.end() {
+            if frame.node == Some(node) {
+                break;
+            }
+        }
+    }
+
+    /// Starts an async span, which trace viewers show on its own track since
+    /// it may be suspended and resumed.
+    fn begin_async(&mut self, name: String, node: usize) {
+        let id = self.next_async_id;
+        self.next_async_id += 1;
+        self.open_async.push((node, id, name.clone()));
+        self.events.push(Event {
+            name,
+            cat: "async",
+            ph: 'b',
+            ts: self.epoch.elapsed(),
+            id: Some(id),
+        });
+    }
+
+    fn end_async(&mut self, node: usize) {
+        // Also end spans inside a timeout that elapsed.  They were cancelled, so
+        // they were never post-visited.
+        while let Some((open, id, name)) = self.open_async.pop() {
+            self.events.push(Event {
+                name,
+                cat: "async",
+                ph: 'e',
+                ts: self.epoch.elapsed(),
+                id: Some(id),
+            });
+            if open == node {
+                break;
+            }
+        }
+    }
+
+    pub fn write_chrome_trace(&self, mut w: impl Write) -> io::Result<()> {
+        let events = self
+            .events
+            .iter()
+            .map(|event| {
+                let mut json = json!({
+                    "name": event.name,
+                    "cat": event.cat,
+                    "ph": event.ph.to_string(),
+                    "ts": event.ts.as_secs_f64() * 1e6,
+                    "pid": 1,
+                    "tid": 1,
+                });
+                if let Some(id) = event.id {
+                    json["id"] = id.into();
+                }
+                json
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer(&mut w, &json!({ "traceEvents": events }))?;
+        w.flush()
+    }
+
+    pub fn write_folded(&self, mut w: impl Write) -> io::Result<()> {
+        for (stack, nanos) in &self.folded {
+            writeln!(w, "{stack} {nanos}")?;
+        }
+        w.flush()
+    }
+}
+
+fn address<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+impl runtime::Visit for Trace {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.begin(stmt.kind().to_owned(), "stmt", Some(address(stmt)));
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(stmt));
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.begin(expr.kind().to_owned(), "expr", Some(address(expr)));
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(expr));
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let span = match factor {
+            Factor::Timeout { milliseconds, .. } => Some(format!("timeout({milliseconds})")),
+            Factor::Yield(_) => Some("yield".to_owned()),
+            _ => None,
+        };
+        if let Some(name) = span {
+            self.begin_async(name, address(factor));
+        }
+        self.begin(factor.kind().to_owned(), "factor", Some(address(factor)));
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.end_node(address(factor));
+        if matches!(factor, Factor::Timeout { .. } | Factor::Yield(_)) {
+            self.end_async(address(factor));
+        }
+        result
+    }
+
+    fn on_call(&mut self, fun: &Identifier, _args: &[Value], _env: &Env) -> Result<(), Error> {
+        self.begin(fun.clone(), "call", None);
+        Ok(())
+    }
+
+    fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        if matches!(self.stack.last(), Some(frame) if frame.node.is_none()) {
+            self.end();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        while self.end().is_some() {}
+        self.write_chrome_trace(BufWriter::new(File::create(&self.chrome_path)?))?;
+        self.write_folded(BufWriter::new(File::create(&self.folded_path)?))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value as Json;
+
+    use super::*;
+    use crate::mutable_self_async::{ast::Function, evaluator::Evaluator};
+
+    /// Forwards to a trace that the test can still read after evaluation.
+    #[derive(Debug)]
+    struct Shared(Arc<Mutex<Trace>>);
+
+    impl runtime::Visit for Shared {
+        fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+            self.0.lock().unwrap().pre_visit_stmt(stmt)
+        }
+
+        fn post_visit_stmt(
+            &mut self,
+            stmt: &Stmt,
+            result: Result<Value, Error>,
+        ) -> Result<Value, Error> {
+            self.0.lock().unwrap().post_visit_stmt(stmt, result)
+        }
+
+        fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+            self.0.lock().unwrap().pre_visit_expr(expr)
+        }
+
+        fn post_visit_expr(
+            &mut self,
+            expr: &Expr,
+            result: Result<Value, Error>,
+        ) -> Result<Value, Error> {
+            self.0.lock().unwrap().post_visit_expr(expr, result)
+        }
+
+        fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+            self.0.lock().unwrap().pre_visit_factor(factor)
+        }
+
+        fn post_visit_factor(
+            &mut self,
+            factor: &Factor,
+            result: Result<Value, Error>,
+        ) -> Result<Value, Error> {
+            self.0.lock().unwrap().post_visit_factor(factor, result)
+        }
+
+        fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+            self.0.lock().unwrap().on_call(fun, args, env)
+        }
+
+        fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+            self.0.lock().unwrap().on_return(value)
+        }
+    }
+
+    fn factor(factor: Factor) -> Expr {
+        Expr::Factor(Box::new(factor))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        factor(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        ))
+    }
+
+    /// Traces a call of `get`, which awaits a `lookup` that never finishes,
+    /// inside a yield inside a timeout that elapses.
+    async fn trace_timeout() -> Trace {
+        let stmts = vec![
+            Stmt::Fun(
+                "get".to_owned(),
+                Arc::new(Function {
+                    params: vec!["key".to_owned()],
+                    body: call("lookup", vec![factor(Factor::Variable("key".to_owned()))]),
+                }),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(factor(Factor::Timeout {
+                    milliseconds: 10,
+                    expr: Box::new(factor(Factor::Yield(Box::new(call(
+                        "get",
+                        vec![factor(Factor::Literal(1.0))],
+                    ))))),
+                })),
+            ),
+        ];
+        let trace = Arc::new(Mutex::new(Trace::new("unused.json", "unused.folded")));
+        let mut evaluator = Evaluator::default();
+        evaluator.register_async_fn("lookup", 1, |args| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(args[0].clone())
+        });
+        evaluator
+            .visitors
+            .push(Box::new(Shared(Arc::clone(&trace))));
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+        assert_eq!(err.to_string(), "Timed out after 10 ms");
+        drop(evaluator);
+        Arc::into_inner(trace).unwrap().into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn chrome_trace_is_balanced_after_a_timeout() {
+        let trace = trace_timeout().await;
+        // Every frame ended without `finish` having to end it.
+        assert!(trace.stack.is_empty());
+        let mut json = Vec::new();
+        trace.write_chrome_trace(&mut json).unwrap();
+        let json: Json = serde_json::from_slice(&json).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+
+        let mut open = Vec::new();
+        let mut spans = HashMap::<u64, Vec<(String, String)>>::new();
+        let mut last_ts = 0.0;
+        for event in events {
+            let name = event["name"].as_str().unwrap().to_owned();
+            let ph = event["ph"].as_str().unwrap();
+            let ts = event["ts"].as_f64().unwrap();
+            assert!(ts >= last_ts, "{event} is out of order");
+            last_ts = ts;
+            match ph {
+                "B" => open.push(name),
+                "E" => assert_eq!(open.pop(), Some(name)),
+                "b" | "e" => {
+                    assert_eq!(event["cat"], "async");
+                    let id = event["id"].as_u64().unwrap();
+                    spans.entry(id).or_default().push((ph.to_owned(), name));
+                }
+                _ => panic!("unexpected phase in {event}"),
+            }
+        }
+        assert!(open.is_empty(), "never ended: {open:?}");
+        let mut spans = spans.into_iter().collect::<Vec<_>>();
+        spans.sort();
+        let span = |ph: &str, name: &str| (ph.to_owned(), name.to_owned());
+        assert_eq!(
+            spans,
+            [
+                (0, vec![span("b", "timeout(10)"), span("e", "timeout(10)")]),
+                (1, vec![span("b", "yield"), span("e", "yield")]),
+            ]
+        );
+        let names = events
+            .iter()
+            .filter(|event| event["ph"] == "B")
+            .map(|event| event["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"get") && names.contains(&"lookup"));
+    }
+
+    #[tokio::test]
+    async fn folded_stacks_nest_calls_in_nodes() {
+        let trace = trace_timeout().await;
+        let mut folded = Vec::new();
+        trace.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+
+        let mut total = 0;
+        let mut stacks = Vec::new();
+        for line in folded.lines() {
+            let (stack, nanos) = line.rsplit_once(' ').unwrap();
+            total += nanos.parse::<u128>().unwrap();
+            stacks.push(stack);
+        }
+        assert!(stacks.contains(&"Stmt::Fun"));
+        let lookup = stacks
+            .iter()
+            .find(|stack| stack.ends_with(";lookup"))
+            .unwrap();
+        assert!(lookup.starts_with("Stmt::Let;Expr::Factor;Factor::Timeout;"));
+        assert!(lookup.contains(";Factor::Yield;"));
+        assert!(lookup.contains(";get;"));
+        // Exclusive times add up to at least the timeout.
+        assert!(total >= Duration::from_millis(10).as_nanos());
+    }
+}
@@ -0,0 +1,51 @@
+//! The base protocol shared by the Language Server Protocol and the Debug
+//! Adapter Protocol: JSON messages, each framed by a `Content-Length` header.
+use serde_json::Value as Json;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Reads the body of a message framed by a `Content-Length` header, without
+/// parsing it.  Returns `None` at the end of the input.
+pub async fn read_body(input: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Vec<u8>>, Error> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or("Missing Content-Length header")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+/// Reads a message framed by a `Content-Length` header.  Returns `None` at the
+/// end of the input, and fails if the body isn't JSON.
+pub async fn read_message(input: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Json>, Error> {
+    match read_body(input).await? {
+        Some(body) => Ok(Some(serde_json::from_slice(&body)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn write_message(
+    output: &mut (impl AsyncWrite + Unpin),
+    message: &Json,
+) -> Result<(), Error> {
+    let body = serde_json::to_vec(message)?;
+    output
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    output.write_all(&body).await?;
+    output.flush().await?;
+    Ok(())
+}
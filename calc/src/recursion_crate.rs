@@ -0,0 +1,8 @@
+//! Synchronous evaluation with the [recursion](https://github.com/inanna-malick/recursion)
+//! crate, which folds each expression through its [`recursion::ExprFrame`]s
+//! instead of recursing by hand.  There are no visitors.
+pub mod ast;
+pub mod evaluator;
+pub mod prelude;
+pub mod recursion;
+pub mod runtime;
@@ -1,21 +1,21 @@
-pub(crate) type Identifier = String;
+pub type Identifier = String;
 
 #[derive(Debug, Clone)]
-pub(crate) enum Stmt {
+pub enum Stmt {
     Let(Identifier, Box<Expr>),
     Fun(Identifier, Box<Function>),
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct Function {
+pub struct Function {
     pub params: Params,
     pub body: Expr,
 }
 
-pub(crate) type Params = Vec<Identifier>;
+pub type Params = Vec<Identifier>;
 
 #[derive(Debug, Clone)]
-pub(crate) enum Expr {
+pub enum Expr {
     Factor(Box<Factor>),
     BinaryOp {
         op: TermBinaryOp,
@@ -25,19 +25,16 @@ pub(crate) enum Expr {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum TermBinaryOp {
+pub enum TermBinaryOp {
     Add,
-    #[allow(dead_code)]
     Sub,
 }
 
 #[derive(Debug, Clone)]
-pub(crate) enum Factor {
+pub enum Factor {
     Literal(f64),
     Variable(Identifier),
-    #[allow(dead_code)]
     Group(Box<Expr>),
-    #[allow(dead_code)]
     BinaryOp {
         op: FactorBinaryOp,
         lhs: Box<Factor>,
@@ -47,9 +44,7 @@ pub(crate) enum Factor {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum FactorBinaryOp {
-    #[allow(dead_code)]
+pub enum FactorBinaryOp {
     Mul,
-    #[allow(dead_code)]
     Div,
 }
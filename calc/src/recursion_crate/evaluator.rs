@@ -0,0 +1,385 @@
+use std::rc::Rc;
+
+use recursion::CollapsibleExt;
+
+use crate::recursion_crate::{
+    ast::{Expr, Factor, FactorBinaryOp, Identifier, Stmt, TermBinaryOp},
+    prelude,
+    recursion::{ExprFrame, FactorFrame},
+    runtime::{Env, Error, Frame, Native, StackOverflow, TracedError, Value},
+};
+
+pub use crate::common::DEFAULT_MAX_CALL_DEPTH;
+
+#[derive(Debug)]
+pub struct Evaluator {
+    /// Starts out with the built-ins from [`prelude`].
+    pub bindings: Env,
+    /// Calls nested deeper than this fail with a [`StackOverflow`] error.
+    pub max_call_depth: usize,
+    /// Calls made by one `eval_stmts()` beyond this many fail, so that
+    /// programs that take exponential time can be stopped.  There's no limit
+    /// by default.
+    pub max_calls: Option<usize>,
+    /// Calls made by the current `eval_stmts()`.
+    calls: usize,
+    /// Calls currently in progress, outermost first.
+    call_stack: Vec<Frame>,
+    /// Snapshot of `call_stack`, innermost first, taken where the current
+    /// error was raised.
+    error_backtrace: Option<Vec<Frame>>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            bindings: prelude::bindings(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_calls: None,
+            calls: 0,
+            call_stack: Vec::new(),
+            error_backtrace: None,
+        }
+    }
+}
+
+impl Evaluator {
+    /// Binds `name` to `fun`, a function implemented by the host that programs
+    /// call like any other, with `arity` arguments.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<Identifier>,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> Result<Value, Error> + 'static,
+    ) {
+        let name = name.into();
+        let native = Native {
+            name: name.clone(),
+            arity,
+            fun: Rc::new(fun),
+        };
+        self.bindings.insert(name, Value::Native(native));
+    }
+
+    pub fn eval_stmts(&mut self, stmts: &[Stmt]) -> Result<Value, Error> {
+        self.error_backtrace = None;
+        self.calls = 0;
+        let mut last = Value::Number(0.0);
+        for stmt in stmts {
+            last = self.eval_stmt(stmt).map_err(|err| self.traced(err))?;
+        }
+        Ok(last)
+    }
+
+    /// Attaches the call stack captured when `error` was raised.
+    fn traced(&mut self, error: Error) -> Error {
+        Box::new(TracedError {
+            error,
+            backtrace: self.error_backtrace.take().unwrap_or_default(),
+        })
+    }
+
+    pub fn eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        match stmt {
+            Stmt::Let(ident, expr) => {
+                let value = self.eval_expr(expr)?;
+                self.bindings.insert(ident.clone(), value.clone());
+                Ok(value)
+            }
+            Stmt::Fun(ident, function) => {
+                let closure = Value::Closure(
+                    ident.clone(),
+                    Box::new(function.as_ref().clone()),
+                    self.bindings.clone(),
+                );
+                self.bindings.insert(ident.to_owned(), closure.clone());
+                Ok(closure)
+            }
+        }
+    }
+
+    pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        expr.try_collapse_frames(|frame| match frame {
+            ExprFrame::Factor(f) => self.eval_factor(f.as_ref()),
+            ExprFrame::BinaryOp { op, lhs, rhs } => match op {
+                TermBinaryOp::Add => match (lhs, rhs) {
+                    (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+                    _ => Err("Addition only supported for numbers".into()),
+                },
+                TermBinaryOp::Sub => match (lhs, rhs) {
+                    (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+                    _ => Err("Subtraction only supported for numbers".into()),
+                },
+            },
+        })
+    }
+
+    fn eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        factor.try_collapse_frames(|frame| match frame {
+            FactorFrame::Literal(a) => Ok(Value::Number(a)),
+            FactorFrame::Variable(ident) => self
+                .bindings
+                .get(ident.as_str())
+                .cloned()
+                .ok_or_else(|| format!("Variable not found in bindings: {}", ident).into()),
+            FactorFrame::Group(e) => self.eval_expr(e.as_ref()),
+            FactorFrame::BinaryOp { op, lhs, rhs } => match op {
+                FactorBinaryOp::Mul => match (lhs, rhs) {
+                    (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+                    _ => Err("Multiplication only supported for numbers".into()),
+                },
+                FactorBinaryOp::Div => match (lhs, rhs) {
+                    (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+                    _ => Err("Division only supported for numbers".into()),
+                },
+            },
+            FactorFrame::Call(fun, args) => {
+                self.calls += 1;
+                if let Some(max_calls) = self.max_calls.filter(|max| self.calls > *max) {
+                    return Err(format!("More than {max_calls} calls").into());
+                }
+                let (name, fun, env) = match fun {
+                    Value::Closure(name, fun, env) => (name, fun, env),
+                    Value::Native(native) => return self.call_native(&native, &args),
+                    Value::Number(_) => return Err("Expected closure".into()),
+                };
+                let mut body_env = env;
+                let mut frame = Frame {
+                    name,
+                    args: Vec::with_capacity(args.len()),
+                };
+                for arg in args.iter().take(fun.params.len()) {
+                    frame.args.push(self.eval_expr(arg)?);
+                }
+                for (param, value) in fun.params.iter().zip(&frame.args) {
+                    body_env.insert(param.clone(), value.clone());
+                }
+                self.call_stack.push(frame);
+                if self.call_stack.len() > self.max_call_depth {
+                    let err = StackOverflow {
+                        max_depth: self.max_call_depth,
+                    };
+                    self.call_stack.pop();
+                    return Err(err.into());
+                }
+                let current_bindings = std::mem::replace(&mut self.bindings, body_env);
+                let result = self.eval_expr(&fun.body);
+                self.bindings = current_bindings;
+                if result.is_err() && self.error_backtrace.is_none() {
+                    self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+                }
+                self.call_stack.pop();
+                result
+            }
+        })
+    }
+
+    /// Calls a function implemented by the host with the values of `args`.
+    fn call_native(&mut self, native: &Native, args: &[Expr]) -> Result<Value, Error> {
+        if args.len() != native.arity {
+            return Err(format!(
+                "Number of function parameters differs from arguments: expected {}, found {}",
+                native.arity,
+                args.len()
+            )
+            .into());
+        }
+        let frame = Frame {
+            name: native.name.clone(),
+            args: args
+                .iter()
+                .map(|arg| self.eval_expr(arg))
+                .collect::<Result<_, _>>()?,
+        };
+        self.call_stack.push(frame);
+        let frame = self.call_stack.last().expect("pushed above");
+        let result = (native.fun)(&frame.args);
+        if result.is_err() && self.error_backtrace.is_none() {
+            self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+        }
+        self.call_stack.pop();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recursion_crate::ast::Function;
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        Expr::Factor(Box::new(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        )))
+    }
+
+    fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Box::new(Function {
+                params: params.iter().map(|p| p.to_string()).collect(),
+                body,
+            }),
+        )
+    }
+
+    #[test]
+    fn prelude_functions_and_constants() {
+        let stmts = vec![
+            Stmt::Let(
+                "r".to_owned(),
+                Box::new(call("sqrt", vec![call("pow", vec![num(3.0), num(2.0)])])),
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Box::new(call("max", vec![var("r"), var("pi")])),
+            ),
+            // Built-ins can be shadowed like any other binding.
+            fun("abs", &["a"], var("e")),
+            Stmt::Let("y".to_owned(), Box::new(call("abs", vec![num(-1.0)]))),
+        ];
+        let mut evaluator = Evaluator::default();
+        evaluator.eval_stmts(&stmts).unwrap();
+
+        let number = |name: &str| match evaluator.bindings[name] {
+            Value::Number(x) => x,
+            _ => panic!("{name} isn't a number"),
+        };
+        assert_eq!(number("r"), 3.0);
+        assert_eq!(number("x"), std::f64::consts::PI);
+        assert_eq!(number("y"), std::f64::consts::E);
+
+        let stmts = vec![Stmt::Let(
+            "z".to_owned(),
+            Box::new(call("floor", vec![var("floor")])),
+        )];
+        let err = evaluator.eval_stmts(&stmts).unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert_eq!(traced.error.to_string(), "floor only supported for numbers");
+    }
+
+    /// `f` defined `depth` times, each calling the one before, and a call of
+    /// the last, so that calls nest exactly `depth` deep.
+    fn nested_calls(depth: usize) -> Vec<Stmt> {
+        let mut stmts = vec![fun("f", &["x"], var("x"))];
+        for _ in 1..depth {
+            stmts.push(fun("f", &["x"], call("f", vec![var("x")])));
+        }
+        stmts.push(Stmt::Let(
+            "y".to_owned(),
+            Box::new(call("f", vec![num(1.0)])),
+        ));
+        stmts
+    }
+
+    #[test]
+    fn calls_nest_up_to_the_limit() {
+        let mut evaluator = Evaluator::default();
+        let value = evaluator.eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH));
+        assert!(matches!(value, Ok(Value::Number(y)) if y == 1.0));
+
+        let mut evaluator = Evaluator::default();
+        let err = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH + 1))
+            .unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    #[test]
+    fn stack_overflow_trace() {
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let("x".to_owned(), Box::new(call("omega", vec![var("omega")]))),
+        ];
+        let mut evaluator = Evaluator {
+            max_call_depth: 2,
+            ..Evaluator::default()
+        };
+        let err = evaluator.eval_stmts(&stmts).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Stack overflow: maximum call depth of 2 exceeded\n\
+             Stack trace:\n  \
+             at omega(<fun omega>)\n  \
+             at omega(<fun omega>)"
+        );
+        // Nothing is left of the calls that overflowed.
+        assert!(evaluator.call_stack.is_empty());
+        assert!(!evaluator.bindings.contains_key("g"));
+    }
+
+    #[test]
+    fn error_trace() {
+        let stmts = vec![
+            fun(
+                "half",
+                &["x"],
+                Expr::Factor(Box::new(Factor::BinaryOp {
+                    op: FactorBinaryOp::Div,
+                    lhs: Box::new(Factor::Variable("x".to_owned())),
+                    rhs: Box::new(Factor::Variable("half".to_owned())),
+                })),
+            ),
+            fun("twice", &["y"], call("half", vec![var("y")])),
+            Stmt::Let("z".to_owned(), Box::new(call("twice", vec![num(4.0)]))),
+        ];
+        let mut evaluator = Evaluator::default();
+        let err = evaluator.eval_stmts(&stmts).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Variable not found in bindings: half\n\
+             Stack trace:\n  \
+             at half(4)\n  \
+             at twice(4)"
+        );
+    }
+
+    /// `f` defined `depth` times, each calling the one before twice, and a
+    /// call of the last, which makes 2^depth - 1 calls.
+    fn doubling_calls(depth: usize) -> Vec<Stmt> {
+        let twice = Expr::BinaryOp {
+            op: TermBinaryOp::Add,
+            lhs: Box::new(call("f", vec![var("x")])),
+            rhs: Box::new(call("f", vec![var("x")])),
+        };
+        let mut stmts = vec![fun("f", &["x"], var("x"))];
+        for _ in 1..depth {
+            stmts.push(fun("f", &["x"], twice.clone()));
+        }
+        stmts.push(Stmt::Let(
+            "y".to_owned(),
+            Box::new(call("f", vec![num(1.0)])),
+        ));
+        stmts
+    }
+
+    #[test]
+    fn calls_can_be_limited() {
+        let mut evaluator = Evaluator {
+            max_calls: Some(1000),
+            ..Evaluator::default()
+        };
+        // The count starts over with each evaluation.
+        for _ in 0..2 {
+            let value = evaluator.eval_stmts(&doubling_calls(9));
+            assert!(matches!(value, Ok(Value::Number(y)) if y == 256.0));
+        }
+
+        let err = evaluator.eval_stmts(&doubling_calls(10)).unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert_eq!(traced.error.to_string(), "More than 1000 calls");
+    }
+}
@@ -0,0 +1,29 @@
+//! The math functions and constants of the [common prelude](crate::common::prelude)
+//! as values, bound in every new
+//! [`Evaluator`](crate::recursion_crate::evaluator::Evaluator).
+use std::rc::Rc;
+
+use crate::{
+    common::prelude::{self, Call},
+    recursion_crate::runtime::{Env, Native, Value},
+};
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn native(name: &'static str, arity: usize, call: Call) -> Value {
+    Value::Native(Native {
+        name: name.to_owned(),
+        arity,
+        fun: Rc::new(move |args| Ok(Value::Number(call(&prelude::numbers(name, args, number)?)))),
+    })
+}
+
+/// The built-in functions and constants, by name.
+pub fn bindings() -> Env {
+    prelude::bindings(native, Value::Number)
+}
@@ -1,8 +1,8 @@
 use recursion::{Collapsible, MappableFrame, PartiallyApplied};
 
-use crate::ast::{Expr, Factor, FactorBinaryOp, Identifier, TermBinaryOp};
+use crate::recursion_crate::ast::{Expr, Factor, FactorBinaryOp, Identifier, TermBinaryOp};
 
-pub(crate) enum ExprFrame<A> {
+pub enum ExprFrame<A> {
     Factor(Box<Factor>),
     BinaryOp { op: TermBinaryOp, lhs: A, rhs: A },
 }
@@ -22,7 +22,7 @@ impl MappableFrame for ExprFrame<PartiallyApplied> {
     }
 }
 
-impl<'a> Collapsible for &'a Expr {
+impl Collapsible for &Expr {
     type FrameToken = ExprFrame<PartiallyApplied>;
 
     fn into_frame(self) -> <Self::FrameToken as MappableFrame>::Frame<Self> {
@@ -33,7 +33,7 @@ impl<'a> Collapsible for &'a Expr {
     }
 }
 
-pub(crate) enum FactorFrame<A> {
+pub enum FactorFrame<A> {
     Literal(f64),
     Variable(Identifier),
     Group(Box<Expr>),
@@ -59,7 +59,7 @@ impl MappableFrame for FactorFrame<PartiallyApplied> {
     }
 }
 
-impl<'a> Collapsible for &'a Factor {
+impl Collapsible for &Factor {
     type FrameToken = FactorFrame<PartiallyApplied>;
 
     fn into_frame(self) -> <Self::FrameToken as MappableFrame>::Frame<Self> {
@@ -0,0 +1,95 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use crate::{
+    common::prelude,
+    recursion_crate::ast::{Function, Identifier},
+};
+
+pub type Error = Box<dyn std::error::Error>;
+
+pub type Env = HashMap<Identifier, Value>;
+
+#[derive(Clone)]
+pub enum Value {
+    Number(f64),
+    /// A function, the name it was defined with, and its captured environment.
+    Closure(Identifier, Box<Function>, Env),
+    /// A function implemented by the host.
+    Native(Native),
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(x) => f.debug_tuple("Number").field(x).finish(),
+            Value::Closure(name, fun, env) => {
+                // Sorted, so that the output is the same from run to run, and
+                // without the prelude that every closure captures.
+                let env = env
+                    .iter()
+                    .filter(|(name, value)| !value.is_prelude(name))
+                    .collect::<BTreeMap<_, _>>();
+                f.debug_tuple("Closure")
+                    .field(name)
+                    .field(fun)
+                    .field(&env)
+                    .finish()
+            }
+            Value::Native(native) => f.debug_tuple("Native").field(native).finish(),
+        }
+    }
+}
+
+impl Value {
+    /// Whether this is the [`prelude`]'s binding of `name`, as in a new
+    /// `Evaluator`.
+    pub fn is_prelude(&self, name: &str) -> bool {
+        match self {
+            Value::Number(x) => prelude::is_constant(name, *x),
+            Value::Native(native) => native.name == name && prelude::is_function(name),
+            Value::Closure(..) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(x) => write!(f, "{x}"),
+            Value::Closure(name, _, _) => write!(f, "<fun {name}>"),
+            Value::Native(native) => write!(f, "<native fun {}>", native.name),
+        }
+    }
+}
+
+/// The type of the functions the host implements.
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, Error>;
+
+/// A function implemented by the host, bound with
+/// [`Evaluator::register_fn`](crate::recursion_crate::evaluator::Evaluator::register_fn).
+#[derive(Clone)]
+pub struct Native {
+    pub name: Identifier,
+    pub arity: usize,
+    pub fun: Rc<NativeFn>,
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+pub use crate::common::StackOverflow;
+
+/// A function call that's in progress.
+pub type Frame = crate::common::Frame<Value>;
+
+/// An error that escaped evaluation, with the calls that led to it.
+pub type TracedError = crate::common::TracedError<Error, Value>;
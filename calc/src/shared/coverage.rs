@@ -0,0 +1,234 @@
+//! A dynamic analysis that measures which parts of a program were evaluated.
+//!
+//! Prints the unparsed program annotated with hit counts and writes an
+//! [LCOV](https://github.com/linux-test-project/lcov) tracefile against it,
+//! so coverage tools can highlight lines and functions that never ran.
+use std::{fmt::Write, fs, path::PathBuf};
+
+use super::{
+    ast::{Child, Expr, Factor, Identifier, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, Error, Value},
+    unparser::unparse,
+};
+
+/// A node of the program, indexed by its id.
+#[derive(Debug)]
+struct Node {
+    line: usize,
+    /// Id of the enclosing node, or `None` for a statement.
+    parent: Option<usize>,
+    source: String,
+}
+
+#[derive(Debug)]
+struct FunctionDef {
+    name: Identifier,
+    line: usize,
+    /// Id of the body, which is visited once per call.
+    body: usize,
+}
+
+/// Collects the nodes of a program in id order.
+#[derive(Debug)]
+struct Collect<'a> {
+    ids: &'a NodeIds,
+    nodes: Vec<Node>,
+    functions: Vec<FunctionDef>,
+}
+
+impl Collect<'_> {
+    fn add(&mut self, location: Option<Location>, parent: Option<usize>, source: String) -> usize {
+        let location = location.expect("every node has an id");
+        // Shared nodes are collected where they first appear.
+        if location.id == self.nodes.len() {
+            self.nodes.push(Node {
+                line: location.line,
+                parent,
+                source,
+            });
+        }
+        location.id
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt) {
+        let source = unparse(|unparser| unparser.unparse_stmt(stmt));
+        let id = self.add(self.ids.stmt(stmt), None, source);
+        if let Stmt::Fun(name, fun) = stmt {
+            let body = self.ids.expr(&fun.body).expect("every node has an id");
+            if !self.functions.iter().any(|def| def.body == body.id) {
+                self.functions.push(FunctionDef {
+                    name: name.clone(),
+                    line: body.line,
+                    body: body.id,
+                });
+            }
+        }
+        self.add_expr(stmt.expr(), id);
+    }
+
+    fn add_expr(&mut self, expr: &Expr, parent: usize) {
+        let source = unparse(|unparser| unparser.unparse_expr(expr));
+        let id = self.add(self.ids.expr(expr), Some(parent), source);
+        match expr {
+            Expr::Factor(factor) => self.add_factor(factor, id),
+            Expr::BinaryOp { op: _, lhs, rhs } => {
+                self.add_expr(lhs, id);
+                self.add_expr(rhs, id);
+            }
+        }
+    }
+
+    fn add_factor(&mut self, factor: &Factor, parent: usize) {
+        let source = unparse(|unparser| unparser.unparse_factor(factor));
+        let id = self.add(self.ids.factor(factor), Some(parent), source);
+        for child in factor.children() {
+            match child {
+                Child::Expr(expr) => self.add_expr(expr, id),
+                Child::Factor(factor) => self.add_factor(factor, id),
+            }
+        }
+    }
+}
+
+/// Counts how many times each node is visited.  When evaluation finishes,
+/// prints an annotated listing, and writes the unparsed program and an LCOV
+/// tracefile for it.
+#[derive(Debug)]
+pub struct Coverage {
+    source_path: PathBuf,
+    lcov_path: PathBuf,
+    ids: NodeIds,
+    nodes: Vec<Node>,
+    functions: Vec<FunctionDef>,
+    /// Visits per node, indexed by id.
+    pub hits: Vec<usize>,
+}
+
+impl Coverage {
+    /// Measures the coverage of `stmts`, which must be the statements that
+    /// are evaluated rather than a copy.
+    pub fn new(
+        stmts: &[Stmt],
+        source_path: impl Into<PathBuf>,
+        lcov_path: impl Into<PathBuf>,
+    ) -> Self {
+        let ids = NodeIds::new(stmts);
+        let mut collect = Collect {
+            ids: &ids,
+            nodes: Vec::new(),
+            functions: Vec::new(),
+        };
+        for stmt in stmts {
+            collect.add_stmt(stmt);
+        }
+        let Collect {
+            nodes, functions, ..
+        } = collect;
+        Self {
+            source_path: source_path.into(),
+            lcov_path: lcov_path.into(),
+            hits: vec![0; nodes.len()],
+            ids,
+            nodes,
+            functions,
+        }
+    }
+
+    fn hit(&mut self, location: Option<Location>) {
+        if let Some(location) = location {
+            self.hits[location.id] += 1;
+        }
+    }
+
+    /// The unparsed program, one statement per line.
+    pub fn source(&self) -> String {
+        let mut out = String::new();
+        for node in self.nodes.iter().filter(|node| node.parent.is_none()) {
+            out.push_str(&node.source);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Visits of the most visited node on each line, indexed by line - 1.
+    fn line_hits(&self) -> Vec<usize> {
+        let mut lines = Vec::new();
+        for (node, &hits) in self.nodes.iter().zip(&self.hits) {
+            if node.line > lines.len() {
+                lines.resize(node.line, 0);
+            }
+            lines[node.line - 1] = lines[node.line - 1].max(hits);
+        }
+        lines
+    }
+
+    /// Each statement prefixed by the number of times it was evaluated.  Calls
+    /// are counted for every function, and under each line are the outermost
+    /// nodes that were never evaluated.
+    pub fn listing(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let hits = self.hits[id];
+            let Some(parent) = node.parent else {
+                writeln!(out, "{hits:>6} | {}", node.source)?;
+                continue;
+            };
+            if let Some(fun) = self.functions.iter().find(|fun| fun.body == id) {
+                let marker = if hits == 0 { "!" } else { " " };
+                writeln!(out, "{marker:>6} |   `{}`: {hits} calls", fun.name)?;
+            } else if hits == 0 && self.hits[parent] > 0 {
+                writeln!(out, "{:>6} |   never evaluated: {}", "!", node.source)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// An LCOV tracefile for the program written by [`Self::source`].
+    pub fn lcov(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", self.source_path.display())?;
+        for fun in &self.functions {
+            writeln!(out, "FN:{},{}", fun.line, fun.name)?;
+        }
+        for fun in &self.functions {
+            writeln!(out, "FNDA:{},{}", self.hits[fun.body], fun.name)?;
+        }
+        let called = self.functions.iter().filter(|fun| self.hits[fun.body] > 0);
+        writeln!(out, "FNF:{}", self.functions.len())?;
+        writeln!(out, "FNH:{}", called.count())?;
+        let lines = self.line_hits();
+        for (i, hits) in lines.iter().enumerate() {
+            writeln!(out, "DA:{},{hits}", i + 1)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.iter().filter(|&&hits| hits > 0).count())?;
+        writeln!(out, "end_of_record")?;
+        Ok(out)
+    }
+}
+
+impl runtime::Visit for Coverage {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.stmt(stmt));
+        Ok(None)
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.expr(expr));
+        Ok(None)
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        self.hit(self.ids.factor(factor));
+        Ok(None)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        print!("{}", self.listing()?);
+        fs::write(&self.source_path, self.source())?;
+        fs::write(&self.lcov_path, self.lcov()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,451 @@
+//! A step debugger driven by the visitor hooks.
+//!
+//! Evaluation pauses in the pre-visit of a node when a breakpoint matches or
+//! a step finishes.  A pause sends a snapshot to a front end and awaits its
+//! next command on a channel, so other tasks keep running meanwhile.  In
+//! `mutable_self_async`, a pause inside a `timeout` counts towards it like any
+//! other wait.
+use std::{io::Write, str::FromStr};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc::{self, error::TryRecvError},
+};
+
+use super::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{AsyncVisit, Env, Error, Value},
+    unparser::unparse,
+};
+
+/// Where evaluation pauses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The body of any call to a function defined with this name.
+    Function(Identifier),
+    /// The first node evaluated on this line of the unparsed program.
+    Line(usize),
+    /// The node with this id.
+    Node(usize),
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["fun", name] => Ok(Breakpoint::Function(name.to_owned())),
+            ["line", line] => line
+                .parse()
+                .map(Breakpoint::Line)
+                .map_err(|_| format!("Invalid line: {line}")),
+            ["node", id] => id
+                .parse()
+                .map(Breakpoint::Node)
+                .map_err(|_| format!("Invalid node id: {id}")),
+            _ => Err("Expected `fun NAME`, `line N` or `node N`".to_owned()),
+        }
+    }
+}
+
+/// Sent by a front end to control evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    StepInto,
+    StepOver,
+    StepOut,
+    Continue,
+    /// Replaces every breakpoint.  Takes effect even while running.
+    SetBreakpoints(Vec<Breakpoint>),
+    /// Fails evaluation with an error.
+    Quit,
+}
+
+/// The bindings of a call in progress, or of the top level.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub name: Identifier,
+    pub env: Env,
+    /// Line the call was made on, or 0 for the top level.
+    pub call_line: usize,
+}
+
+/// Sent to the front end whenever evaluation pauses.
+#[derive(Debug, Clone)]
+pub struct Paused {
+    pub location: Location,
+    pub kind: &'static str,
+    pub source: String,
+    /// The top level first, then each call in progress.
+    pub scopes: Vec<Scope>,
+}
+
+/// The front end's ends of the debugger's channels.
+#[derive(Debug)]
+pub struct Controller {
+    pub paused: mpsc::UnboundedReceiver<Paused>,
+    pub commands: mpsc::UnboundedSender<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    /// Pause at the next node.
+    StepInto,
+    /// Pause at the next node nested in no more than this many others.
+    StepOver(usize),
+    /// Pause at the next node when fewer than this many scopes are open.
+    StepOut(usize),
+}
+
+/// A node that has been pre-visited but not post-visited.
+#[derive(Debug)]
+struct Open {
+    /// Address of the node.
+    node: usize,
+    /// Number of scopes when the node was entered.
+    scopes: usize,
+}
+
+/// Pauses evaluation at breakpoints and steps through it.
+///
+/// Bindings start out as those given to `new()`, and are then mirrored from
+/// `on_bind()` and `on_call()`, so they match `Evaluator::bindings` when
+/// given the evaluator's bindings.
+#[derive(Debug)]
+pub struct Debugger {
+    paused: mpsc::UnboundedSender<Paused>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    ids: NodeIds,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    open: Vec<Open>,
+    /// The top level first, then each call in progress.
+    scopes: Vec<Scope>,
+    /// A function that was just called and whose body hasn't been entered.
+    called: Option<Identifier>,
+    /// Line of the last node pre-visited.
+    line: usize,
+}
+
+impl Debugger {
+    /// Debugs the evaluation of `stmts` from `bindings`, which pauses before
+    /// the first statement.
+    pub fn new(stmts: &[Stmt], bindings: &Env) -> (Self, Controller) {
+        let (paused_tx, paused_rx) = mpsc::unbounded_channel();
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        let debugger = Self {
+            paused: paused_tx,
+            commands: commands_rx,
+            ids: NodeIds::new(stmts),
+            breakpoints: Vec::new(),
+            mode: Mode::StepInto,
+            open: Vec::new(),
+            scopes: vec![Scope {
+                name: "<top level>".to_owned(),
+                env: bindings.clone(),
+                call_line: 0,
+            }],
+            called: None,
+            line: 0,
+        };
+        let controller = Controller {
+            paused: paused_rx,
+            commands: commands_tx,
+        };
+        (debugger, controller)
+    }
+
+    fn should_pause(&self, location: Location) -> bool {
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(depth) => self.open.len() <= depth,
+            Mode::StepOut(scopes) => self.scopes.len() < scopes,
+        };
+        stepped
+            || self.breakpoints.iter().any(|breakpoint| match breakpoint {
+                Breakpoint::Function(name) => self.called.as_ref() == Some(name),
+                Breakpoint::Line(line) => *line == location.line && self.line != location.line,
+                Breakpoint::Node(id) => *id == location.id,
+            })
+    }
+
+    /// Applies a command.  Returns whether it resumes evaluation.
+    fn apply(&mut self, command: Command) -> Result<bool, Error> {
+        self.mode = match command {
+            Command::StepInto => Mode::StepInto,
+            Command::StepOver => Mode::StepOver(self.open.len()),
+            Command::StepOut => Mode::StepOut(self.scopes.len()),
+            Command::Continue => Mode::Continue,
+            Command::SetBreakpoints(breakpoints) => {
+                self.breakpoints = breakpoints;
+                return Ok(false);
+            }
+            Command::Quit => return Err("Quit debugging".into()),
+        };
+        Ok(true)
+    }
+
+    async fn enter(
+        &mut self,
+        node: usize,
+        location: Option<Location>,
+        kind: &'static str,
+        source: impl FnOnce() -> String,
+    ) -> Result<(), Error> {
+        // Catch up on commands sent while running.
+        loop {
+            match self.commands.try_recv() {
+                Ok(command) => {
+                    self.apply(command)?;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.mode = Mode::Continue;
+                    break;
+                }
+            }
+        }
+        if let Some(location) = location {
+            if self.should_pause(location) {
+                self.pause(Paused {
+                    location,
+                    kind,
+                    source: source(),
+                    scopes: self.scopes.clone(),
+                })
+                .await?;
+            }
+            self.line = location.line;
+        }
+        self.called = None;
+        self.open.push(Open {
+            node,
+            scopes: self.scopes.len(),
+        });
+        Ok(())
+    }
+
+    /// Closes `node`, along with any nodes inside it that weren't closed,
+    /// such as those a timeout cancelled.
+    fn exit(&mut self, node: usize) {
+        while let Some(open) = self.open.pop() {
+            self.scopes.truncate(open.scopes);
+            if open.node == node {
+                break;
+            }
+        }
+    }
+
+    /// Waits for commands until one resumes evaluation.
+    async fn pause(&mut self, paused: Paused) -> Result<(), Error> {
+        if self.paused.send(paused).is_err() {
+            // Nobody is left to send commands.
+            self.mode = Mode::Continue;
+            return Ok(());
+        }
+        loop {
+            let Some(command) = self.commands.recv().await else {
+                self.mode = Mode::Continue;
+                return Ok(());
+            };
+            if self.apply(command)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncVisit for Debugger {
+    async fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        let location = self.ids.stmt(stmt);
+        self.enter(stmt as *const Stmt as usize, location, stmt.kind(), || {
+            unparse(|unparser| unparser.unparse_stmt(stmt))
+        })
+        .await?;
+        Ok(None)
+    }
+
+    async fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(stmt as *const Stmt as usize);
+        result
+    }
+
+    async fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let location = self.ids.expr(expr);
+        self.enter(expr as *const Expr as usize, location, expr.kind(), || {
+            unparse(|unparser| unparser.unparse_expr(expr))
+        })
+        .await?;
+        Ok(None)
+    }
+
+    async fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(expr as *const Expr as usize);
+        result
+    }
+
+    async fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let location = self.ids.factor(factor);
+        let kind = factor.kind();
+        self.enter(factor as *const Factor as usize, location, kind, || {
+            unparse(|unparser| unparser.unparse_factor(factor))
+        })
+        .await?;
+        Ok(None)
+    }
+
+    async fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit(factor as *const Factor as usize);
+        result
+    }
+
+    async fn on_call(&mut self, fun: &Identifier, _args: &[Value], env: &Env) -> Result<(), Error> {
+        self.scopes.push(Scope {
+            name: fun.clone(),
+            env: env.clone(),
+            call_line: self.line,
+        });
+        self.called = Some(fun.clone());
+        Ok(())
+    }
+
+    async fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("the top level is never closed");
+        scope.env.insert(name.clone(), value.clone());
+        Ok(())
+    }
+}
+
+const HELP: &str = "\
+step, s              evaluate until the next node
+next, n              evaluate until the next node that isn't nested in this one
+out, o               evaluate until the current function returns
+continue, c          evaluate until a breakpoint
+print, p NAME        show the value bound to NAME
+vars, v              show every binding in scope
+backtrace, bt        show the calls in progress
+break, b fun NAME    pause when NAME is called
+break, b line N      pause on line N
+break, b node N      pause on the node with id N
+clear                remove every breakpoint
+quit, q              stop evaluating
+";
+
+/// A line typed into [`repl()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Input {
+    Command(Command),
+    Print(Identifier),
+    Vars,
+    Backtrace,
+    Break(Breakpoint),
+    Clear,
+    Help,
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (word, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        match (word, rest) {
+            ("step" | "s", "") => Ok(Input::Command(Command::StepInto)),
+            ("next" | "n", "") => Ok(Input::Command(Command::StepOver)),
+            ("out" | "o", "") => Ok(Input::Command(Command::StepOut)),
+            ("continue" | "c", "") => Ok(Input::Command(Command::Continue)),
+            ("print" | "p", name) if !name.is_empty() => Ok(Input::Print(name.to_owned())),
+            ("vars" | "v", "") => Ok(Input::Vars),
+            ("backtrace" | "bt", "") => Ok(Input::Backtrace),
+            ("break" | "b", breakpoint) => breakpoint.parse().map(Input::Break),
+            ("clear", "") => Ok(Input::Clear),
+            ("quit" | "q", "") => Ok(Input::Command(Command::Quit)),
+            ("help" | "h", "") => Ok(Input::Help),
+            _ => Err(format!("Unknown command: {s}.  Type `help` for a list.")),
+        }
+    }
+}
+
+/// A command-line front end reading from stdin.  Returns when evaluation
+/// finishes or stdin is closed.
+pub async fn repl(mut controller: Controller) -> Result<(), Error> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut breakpoints = Vec::new();
+    while let Some(paused) = controller.paused.recv().await {
+        println!(
+            "Paused at line {} node {} ({}): {}",
+            paused.location.line, paused.location.id, paused.kind, paused.source
+        );
+        let scope = paused.scopes.last().expect("the top level is never closed");
+        loop {
+            print!("(debug) ");
+            std::io::stdout().flush()?;
+            let Some(line) = lines.next_line().await? else {
+                return Ok(());
+            };
+            let input = match line.parse() {
+                Ok(input) => input,
+                Err(message) => {
+                    println!("{message}");
+                    continue;
+                }
+            };
+            match input {
+                Input::Command(command) => {
+                    controller.commands.send(command)?;
+                    break;
+                }
+                Input::Print(name) => match scope.env.get(&name) {
+                    Some(value) => println!("{name} = {value}"),
+                    None => println!("{name} is not bound"),
+                },
+                Input::Vars => {
+                    let mut bindings = scope.env.iter().collect::<Vec<_>>();
+                    bindings.sort_by_key(|(name, _)| *name);
+                    for (name, value) in bindings {
+                        println!("{name} = {value}");
+                    }
+                }
+                Input::Backtrace => {
+                    for scope in paused.scopes.iter().rev() {
+                        println!("  in {}", scope.name);
+                    }
+                }
+                Input::Break(breakpoint) => {
+                    breakpoints.push(breakpoint);
+                    let command = Command::SetBreakpoints(breakpoints.clone());
+                    controller.commands.send(command)?;
+                }
+                Input::Clear => {
+                    breakpoints.clear();
+                    controller
+                        .commands
+                        .send(Command::SetBreakpoints(Vec::new()))?;
+                }
+                Input::Help => print!("{HELP}"),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Examples of dynamic analyses.
+use std::io::Write;
+
+use serde_json::json;
+
+use super::{
+    ast::{Expr, Factor, Stmt},
+    node_id::{Location, NodeIds},
+    runtime::{self, Error, Value},
+    unparser::unparse,
+};
+
+/// Prints each node entered and left, indented by nesting, to stdout unless
+/// given another writer.
+pub struct Print {
+    pub indent: usize,
+    out: Box<dyn Write + Send>,
+    open: Open,
+}
+
+const INDENT_WIDTH: usize = 2;
+
+/// Addresses of the nodes entered and not yet left.
+///
+/// Nodes are matched by address, since a node inside a timeout that elapses
+/// is cancelled and never left.  Leaving the timeout leaves it too.
+#[derive(Debug, Default)]
+struct Open(Vec<usize>);
+
+impl Open {
+    /// Enters `node`, and returns its depth.
+    fn enter<T>(&mut self, node: &T) -> usize {
+        self.0.push(address(node));
+        self.0.len() - 1
+    }
+
+    /// Leaves `node` and any nodes still open inside it, and returns its depth.
+    fn leave<T>(&mut self, node: &T) -> usize {
+        let node = address(node);
+        if let Some(depth) = self.0.iter().rposition(|&open| open == node) {
+            self.0.truncate(depth);
+        }
+        self.0.len()
+    }
+}
+
+fn address<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+impl Print {
+    /// Prints to `out` instead of stdout.
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            indent: 0,
+            out: Box::new(out),
+            open: Open::default(),
+        }
+    }
+}
+
+impl Default for Print {
+    fn default() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+impl std::fmt::Debug for Print {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Print")
+            .field("indent", &self.indent)
+            .finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for Print {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        // Entered even if writing fails, since the post-visit still runs.
+        let indent = self.open.enter(stmt) * INDENT_WIDTH;
+        self.indent = self.open.0.len();
+        writeln!(
+            self.out,
+            "{:indent$}Eval stmt={stmt:?}",
+            "",
+            indent = indent,
+            stmt = stmt
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent = self.open.leave(stmt);
+        writeln!(
+            self.out,
+            "{:indent$}Eval stmt={stmt:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            stmt = stmt,
+            result = &result
+        )?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let indent = self.open.enter(expr) * INDENT_WIDTH;
+        self.indent = self.open.0.len();
+        writeln!(
+            self.out,
+            "{:indent$}Eval expr={expr:?}",
+            "",
+            indent = indent,
+            expr = expr
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent = self.open.leave(expr);
+        writeln!(
+            self.out,
+            "{:indent$}Eval expr={expr:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            expr = expr,
+            result = &result
+        )?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let indent = self.open.enter(factor) * INDENT_WIDTH;
+        self.indent = self.open.0.len();
+        writeln!(
+            self.out,
+            "{:indent$}Eval factor={factor:?}",
+            "",
+            indent = indent,
+            factor = factor
+        )?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.indent = self.open.leave(factor);
+        writeln!(
+            self.out,
+            "{:indent$}Eval factor={factor:?} result={result:?}",
+            "",
+            indent = self.indent * INDENT_WIDTH,
+            factor = factor,
+            result = &result
+        )?;
+        result
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Logs one JSON object per line for each node entered and left, for tools
+/// to consume instead of [`Print`]'s output.
+///
+/// Each event has the `phase` (`pre` or `post`), the node `kind`, its `id`
+/// and `line` (see [`NodeIds`]), the `depth` of nesting, and the unparsed
+/// `source` of the node.  `post` events also have the resulting `value` or
+/// `error`.
+pub struct JsonLines {
+    out: Box<dyn Write + Send>,
+    ids: NodeIds,
+    open: Open,
+}
+
+impl JsonLines {
+    /// Logs the evaluation of `stmts` to `out`.
+    pub fn new(out: impl Write + Send + 'static, stmts: &[Stmt]) -> Self {
+        Self {
+            out: Box::new(out),
+            ids: NodeIds::new(stmts),
+            open: Open::default(),
+        }
+    }
+
+    fn emit(
+        &mut self,
+        phase: &str,
+        depth: usize,
+        kind: &str,
+        location: Option<Location>,
+        source: String,
+        result: Option<&Result<Value, Error>>,
+    ) -> Result<(), Error> {
+        let mut event = json!({
+            "phase": phase,
+            "kind": kind,
+            "id": location.map(|location| location.id),
+            "line": location.map(|location| location.line),
+            "depth": depth,
+            "source": source,
+        });
+        match result {
+            Some(Ok(Value::Number(n))) => event["value"] = json!(n),
+            Some(Ok(value)) => event["value"] = json!(value.to_string()),
+            Some(Err(error)) => event["error"] = json!(error.to_string()),
+            None => {}
+        }
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for JsonLines {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonLines")
+            .field("depth", &self.open.0.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl runtime::Visit for JsonLines {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_stmt(stmt));
+        // Entered even if writing fails, since the post-visit still runs.
+        let depth = self.open.enter(stmt);
+        let location = self.ids.stmt(stmt);
+        self.emit("pre", depth, stmt.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let depth = self.open.leave(stmt);
+        let source = unparse(|unparser| unparser.unparse_stmt(stmt));
+        let location = self.ids.stmt(stmt);
+        let kind = stmt.kind();
+        self.emit("post", depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_expr(expr));
+        let depth = self.open.enter(expr);
+        let location = self.ids.expr(expr);
+        self.emit("pre", depth, expr.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let depth = self.open.leave(expr);
+        let source = unparse(|unparser| unparser.unparse_expr(expr));
+        let location = self.ids.expr(expr);
+        let kind = expr.kind();
+        self.emit("post", depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        let source = unparse(|unparser| unparser.unparse_factor(factor));
+        let depth = self.open.enter(factor);
+        let location = self.ids.factor(factor);
+        self.emit("pre", depth, factor.kind(), location, source, None)?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let depth = self.open.leave(factor);
+        let source = unparse(|unparser| unparser.unparse_factor(factor));
+        let location = self.ids.factor(factor);
+        let kind = factor.kind();
+        self.emit("post", depth, kind, location, source, Some(&result))?;
+        result
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//! A dynamic analysis that measures where evaluation time goes.
+//!
+//! Times are wall-clock, so in the async designs they include time spent
+//! suspended at an `.await`.
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    runtime::{self, Env, Error, Value},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub calls: usize,
+    /// Time spent including children.  Overlapping time is counted more than
+    /// once for recursive calls and nested nodes of the same kind.
+    pub inclusive: Duration,
+    /// Time spent excluding children.
+    pub exclusive: Duration,
+}
+
+/// A function call or AST node that hasn't finished yet.
+#[derive(Debug)]
+struct Open<K> {
+    key: K,
+    /// For a node, its address.  For a call, the number of nodes open when it
+    /// was made.
+    at: usize,
+    start: Instant,
+    children: Duration,
+}
+
+impl<K: Eq + std::hash::Hash> Open<K> {
+    fn new(key: K, at: usize) -> Self {
+        Self {
+            key,
+            at,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        }
+    }
+
+    /// Adds the time since this opened to `stats`, and returns it.
+    fn close(self, stats: &mut HashMap<K, Stats>) -> Duration {
+        let elapsed = self.start.elapsed();
+        let stats = stats.entry(self.key).or_default();
+        stats.calls += 1;
+        stats.inclusive += elapsed;
+        stats.exclusive += elapsed.saturating_sub(self.children);
+        elapsed
+    }
+}
+
+/// Prints inclusive and exclusive times and call counts per function and per
+/// node kind when evaluation finishes.
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub functions: HashMap<Identifier, Stats>,
+    pub nodes: HashMap<&'static str, Stats>,
+    open_calls: Vec<Open<Identifier>>,
+    open_nodes: Vec<Open<&'static str>>,
+}
+
+impl Profile {
+    fn enter_node<T>(&mut self, kind: &'static str, node: &T) {
+        self.open_nodes.push(Open::new(kind, address(node)));
+    }
+
+    /// Closes `node` and anything opened inside it that's still open: nodes
+    /// cancelled by a timeout, which are never post-visited, and calls that
+    /// failed, which never return.
+    fn exit_node<T>(&mut self, node: &T) {
+        let node = address(node);
+        let Some(index) = self.open_nodes.iter().rposition(|open| open.at == node) else {
+            return;
+        };
+        while self.open_nodes.len() > index {
+            self.close_calls_inside(self.open_nodes.len());
+            let open = self.open_nodes.pop().expect("longer than index");
+            let elapsed = open.close(&mut self.nodes);
+            if let Some(parent) = self.open_nodes.last_mut() {
+                parent.children += elapsed;
+            }
+        }
+    }
+
+    fn exit_call(&mut self) {
+        let Some(open) = self.open_calls.pop() else {
+            return;
+        };
+        let elapsed = open.close(&mut self.functions);
+        if let Some(parent) = self.open_calls.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    /// Closes the calls made while the first `nodes` nodes were open.
+    fn close_calls_inside(&mut self, nodes: usize) {
+        while matches!(self.open_calls.last(), Some(open) if open.at >= nodes) {
+            self.exit_call();
+        }
+    }
+
+    /// A table of stats per function, then per node kind, each sorted by
+    /// inclusive time.
+    pub fn report(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        write_table(&mut out, "Function", self.functions.iter())?;
+        writeln!(out)?;
+        write_table(&mut out, "Node", self.nodes.iter())?;
+        Ok(out)
+    }
+}
+
+fn address<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+fn write_table<'a, K: std::fmt::Display + Ord + 'a>(
+    out: &mut String,
+    heading: &str,
+    rows: impl Iterator<Item = (K, &'a Stats)>,
+) -> std::fmt::Result {
+    let mut rows = rows.collect::<Vec<_>>();
+    rows.sort_by(|(k1, s1), (k2, s2)| s2.inclusive.cmp(&s1.inclusive).then(k1.cmp(k2)));
+    writeln!(
+        out,
+        "{heading:<20} {:>8} {:>14} {:>14}",
+        "Calls", "Inclusive", "Exclusive"
+    )?;
+    for (key, stats) in rows {
+        writeln!(
+            out,
+            "{:<20} {:>8} {:>14} {:>14}",
+            key.to_string(),
+            stats.calls,
+            format!("{:?}", stats.inclusive),
+            format!("{:?}", stats.exclusive)
+        )?;
+    }
+    Ok(())
+}
+
+impl runtime::Visit for Profile {
+    fn pre_visit_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.enter_node(stmt.kind(), stmt);
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit_node(stmt);
+        result
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> Result<Option<Value>, Error> {
+        self.enter_node(expr.kind(), expr);
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit_node(expr);
+        result
+    }
+
+    fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+        self.enter_node(factor.kind(), factor);
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.exit_node(factor);
+        result
+    }
+
+    fn on_call(&mut self, fun: &Identifier, _args: &[Value], _env: &Env) -> Result<(), Error> {
+        self.open_calls
+            .push(Open::new(fun.clone(), self.open_nodes.len()));
+        Ok(())
+    }
+
+    fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        self.exit_call();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        // Forget what's still open after an error, so that another run
+        // doesn't charge its time to them.
+        self.open_calls.clear();
+        self.open_nodes.clear();
+        print!("{}", self.report()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::Visit;
+
+    fn literal(x: f64) -> Factor {
+        Factor::Literal(x)
+    }
+
+    fn expr(factor: Factor) -> Expr {
+        Expr::Factor(Box::new(factor))
+    }
+
+    fn calls(stats: &HashMap<&'static str, Stats>, kind: &str) -> usize {
+        stats.get(kind).map_or(0, |stats| stats.calls)
+    }
+
+    #[test]
+    fn times_nested_nodes_and_calls() {
+        let (outer, inner) = (expr(literal(1.0)), literal(2.0));
+        let mut profile = Profile::default();
+        profile.pre_visit_expr(&outer).unwrap();
+        profile.on_call(&"f".to_owned(), &[], &Env::new()).unwrap();
+        profile.pre_visit_factor(&inner).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        profile
+            .post_visit_factor(&inner, Ok(Value::Number(2.0)))
+            .unwrap();
+        profile.on_return(&Value::Number(2.0)).unwrap();
+        profile
+            .post_visit_expr(&outer, Ok(Value::Number(2.0)))
+            .unwrap();
+
+        assert_eq!(calls(&profile.nodes, "Expr::Factor"), 1);
+        assert_eq!(calls(&profile.nodes, "Factor::Literal"), 1);
+        assert_eq!(profile.functions["f"].calls, 1);
+        let outer = profile.nodes["Expr::Factor"];
+        let inner = profile.nodes["Factor::Literal"];
+        assert!(outer.inclusive >= inner.inclusive);
+        assert!(inner.inclusive >= Duration::from_millis(2));
+        // The literal's time is the expression's children's.
+        assert!(outer.exclusive < Duration::from_millis(2));
+        assert!(profile.open_nodes.is_empty() && profile.open_calls.is_empty());
+    }
+
+    #[test]
+    fn closes_nodes_that_were_never_post_visited() {
+        // As when a timeout cancels the nodes inside it.
+        let (outer, middle, inner) = (expr(literal(1.0)), expr(literal(2.0)), literal(3.0));
+        let mut profile = Profile::default();
+        profile.pre_visit_expr(&outer).unwrap();
+        profile.pre_visit_expr(&middle).unwrap();
+        profile.on_call(&"f".to_owned(), &[], &Env::new()).unwrap();
+        profile.pre_visit_factor(&inner).unwrap();
+        profile
+            .post_visit_expr(&outer, Ok(Value::Number(1.0)))
+            .unwrap();
+
+        assert_eq!(calls(&profile.nodes, "Expr::Factor"), 2);
+        assert_eq!(calls(&profile.nodes, "Factor::Literal"), 1);
+        // The call failed or was cancelled, so it never returned.
+        assert_eq!(profile.functions["f"].calls, 1);
+        assert!(profile.open_nodes.is_empty() && profile.open_calls.is_empty());
+
+        // Post-visiting a node that was closed with its parent does nothing.
+        profile
+            .post_visit_factor(&inner, Ok(Value::Number(3.0)))
+            .unwrap();
+        assert_eq!(calls(&profile.nodes, "Factor::Literal"), 1);
+    }
+
+    #[test]
+    fn finish_forgets_what_is_open() {
+        let (outer, inner) = (expr(literal(1.0)), literal(2.0));
+        let mut profile = Profile::default();
+        profile.pre_visit_expr(&outer).unwrap();
+        profile.on_call(&"f".to_owned(), &[], &Env::new()).unwrap();
+        profile.finish().unwrap();
+        assert!(profile.open_nodes.is_empty() && profile.open_calls.is_empty());
+
+        // A second run isn't charged to the first's nodes.
+        profile.pre_visit_factor(&inner).unwrap();
+        profile
+            .post_visit_factor(&inner, Ok(Value::Number(2.0)))
+            .unwrap();
+        profile.on_return(&Value::Number(2.0)).unwrap();
+        assert_eq!(calls(&profile.nodes, "Factor::Literal"), 1);
+        assert_eq!(calls(&profile.nodes, "Expr::Factor"), 0);
+        assert!(!profile.functions.contains_key("f"));
+    }
+}
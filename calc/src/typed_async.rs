@@ -0,0 +1,31 @@
+//! Async evaluation of a simply typed language with a concrete syntax.
+//!
+//! `coverage`, `debugger`, `log`, `node_id` and `profile` are built from the
+//! same source files in both async designs, each against its own AST.
+pub mod ast;
+#[path = "shared/coverage.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod coverage;
+#[path = "shared/debugger.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod debugger;
+pub mod erase;
+pub mod evaluator;
+#[cfg(test)]
+pub(crate) mod generate;
+#[path = "shared/log.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod log;
+#[path = "shared/node_id.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod node_id;
+pub mod parser;
+pub mod prelude;
+pub mod pretty;
+#[path = "shared/profile.rs"]
+#[allow(clippy::duplicate_mod)]
+pub mod profile;
+pub mod runtime;
+pub mod tc;
+pub mod trace;
+pub mod unparser;
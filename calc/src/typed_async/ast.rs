@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Arrow(Vec<Type>, Box<Type>),
+}
+
+pub type Identifier = String;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Let(Identifier, Type, Box<Expr>),
+    Fun(Identifier, Arc<Function>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub params: Params,
+    pub return_ty: Type,
+    pub body: Expr,
+}
+
+pub type Params = Vec<Param>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Identifier,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Factor(Box<Factor>),
+    BinaryOp {
+        op: TermBinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermBinaryOp {
+    Add,
+    #[allow(dead_code)]
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Factor {
+    Literal(f64),
+    Variable(Identifier),
+    #[allow(dead_code)]
+    Group(Box<Expr>),
+    #[allow(dead_code)]
+    BinaryOp {
+        op: FactorBinaryOp,
+        lhs: Box<Factor>,
+        rhs: Box<Factor>,
+    },
+    Call(Box<Factor>, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactorBinaryOp {
+    #[allow(dead_code)]
+    Mul,
+    #[allow(dead_code)]
+    Div,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Number => write!(f, "number"),
+            Type::Arrow(param_tys, t2) => {
+                write!(f, "(")?;
+                for (i, t) in param_tys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ") -> {t2}")
+            }
+        }
+    }
+}
+
+crate::common::node_kinds! {
+    Stmt { Let, Fun }
+    Expr { Factor, BinaryOp }
+    Factor { Literal, Variable, Group, BinaryOp, Call }
+}
+
+impl Stmt {
+    /// The expression in the statement: the value of a `let`, or the body of
+    /// a function.
+    pub fn expr(&self) -> &Expr {
+        match self {
+            Stmt::Let(_, _, expr) => expr,
+            Stmt::Fun(_, fun) => &fun.body,
+        }
+    }
+}
+
+/// A child of an AST node.
+#[derive(Debug, Clone, Copy)]
+pub enum Child<'a> {
+    Expr(&'a Expr),
+    Factor(&'a Factor),
+}
+
+impl Factor {
+    /// The factor's children, in the order they're evaluated.
+    pub fn children(&self) -> Vec<Child<'_>> {
+        match self {
+            Factor::Literal(_) | Factor::Variable(_) => Vec::new(),
+            Factor::Group(expr) => vec![Child::Expr(expr)],
+            Factor::BinaryOp { op: _, lhs, rhs } => vec![Child::Factor(lhs), Child::Factor(rhs)],
+            Factor::Call(fun, args) => {
+                let args = args.iter().map(Child::Expr);
+                std::iter::once(Child::Factor(fun)).chain(args).collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Typed programs as programs of the untyped designs, to run them there too.
+//!
+//! Each design has a module of its name here whose `stmts` drops the types of
+//! `let`s and parameters, and keeps the rest of the tree as is.
+
+/// Defines the module `$design`, whose `stmts` erases the types of typed
+/// statements to get `$design`'s, putting functions in `$new`.
+macro_rules! erase_types {
+    ($design:ident, $new:expr) => {
+        pub mod $design {
+            use crate::{typed_async::ast as typed, $design::ast};
+
+            /// `stmts` without their types.
+            pub fn stmts(stmts: &[typed::Stmt]) -> Vec<ast::Stmt> {
+                stmts.iter().map(stmt).collect()
+            }
+
+            fn stmt(stmt: &typed::Stmt) -> ast::Stmt {
+                match stmt {
+                    typed::Stmt::Let(name, _, value) => {
+                        ast::Stmt::Let(name.clone(), Box::new(expr(value)))
+                    }
+                    typed::Stmt::Fun(name, fun) => {
+                        let fun = ast::Function {
+                            params: fun.params.iter().map(|param| param.name.clone()).collect(),
+                            body: expr(&fun.body),
+                        };
+                        ast::Stmt::Fun(name.clone(), ($new)(fun))
+                    }
+                }
+            }
+
+            fn expr(expr: &typed::Expr) -> ast::Expr {
+                match expr {
+                    typed::Expr::Factor(f) => ast::Expr::Factor(Box::new(factor(f))),
+                    typed::Expr::BinaryOp { op, lhs, rhs } => ast::Expr::BinaryOp {
+                        op: match op {
+                            typed::TermBinaryOp::Add => ast::TermBinaryOp::Add,
+                            typed::TermBinaryOp::Sub => ast::TermBinaryOp::Sub,
+                        },
+                        lhs: Box::new(self::expr(lhs)),
+                        rhs: Box::new(self::expr(rhs)),
+                    },
+                }
+            }
+
+            fn factor(factor: &typed::Factor) -> ast::Factor {
+                match factor {
+                    typed::Factor::Literal(value) => ast::Factor::Literal(*value),
+                    typed::Factor::Variable(name) => ast::Factor::Variable(name.clone()),
+                    typed::Factor::Group(e) => ast::Factor::Group(Box::new(expr(e))),
+                    typed::Factor::BinaryOp { op, lhs, rhs } => ast::Factor::BinaryOp {
+                        op: match op {
+                            typed::FactorBinaryOp::Mul => ast::FactorBinaryOp::Mul,
+                            typed::FactorBinaryOp::Div => ast::FactorBinaryOp::Div,
+                        },
+                        lhs: Box::new(self::factor(lhs)),
+                        rhs: Box::new(self::factor(rhs)),
+                    },
+                    typed::Factor::Call(fun, args) => ast::Factor::Call(
+                        Box::new(self::factor(fun)),
+                        args.iter().map(expr).collect(),
+                    ),
+                }
+            }
+        }
+    };
+}
+
+erase_types!(mutable_self, std::rc::Rc::new);
+erase_types!(mutable_self_async, std::sync::Arc::new);
+#[cfg(feature = "recursion")]
+erase_types!(recursion_crate, Box::new);
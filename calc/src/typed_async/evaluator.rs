@@ -0,0 +1,674 @@
+use std::sync::Arc;
+
+use async_recursion::async_recursion;
+
+use crate::typed_async::{
+    ast::{Expr, Factor, FactorBinaryOp, Identifier, Stmt, TermBinaryOp, Type},
+    prelude,
+    runtime::{AsyncVisit, Env, Error, Frame, Native, StackOverflow, TracedError, Value},
+};
+
+pub use crate::common::DEFAULT_MAX_CALL_DEPTH;
+
+#[derive(Debug)]
+pub struct Evaluator {
+    /// Starts out with the built-ins from [`prelude`].
+    pub bindings: Env,
+    pub visitors: Vec<Box<dyn AsyncVisit>>,
+    /// Calls nested deeper than this fail with a [`StackOverflow`] error.
+    pub max_call_depth: usize,
+    /// Calls currently in progress, outermost first.
+    call_stack: Vec<Frame>,
+    /// Snapshot of `call_stack`, innermost first, taken where the current
+    /// error was raised.
+    error_backtrace: Option<Vec<Frame>>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            bindings: prelude::bindings(),
+            visitors: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_stack: Vec::new(),
+            error_backtrace: None,
+        }
+    }
+}
+
+impl Evaluator {
+    /// Binds `name` to `fun`, a function implemented by the host that programs
+    /// call like any other.  Its arguments and result have the types in
+    /// `signature`, which the type checker trusts.
+    ///
+    /// # Panics
+    ///
+    /// If `signature` isn't a [`Type::Arrow`].
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<Identifier>,
+        signature: Type,
+        fun: impl Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    ) {
+        let name = name.into();
+        assert!(
+            matches!(signature, Type::Arrow(..)),
+            "Native function {name} must have a function type, found {signature}"
+        );
+        let native = Native {
+            name: name.clone(),
+            signature,
+            fun: Arc::new(fun),
+        };
+        self.bindings.insert(name, Value::Native(native));
+    }
+
+    pub async fn eval_stmts(&mut self, stmts: &[Stmt]) -> Result<Value, Error> {
+        self.error_backtrace = None;
+        let mut result = Ok(Value::Number(0.0));
+        for stmt in stmts {
+            result = self.eval_stmt(stmt).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        // Let every visitor finish, even after an error.
+        for visitor in self.visitors.iter_mut() {
+            if let Err(err) = visitor.finish().await {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result.map_err(|err| self.traced(err))
+    }
+
+    /// Attaches the call stack captured when `error` was raised.
+    fn traced(&mut self, error: Error) -> Error {
+        Box::new(TracedError {
+            error,
+            backtrace: self.error_backtrace.take().unwrap_or_default(),
+        })
+    }
+
+    pub async fn eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_stmt(stmt).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_stmt(stmt).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_stmt(stmt, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_stmt(&mut self, stmt: &Stmt) -> Result<Value, Error> {
+        match stmt {
+            Stmt::Let(ident, _ty, expr) => {
+                let value = self.eval_expr(expr).await?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &value).await?;
+                }
+                self.bindings.insert(ident.clone(), value.clone());
+                Ok(value)
+            }
+            Stmt::Fun(ident, function) => {
+                let closure =
+                    Value::Closure(ident.clone(), Arc::clone(function), self.bindings.clone());
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_bind(ident, &closure).await?;
+                }
+                self.bindings.insert(ident.to_owned(), closure.clone());
+                Ok(closure)
+            }
+        }
+    }
+
+    #[async_recursion]
+    pub async fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_expr(expr).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_expr(expr).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_expr(expr, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        match expr {
+            Expr::Factor(f) => self.eval_factor(f).await,
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_expr(lhs).await?;
+                let rhs = self.eval_expr(rhs).await?;
+
+                match op {
+                    TermBinaryOp::Add => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+                        _ => Err("Addition only supported for numbers".into()),
+                    },
+                    TermBinaryOp::Sub => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+                        _ => Err("Subtraction only supported for numbers".into()),
+                    },
+                }
+            }
+        }
+    }
+
+    #[async_recursion]
+    async fn eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        // Pre-order visitors.  The first one to return a value or an error
+        // skips evaluation and the pre-order visitors after it.
+        let mut visited = 0;
+        let mut replacement = None;
+        for visitor in self.visitors.iter_mut() {
+            visited += 1;
+            replacement = visitor.pre_visit_factor(factor).await.transpose();
+            if replacement.is_some() {
+                break;
+            }
+        }
+        // Evaluate.
+        let mut result = match replacement {
+            Some(result) => result,
+            None => self.inner_eval_factor(factor).await,
+        };
+        // Post-order visitors of those that were pre-visited, each
+        // transforming the result of the one before.
+        for visitor in self.visitors[..visited].iter_mut().rev() {
+            result = visitor.post_visit_factor(factor, result).await;
+        }
+        if result.is_ok() {
+            // Forget the backtrace of any error that a visitor recovered from.
+            self.error_backtrace = None;
+        }
+
+        result
+    }
+
+    async fn inner_eval_factor(&mut self, factor: &Factor) -> Result<Value, Error> {
+        match factor {
+            Factor::Literal(x) => Ok(Value::Number(*x)),
+            Factor::Variable(ident) => {
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_lookup(ident).await?;
+                }
+                self.bindings
+                    .get(ident)
+                    .cloned()
+                    .ok_or_else(|| format!("Variable not found in bindings: {}", ident).into())
+            }
+            Factor::Group(expr) => self.eval_expr(expr).await,
+            Factor::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.eval_factor(lhs).await?;
+                let rhs = self.eval_factor(rhs).await?;
+                match op {
+                    FactorBinaryOp::Mul => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+                        _ => Err("Multiplication only supported for numbers".into()),
+                    },
+                    FactorBinaryOp::Div => match (lhs, rhs) {
+                        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+                        _ => Err("Division only supported for numbers".into()),
+                    },
+                }
+            }
+            Factor::Call(fun, args) => {
+                let (name, fun, env) = match self.eval_factor(fun).await? {
+                    Value::Closure(name, fun, env) => (name, fun, env),
+                    Value::Native(native) => return self.call_native(&native, args).await,
+                    Value::Number(_) => return Err("Expected function".into()),
+                };
+                let mut body_env = env.clone();
+                let mut frame = Frame {
+                    name,
+                    args: Vec::with_capacity(args.len()),
+                };
+                for arg in args.iter().take(fun.params.len()) {
+                    frame.args.push(self.eval_expr(arg).await?);
+                }
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_call(&frame.name, &frame.args, &env).await?;
+                }
+                for (param, value) in fun.params.iter().zip(&frame.args) {
+                    for visitor in self.visitors.iter_mut() {
+                        visitor.on_bind(&param.name, value).await?;
+                    }
+                    body_env.insert(param.name.clone(), value.clone());
+                }
+                self.call_stack.push(frame);
+                if self.call_stack.len() > self.max_call_depth {
+                    let err = StackOverflow {
+                        max_depth: self.max_call_depth,
+                    };
+                    self.call_stack.pop();
+                    return Err(err.into());
+                }
+                let current_bindings = std::mem::replace(&mut self.bindings, body_env);
+                let result = self.eval_expr(&fun.body).await;
+                self.bindings = current_bindings;
+                if result.is_err() && self.error_backtrace.is_none() {
+                    self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+                }
+                self.call_stack.pop();
+                let value = result?;
+                for visitor in self.visitors.iter_mut() {
+                    visitor.on_return(&value).await?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Calls a function implemented by the host with the values of `args`.
+    async fn call_native(&mut self, native: &Native, args: &[Expr]) -> Result<Value, Error> {
+        if args.len() != native.arity() {
+            return Err(format!(
+                "Number of function parameters differs from arguments: expected {}, found {}",
+                native.arity(),
+                args.len()
+            )
+            .into());
+        }
+        let mut frame = Frame {
+            name: native.name.clone(),
+            args: Vec::with_capacity(args.len()),
+        };
+        for arg in args {
+            frame.args.push(self.eval_expr(arg).await?);
+        }
+        for visitor in self.visitors.iter_mut() {
+            visitor
+                .on_call(&frame.name, &frame.args, &Env::new())
+                .await?;
+        }
+        self.call_stack.push(frame);
+        let frame = self.call_stack.last().expect("pushed above");
+        let result = (native.fun)(&frame.args);
+        if result.is_err() && self.error_backtrace.is_none() {
+            self.error_backtrace = Some(self.call_stack.iter().rev().cloned().collect());
+        }
+        self.call_stack.pop();
+        let value = result?;
+        for visitor in self.visitors.iter_mut() {
+            visitor.on_return(&value).await?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed_async::{
+        ast::{Function, Param, Type},
+        log,
+        runtime::{TracedError, Visit},
+        tc::TypeChecker,
+    };
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn call(fun: &str, args: Vec<Expr>) -> Expr {
+        Expr::Factor(Box::new(Factor::Call(
+            Box::new(Factor::Variable(fun.to_owned())),
+            args,
+        )))
+    }
+
+    fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+        Stmt::Fun(
+            name.to_owned(),
+            Arc::new(Function {
+                params: params
+                    .iter()
+                    .map(|p| Param {
+                        name: p.to_string(),
+                        ty: Type::Number,
+                    })
+                    .collect(),
+                return_ty: Type::Number,
+                body,
+            }),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawn_concurrent_evaluations() {
+        let handles = (0..64)
+            .map(|i| {
+                let stmts = vec![
+                    Stmt::Let("x".to_owned(), Type::Number, Box::new(num(i as f64))),
+                    fun(
+                        "add",
+                        &["a", "b"],
+                        Expr::BinaryOp {
+                            op: TermBinaryOp::Add,
+                            lhs: Box::new(var("a")),
+                            rhs: Box::new(var("b")),
+                        },
+                    ),
+                    Stmt::Let(
+                        "y".to_owned(),
+                        Type::Number,
+                        Box::new(call("add", vec![var("x"), var("x")])),
+                    ),
+                ];
+                tokio::spawn(async move {
+                    let mut evaluator = Evaluator::default();
+                    evaluator
+                        .visitors
+                        .push(Box::new(log::Print::new(std::io::sink())));
+                    evaluator.eval_stmts(&stmts).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let value = handle.await.unwrap().unwrap();
+            assert!(matches!(value, Value::Number(y) if y == 2.0 * i as f64));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawned_stack_overflow() {
+        // Ill-typed, but the evaluator doesn't care.
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let(
+                "x".to_owned(),
+                Type::Number,
+                Box::new(call("omega", vec![var("omega")])),
+            ),
+        ];
+        let result = tokio::spawn(async move {
+            let mut evaluator = Evaluator::default();
+            evaluator.eval_stmts(&stmts).await
+        })
+        .await
+        .unwrap();
+
+        let err = result.unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    #[tokio::test]
+    async fn stack_overflow_trace() {
+        // Ill-typed, but the evaluator doesn't care.
+        let stmts = vec![
+            fun("omega", &["g"], call("g", vec![var("g")])),
+            Stmt::Let(
+                "x".to_owned(),
+                Type::Number,
+                Box::new(call("omega", vec![var("omega")])),
+            ),
+        ];
+        let mut evaluator = Evaluator {
+            max_call_depth: 2,
+            ..Evaluator::default()
+        };
+        let err = evaluator.eval_stmts(&stmts).await.unwrap_err();
+
+        // The calls are only listed once, in the trace.
+        assert_eq!(
+            err.to_string(),
+            "Stack overflow: maximum call depth of 2 exceeded\n\
+             Stack trace:\n  \
+             at omega(<fun omega>)\n  \
+             at omega(<fun omega>)"
+        );
+    }
+
+    #[tokio::test]
+    async fn type_checked_calls_to_native_functions() {
+        let price = Type::Arrow(vec![Type::Number], Box::new(Type::Number));
+        let mut evaluator = Evaluator::default();
+        evaluator.register_fn("price", price, |args| match args {
+            [Value::Number(quantity)] => Ok(Value::Number(quantity * 2.5)),
+            _ => Err("Expected a quantity".into()),
+        });
+        let mut type_checker = TypeChecker::new(&evaluator.bindings);
+
+        let wrong = vec![Stmt::Let(
+            "total".to_owned(),
+            Type::Number,
+            Box::new(var("price")),
+        )];
+        let err = type_checker.check_stmts(&wrong).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected number, found (number) -> number"
+        );
+
+        let stmts = vec![Stmt::Let(
+            "total".to_owned(),
+            Type::Number,
+            Box::new(call("price", vec![num(4.0)])),
+        )];
+        type_checker.check_stmts(&stmts).unwrap();
+        let value = evaluator.eval_stmts(&stmts).await.unwrap();
+        assert!(matches!(value, Value::Number(total) if total == 10.0));
+    }
+
+    /// `f` defined `depth` times, each calling the one before, and a call of
+    /// the last, so that calls nest exactly `depth` deep.
+    fn nested_calls(depth: usize) -> Vec<Stmt> {
+        let mut stmts = vec![fun("f", &["x"], var("x"))];
+        for _ in 1..depth {
+            stmts.push(fun("f", &["x"], call("f", vec![var("x")])));
+        }
+        stmts.push(Stmt::Let(
+            "y".to_owned(),
+            Type::Number,
+            Box::new(call("f", vec![num(1.0)])),
+        ));
+        stmts
+    }
+
+    #[tokio::test]
+    async fn calls_nest_up_to_the_limit() {
+        let mut evaluator = Evaluator::default();
+        let value = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH))
+            .await;
+        assert!(matches!(value, Ok(Value::Number(y)) if y == 1.0));
+
+        let mut evaluator = Evaluator::default();
+        let err = evaluator
+            .eval_stmts(&nested_calls(DEFAULT_MAX_CALL_DEPTH + 1))
+            .await
+            .unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        assert!(traced.error.is::<StackOverflow>());
+        assert_eq!(traced.backtrace.len(), DEFAULT_MAX_CALL_DEPTH);
+    }
+
+    /// Reports each literal it's about to see, then waits for the test's
+    /// go-ahead before letting evaluation continue.
+    #[derive(Debug)]
+    struct Gate {
+        seen: tokio::sync::mpsc::UnboundedSender<f64>,
+        go: tokio::sync::mpsc::Receiver<()>,
+    }
+
+    #[async_trait::async_trait]
+    impl AsyncVisit for Gate {
+        async fn pre_visit_factor(&mut self, factor: &Factor) -> Result<Option<Value>, Error> {
+            if let Factor::Literal(x) = factor {
+                self.seen.send(*x)?;
+                self.go.recv().await.ok_or("gate closed")?;
+            }
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn visitors_can_await() {
+        let (seen, mut seen_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (go_tx, go) = tokio::sync::mpsc::channel(1);
+        let mut evaluator = Evaluator::default();
+        evaluator.visitors.push(Box::new(Gate { seen, go }));
+        let stmts = vec![Stmt::Let(
+            "x".to_owned(),
+            Type::Number,
+            Box::new(Expr::BinaryOp {
+                op: TermBinaryOp::Add,
+                lhs: Box::new(num(1.0)),
+                rhs: Box::new(num(2.0)),
+            }),
+        )];
+        let evaluation = tokio::spawn(async move { evaluator.eval_stmts(&stmts).await.unwrap() });
+
+        for x in [1.0, 2.0] {
+            assert_eq!(seen_rx.recv().await, Some(x));
+            // Evaluation is suspended in the hook until the gate opens.
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            assert!(!evaluation.is_finished());
+            go_tx.send(()).await.unwrap();
+        }
+        let value = evaluation.await.unwrap();
+        assert!(matches!(value, Value::Number(x) if x == 3.0));
+    }
+
+    /// Records the targeted hooks it's called with.
+    #[derive(Debug)]
+    struct Record(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl Record {
+        fn log(&self, event: String) -> Result<(), Error> {
+            self.0.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    impl Visit for Record {
+        fn on_call(&mut self, fun: &Identifier, args: &[Value], env: &Env) -> Result<(), Error> {
+            let args = args.iter().map(Value::to_string).collect::<Vec<_>>();
+            let mut env = env
+                .iter()
+                .filter(|(name, value)| !value.is_prelude(name))
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            env.sort();
+            self.log(format!("call {fun}({}) in {env:?}", args.join(", ")))
+        }
+
+        fn on_return(&mut self, value: &Value) -> Result<(), Error> {
+            self.log(format!("return {value}"))
+        }
+
+        fn on_bind(&mut self, name: &Identifier, value: &Value) -> Result<(), Error> {
+            self.log(format!("bind {name} {value}"))
+        }
+
+        fn on_lookup(&mut self, name: &Identifier) -> Result<(), Error> {
+            self.log(format!("lookup {name}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_run_in_evaluation_order() {
+        let stmts = vec![
+            Stmt::Let("k".to_owned(), Type::Number, Box::new(num(1.0))),
+            fun(
+                "add",
+                &["a", "b"],
+                Expr::BinaryOp {
+                    op: TermBinaryOp::Add,
+                    lhs: Box::new(var("a")),
+                    rhs: Box::new(var("b")),
+                },
+            ),
+            Stmt::Let(
+                "x".to_owned(),
+                Type::Number,
+                Box::new(call("add", vec![var("k"), num(2.0)])),
+            ),
+            Stmt::Let(
+                "y".to_owned(),
+                Type::Number,
+                Box::new(call("abs", vec![var("x")])),
+            ),
+        ];
+        let events = Arc::default();
+        let mut evaluator = Evaluator {
+            ..Evaluator::default()
+        };
+        evaluator
+            .visitors
+            .push(Box::new(Record(Arc::clone(&events))));
+        evaluator.eval_stmts(&stmts).await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "bind k 1",
+                "bind add <fun add>",
+                // The function, then the arguments, are evaluated before the call.
+                "lookup add",
+                "lookup k",
+                "call add(1, 2) in [\"k\"]",
+                "bind a 1",
+                "bind b 2",
+                "lookup a",
+                "lookup b",
+                "return 3",
+                "bind x 3",
+                "lookup abs",
+                "lookup x",
+                // Natives are called with no environment.
+                "call abs(3) in []",
+                "return 3",
+                "bind y 3",
+            ]
+        );
+    }
+}
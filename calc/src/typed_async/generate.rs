@@ -0,0 +1,333 @@
+//! Random well-typed programs for property tests.
+//!
+//! Programs are generated in the shape the parser produces: operators
+//! associate to the left, and anything else that needs parentheses is a
+//! [`Factor::Group`].
+use std::sync::Arc;
+
+use proptest::prelude::*;
+
+use crate::typed_async::ast::{
+    Expr, Factor, FactorBinaryOp, Function, Identifier, Param, Stmt, TermBinaryOp, Type,
+};
+
+/// Names bindings are drawn from, few enough that they often shadow each
+/// other.  The last two start out bound by the [`prelude`].
+const NAMES: [&str; 6] = ["a", "b", "c", "d", "max", "pi"];
+
+/// How deeply expressions nest.
+const MAX_DEPTH: u32 = 3;
+
+/// Most statements in a program.
+const MAX_STMTS: usize = 6;
+
+/// Most parameters of a function.
+const MAX_PARAMS: usize = 3;
+
+/// Bindings in scope, innermost last.
+type Scope = Vec<(Identifier, Type)>;
+
+/// The prelude's bindings of [`NAMES`], which every program starts with.
+fn prelude() -> Scope {
+    vec![
+        (
+            "max".to_owned(),
+            Type::Arrow(vec![Type::Number; 2], Box::new(Type::Number)),
+        ),
+        ("pi".to_owned(), Type::Number),
+    ]
+}
+
+/// The bindings of `scope` that aren't shadowed.
+fn visible(scope: &Scope) -> Vec<(Identifier, Type)> {
+    NAMES
+        .iter()
+        .filter_map(|name| scope.iter().rev().find(|(other, _)| other == name))
+        .cloned()
+        .collect()
+}
+
+/// Types that have a literal or a visible variable.
+fn leaf_types(scope: &Scope) -> Vec<Type> {
+    let mut types = vec![Type::Number];
+    for (_, ty) in visible(scope) {
+        if !types.contains(&ty) {
+            types.push(ty);
+        }
+    }
+    types
+}
+
+/// Types of the visible functions that return `ty` and whose arguments have
+/// leaf types.
+fn callees(scope: &Scope, ty: &Type) -> Vec<Type> {
+    let leaf_types = leaf_types(scope);
+    let mut callees = Vec::new();
+    for (_, callee) in visible(scope) {
+        if let Type::Arrow(params, return_ty) = &callee {
+            if return_ty.as_ref() == ty
+                && params.iter().all(|param| leaf_types.contains(param))
+                && !callees.contains(&callee)
+            {
+                callees.push(callee);
+            }
+        }
+    }
+    callees
+}
+
+/// Types that [`expr`] can generate in `scope`.
+fn types(scope: &Scope) -> Vec<Type> {
+    let mut types = leaf_types(scope);
+    for (_, ty) in visible(scope) {
+        if let Type::Arrow(_, return_ty) = ty {
+            if !types.contains(&return_ty) && !callees(scope, &return_ty).is_empty() {
+                types.push(*return_ty);
+            }
+        }
+    }
+    types
+}
+
+fn literal() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        (-100..100).prop_map(f64::from),
+        any::<f64>().prop_filter("finite", |x| x.is_finite()),
+    ]
+}
+
+fn name() -> impl Strategy<Value = Identifier> {
+    proptest::sample::select(&NAMES[..]).prop_map(str::to_owned)
+}
+
+/// An expression of type `ty`, which must be one of [`types`] in `scope`.
+fn expr(scope: Scope, ty: Type, depth: u32) -> BoxedStrategy<Expr> {
+    let factor =
+        factor(scope.clone(), ty.clone(), depth).prop_map(|factor| Expr::Factor(Box::new(factor)));
+    if depth == 0 || ty != Type::Number {
+        return factor.boxed();
+    }
+    let op = prop_oneof![Just(TermBinaryOp::Add), Just(TermBinaryOp::Sub)];
+    let binary_op = (
+        op,
+        expr(scope.clone(), Type::Number, depth - 1),
+        self::factor(scope, Type::Number, depth - 1),
+    )
+        .prop_map(|(op, lhs, rhs)| Expr::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(Expr::Factor(Box::new(rhs))),
+        });
+    prop_oneof![factor, binary_op].boxed()
+}
+
+/// A factor of type `ty`, which must be one of [`types`] in `scope`.
+fn factor(scope: Scope, ty: Type, depth: u32) -> BoxedStrategy<Factor> {
+    let mut leaves = Vec::new();
+    if ty == Type::Number {
+        leaves.push(literal().prop_map(Factor::Literal).boxed());
+    }
+    let variables = visible(&scope)
+        .into_iter()
+        .filter(|(_, other)| other == &ty)
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    if !variables.is_empty() {
+        leaves.push(
+            proptest::sample::select(variables)
+                .prop_map(Factor::Variable)
+                .boxed(),
+        );
+    }
+
+    let mut branches = Vec::new();
+    let sub_depth = depth.saturating_sub(1);
+    if depth > 0 {
+        branches.push(
+            expr(scope.clone(), ty.clone(), sub_depth)
+                .prop_map(|expr| Factor::Group(Box::new(expr)))
+                .boxed(),
+        );
+        if ty == Type::Number {
+            let op = prop_oneof![Just(FactorBinaryOp::Mul), Just(FactorBinaryOp::Div)];
+            let binary_op = (
+                op,
+                factor(scope.clone(), Type::Number, sub_depth),
+                factor(scope.clone(), Type::Number, sub_depth),
+            )
+                .prop_map(|(op, lhs, rhs)| Factor::BinaryOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(parenthesized(rhs)),
+                });
+            branches.push(binary_op.boxed());
+        }
+    }
+    // A type that's only returned by functions has no leaves, so calls are
+    // generated even at the bottom.
+    if depth > 0 || leaves.is_empty() {
+        for callee in callees(&scope, &ty) {
+            let Type::Arrow(params, _) = &callee else {
+                unreachable!("callees are functions");
+            };
+            let args = params
+                .iter()
+                .map(|param| expr(scope.clone(), param.clone(), sub_depth))
+                .collect::<Vec<_>>();
+            let call = (factor(scope.clone(), callee, sub_depth), args).prop_map(|(fun, args)| {
+                let fun = match fun {
+                    Factor::Variable(_) | Factor::Group(_) | Factor::Call(..) => fun,
+                    _ => parenthesized(fun),
+                };
+                Factor::Call(Box::new(fun), args)
+            });
+            branches.push(call.boxed());
+        }
+    }
+
+    let mut options = leaves;
+    options.extend(branches);
+    proptest::strategy::Union::new(options).boxed()
+}
+
+/// Wraps operations in parentheses so that they parse as one operand.
+fn parenthesized(factor: Factor) -> Factor {
+    match factor {
+        Factor::BinaryOp { .. } => Factor::Group(Box::new(Expr::Factor(Box::new(factor)))),
+        _ => factor,
+    }
+}
+
+/// A statement and the binding it adds to `scope`.
+fn stmt(scope: Scope) -> BoxedStrategy<(Stmt, (Identifier, Type))> {
+    let let_scope = scope.clone();
+    let let_ =
+        (name(), proptest::sample::select(types(&scope))).prop_flat_map(move |(name, ty)| {
+            expr(let_scope.clone(), ty.clone(), MAX_DEPTH).prop_map(move |expr| {
+                (
+                    Stmt::Let(name.clone(), ty.clone(), Box::new(expr)),
+                    (name.clone(), ty.clone()),
+                )
+            })
+        });
+    let param = (name(), proptest::sample::select(leaf_types(&scope)))
+        .prop_map(|(name, ty)| Param { name, ty });
+    let fun = (name(), prop::collection::vec(param, 0..=MAX_PARAMS)).prop_flat_map(
+        move |(name, params)| {
+            let mut body_scope = scope.clone();
+            body_scope.extend(
+                params
+                    .iter()
+                    .map(|param| (param.name.clone(), param.ty.clone())),
+            );
+            proptest::sample::select(types(&body_scope)).prop_flat_map(move |return_ty| {
+                let name = name.clone();
+                let params = params.clone();
+                expr(body_scope.clone(), return_ty.clone(), MAX_DEPTH).prop_map(move |body| {
+                    let ty = Type::Arrow(
+                        params.iter().map(|param| param.ty.clone()).collect(),
+                        Box::new(return_ty.clone()),
+                    );
+                    let fun = Function {
+                        params: params.clone(),
+                        return_ty: return_ty.clone(),
+                        body,
+                    };
+                    (Stmt::Fun(name.clone(), Arc::new(fun)), (name.clone(), ty))
+                })
+            })
+        },
+    );
+    prop_oneof![let_, fun].boxed()
+}
+
+/// `len` statements run in `scope`.
+fn stmts(scope: Scope, len: usize) -> BoxedStrategy<Vec<Stmt>> {
+    if len == 0 {
+        return Just(Vec::new()).boxed();
+    }
+    stmt(scope.clone())
+        .prop_flat_map(move |(stmt, binding)| {
+            let mut scope = scope.clone();
+            scope.push(binding);
+            stmts(scope, len - 1).prop_map(move |mut rest| {
+                rest.insert(0, stmt.clone());
+                rest
+            })
+        })
+        .boxed()
+}
+
+/// A well-typed program.
+pub fn program() -> impl Strategy<Value = Vec<Stmt>> {
+    (0..=MAX_STMTS).prop_flat_map(|len| stmts(prelude(), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed_async::{
+        evaluator::Evaluator, parser::parse, pretty, runtime::Value, tc::TypeChecker,
+        unparser::Unparser,
+    };
+
+    fn unparse(stmts: &[Stmt]) -> String {
+        let mut unparser = Unparser::default();
+        unparser.unparse_stmts(stmts).unwrap();
+        unparser.output().to_owned()
+    }
+
+    /// Whether `value` has the shape of `ty`.
+    fn has_type(value: &Value, ty: &Type) -> bool {
+        match (value, ty) {
+            (Value::Number(_), Type::Number) => true,
+            (Value::Closure(_, fun, _), Type::Arrow(params, return_ty)) => {
+                fun.params.len() == params.len()
+                    && fun
+                        .params
+                        .iter()
+                        .zip(params)
+                        .all(|(param, ty)| &param.ty == ty)
+                    && &fun.return_ty == return_ty.as_ref()
+            }
+            (Value::Native(native), ty) => &native.signature == ty,
+            _ => false,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parse_inverts_unparse(stmts in program()) {
+            let source = unparse(&stmts);
+            let program = parse(&source).map_err(|err| TestCaseError::fail(format!("{source}{err}")))?;
+            prop_assert_eq!(&program.stmts, &stmts, "{}", source);
+
+            let formatted = pretty::format(&program, pretty::DEFAULT_WIDTH);
+            let reformatted = parse(&formatted).map_err(|err| TestCaseError::fail(format!("{formatted}{err}")))?;
+            prop_assert_eq!(&reformatted.stmts, &stmts, "{}", formatted);
+        }
+
+        #[test]
+        fn generated_programs_type_check(stmts in program()) {
+            let result = TypeChecker::default().check_stmts(&stmts);
+            prop_assert!(result.is_ok(), "{}\n{}", unparse(&stmts), result.unwrap_err());
+        }
+
+        /// Programs that type check evaluate without errors, to values of the
+        /// types the checker gave them.
+        #[test]
+        fn well_typed_programs_evaluate(stmts in program()) {
+            let mut tc = TypeChecker::default();
+            prop_assume!(tc.check_stmts(&stmts).is_ok());
+
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            let mut evaluator = Evaluator::default();
+            let result = runtime.block_on(evaluator.eval_stmts(&stmts));
+            prop_assert!(result.is_ok(), "{}\n{}", unparse(&stmts), result.unwrap_err());
+            for (name, ty) in &tc.bindings {
+                let value = &evaluator.bindings[name];
+                prop_assert!(has_type(value, ty), "{}: {} = {}", name, ty, value);
+            }
+        }
+    }
+}
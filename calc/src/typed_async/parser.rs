@@ -0,0 +1,760 @@
+//! Parsing of the concrete syntax printed by the [`Unparser`](crate::typed_async::unparser::Unparser).
+//!
+//! ```text
+//! program = stmt*
+//! stmt    = "let" NAME ":" type "=" expr
+//!         | "fun" NAME "(" (param ("," param)*)? ")" ":" type "=" expr
+//! param   = NAME ":" type
+//! type    = "number" | "(" (type ("," type)*)? ")" "->" type
+//! expr    = factor (("+" | "-") factor)*
+//! factor  = call (("*" | "/") call)*
+//! call    = primary ("(" (expr ("," expr)*)? ")")*
+//! primary = NUMBER | "-" NUMBER | NAME | "(" expr ")"
+//! ```
+//!
+//! Comments start with `#` and run to the end of the line.
+//!
+//! Besides the statements, the parser records where each statement, name and
+//! comment is, and resolves each variable to the name that binds it, for tools
+//! that work with source files.
+use std::{collections::HashMap, sync::Arc};
+
+use crate::typed_async::ast::{
+    Expr, Factor, FactorBinaryOp, Function, Identifier, Param, Stmt, TermBinaryOp, Type,
+};
+
+/// A position in source text, both 0-based.  Columns count UTF-16 code
+/// units, as in the Language Server Protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// Moves past `text`.
+    pub fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += c.len_utf16();
+            }
+        }
+    }
+}
+
+/// The text between two positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line + 1,
+            self.span.start.column + 1,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// What an occurrence of a name does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Bound by `let` with this type.
+    Let(Type),
+    /// Bound by `fun` to a function of this type.
+    Fun(Type),
+    /// A parameter of this type.
+    Param(Type),
+    /// A variable, with the index of the symbol that binds it, if any.
+    Use(Option<usize>),
+}
+
+/// An occurrence of a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: Identifier,
+    pub span: Span,
+    pub kind: SymbolKind,
+}
+
+/// Where a statement is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StmtSpan {
+    pub span: Span,
+    /// Index of the symbol the statement binds.
+    pub name: usize,
+}
+
+/// A comment, which doesn't affect the statements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// The text from `#` to the end of the line, without trailing whitespace.
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+    /// Indexed like `stmts`.
+    pub spans: Vec<StmtSpan>,
+    /// Every name in the program, in order.
+    pub symbols: Vec<Symbol>,
+    /// Every comment in the program, in order.
+    pub comments: Vec<Comment>,
+}
+
+impl Program {
+    /// Index of the symbol at `position`.
+    pub fn symbol_at(&self, position: Position) -> Option<usize> {
+        self.symbols
+            .iter()
+            .position(|symbol| symbol.span.contains(position))
+    }
+
+    /// The symbol that binds the symbol at `index`, which is the symbol
+    /// itself unless it's a variable.
+    pub fn definition(&self, index: usize) -> Option<usize> {
+        match self.symbols[index].kind {
+            SymbolKind::Use(definition) => definition,
+            _ => Some(index),
+        }
+    }
+
+    /// Type of the value bound to the symbol at `index`.
+    pub fn ty(&self, index: usize) -> Option<&Type> {
+        match &self.symbols[self.definition(index)?].kind {
+            SymbolKind::Let(ty) | SymbolKind::Fun(ty) | SymbolKind::Param(ty) => Some(ty),
+            SymbolKind::Use(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Name(Identifier),
+    /// Punctuation and operators.
+    Symbol(&'static str),
+    End,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Number(x) => write!(f, "`{x}`"),
+            TokenKind::Name(name) => write!(f, "`{name}`"),
+            TokenKind::Symbol(symbol) => write!(f, "`{symbol}`"),
+            TokenKind::End => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+const SYMBOLS: [&str; 10] = ["->", "(", ")", ",", ":", "=", "+", "-", "*", "/"];
+
+fn tokenize(source: &str) -> Result<(Vec<Token>, Vec<Comment>), ParseError> {
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let mut position = Position::default();
+    let mut rest = source;
+    loop {
+        let trimmed = rest.trim_start();
+        position.advance(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+        let start = position;
+        let Some(c) = rest.chars().next() else {
+            tokens.push(Token {
+                kind: TokenKind::End,
+                span: Span { start, end: start },
+            });
+            return Ok((tokens, comments));
+        };
+        if c == '#' {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            let text = rest[..len].trim_end();
+            position.advance(text);
+            comments.push(Comment {
+                text: text.to_owned(),
+                span: Span {
+                    start,
+                    end: position,
+                },
+            });
+            rest = &rest[text.len()..];
+            continue;
+        }
+        let (kind, len) = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..len].parse().map_err(|_| ParseError {
+                message: format!("Invalid number: {}", &rest[..len]),
+                span: Span {
+                    start,
+                    end: Position {
+                        line: start.line,
+                        column: start.column + len,
+                    },
+                },
+            })?;
+            (TokenKind::Number(number), len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (TokenKind::Name(rest[..len].to_owned()), len)
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            (TokenKind::Symbol(symbol), symbol.len())
+        } else {
+            let mut end = start;
+            end.advance(&rest[..c.len_utf8()]);
+            return Err(ParseError {
+                message: format!("Unexpected character: {c:?}"),
+                span: Span { start, end },
+            });
+        };
+        position.advance(&rest[..len]);
+        rest = &rest[len..];
+        tokens.push(Token {
+            kind,
+            span: Span {
+                start,
+                end: position,
+            },
+        });
+    }
+}
+
+/// Deepest nesting of parentheses, so that parsing can't overflow the stack.
+const MAX_NESTING: usize = 256;
+
+/// Tallest tree of expressions, counting each `Expr` and `Factor` on the way
+/// down, so that checking and printing it can't overflow the stack either.
+/// Chains like `1 + 1 + 1` grow one taller per operator, without parentheses.
+const MAX_HEIGHT: usize = 1024;
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    symbols: Vec<Symbol>,
+    /// Names in scope, innermost last, mapped to the symbols binding them.
+    scopes: Vec<HashMap<Identifier, usize>>,
+    /// Parentheses open around the next token.
+    nesting: usize,
+    /// How tall the expression or factor parsed last is.
+    height: usize,
+    /// Where the first node taller than `MAX_HEIGHT` in the statement is.
+    too_tall: Option<Span>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next]
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.next].clone();
+        if token.kind != TokenKind::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    /// Parses with `parse` inside one more pair of parentheses.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(ParseError {
+                message: format!("Nested more than {MAX_NESTING} deep"),
+                span: self.peek().span,
+            });
+        }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Sets `height` to one more than `children`, for a node at `span`.
+    /// Doesn't fail, so as not to grow the parser's frames in debug builds.
+    fn grow(&mut self, children: usize, span: Span) {
+        self.height = children + 1;
+        if self.height > MAX_HEIGHT && self.too_tall.is_none() {
+            self.too_tall = Some(span);
+        }
+    }
+
+    /// Parses the expression ending a statement.
+    fn top_expr(&mut self) -> Result<(Expr, Span), ParseError> {
+        let expr = self.expr()?;
+        match self.too_tall.take() {
+            Some(span) => Err(ParseError {
+                message: format!("Nested more than {MAX_HEIGHT} deep"),
+                span,
+            }),
+            None => Ok(expr),
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, ParseError> {
+        let token = self.peek();
+        Err(ParseError {
+            message: format!("Expected {expected}, found {}", token.kind),
+            span: token.span,
+        })
+    }
+
+    fn at(&self, symbol: &'static str) -> bool {
+        self.peek().kind == TokenKind::Symbol(symbol)
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        let found = self.at(symbol);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<Span, ParseError> {
+        if !self.at(symbol) {
+            return self.error(&format!("`{symbol}`"));
+        }
+        Ok(self.bump().span)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Option<Span> {
+        match &self.peek().kind {
+            TokenKind::Name(name) if name == keyword => Some(self.bump().span),
+            _ => None,
+        }
+    }
+
+    fn name(&mut self) -> Result<(Identifier, Span), ParseError> {
+        match &self.peek().kind {
+            TokenKind::Name(name) if !["let", "fun"].contains(&name.as_str()) => {
+                let name = name.clone();
+                Ok((name, self.bump().span))
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn symbol(&mut self, name: Identifier, span: Span, kind: SymbolKind) -> usize {
+        self.symbols.push(Symbol { name, span, kind });
+        self.symbols.len() - 1
+    }
+
+    fn bind(&mut self, index: usize) {
+        let name = self.symbols[index].name.clone();
+        self.scopes
+            .last_mut()
+            .expect("the top level is never closed")
+            .insert(name, index);
+    }
+
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let mut program = Program::default();
+        while self.peek().kind != TokenKind::End {
+            let (stmt, span) = self.stmt()?;
+            program.stmts.push(stmt);
+            program.spans.push(span);
+        }
+        program.symbols = std::mem::take(&mut self.symbols);
+        Ok(program)
+    }
+
+    fn stmt(&mut self) -> Result<(Stmt, StmtSpan), ParseError> {
+        if let Some(start) = self.keyword("let") {
+            let (name, name_span) = self.name()?;
+            self.expect(":")?;
+            let (ty, _) = self.ty()?;
+            self.expect("=")?;
+            let (expr, end) = self.top_expr()?;
+            // Bound after its expression, so it can't refer to itself.
+            let index = self.symbol(name.clone(), name_span, SymbolKind::Let(ty.clone()));
+            self.bind(index);
+            let span = StmtSpan {
+                span: start.to(end),
+                name: index,
+            };
+            return Ok((Stmt::Let(name, ty, Box::new(expr)), span));
+        }
+        if let Some(start) = self.keyword("fun") {
+            let (name, name_span) = self.name()?;
+            // The type isn't known until the return type is parsed.
+            let index = self.symbol(name.clone(), name_span, SymbolKind::Fun(Type::Number));
+            self.expect("(")?;
+            self.scopes.push(HashMap::new());
+            let mut params = Vec::new();
+            if !self.at(")") {
+                loop {
+                    let (name, span) = self.name()?;
+                    self.expect(":")?;
+                    let (ty, _) = self.ty()?;
+                    let param = self.symbol(name.clone(), span, SymbolKind::Param(ty.clone()));
+                    self.bind(param);
+                    params.push(Param { name, ty });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+            }
+            self.expect(")")?;
+            self.expect(":")?;
+            let (return_ty, _) = self.ty()?;
+            self.expect("=")?;
+            let (body, end) = self.top_expr()?;
+            self.scopes.pop();
+            let param_tys = params.iter().map(|param| param.ty.clone()).collect();
+            let ty = Type::Arrow(param_tys, Box::new(return_ty.clone()));
+            self.symbols[index].kind = SymbolKind::Fun(ty);
+            // Bound after its body, so it can't call itself.
+            self.bind(index);
+            let function = Function {
+                params,
+                return_ty,
+                body,
+            };
+            let span = StmtSpan {
+                span: start.to(end),
+                name: index,
+            };
+            return Ok((Stmt::Fun(name, Arc::new(function)), span));
+        }
+        self.error("`let` or `fun`")
+    }
+
+    fn ty(&mut self) -> Result<(Type, Span), ParseError> {
+        if let Some(span) = self.keyword("number") {
+            return Ok((Type::Number, span));
+        }
+        if self.at("(") {
+            let start = self.bump().span;
+            let mut params = Vec::new();
+            if !self.at(")") {
+                loop {
+                    params.push(self.nested(Self::ty)?.0);
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+            }
+            self.expect(")")?;
+            self.expect("->")?;
+            let (result, end) = self.nested(Self::ty)?;
+            return Ok((Type::Arrow(params, Box::new(result)), start.to(end)));
+        }
+        self.error("a type")
+    }
+
+    fn expr(&mut self) -> Result<(Expr, Span), ParseError> {
+        let (factor, mut span) = self.factor()?;
+        self.grow(self.height, span);
+        let mut expr = Expr::Factor(Box::new(factor));
+        loop {
+            let op = if self.eat("+") {
+                TermBinaryOp::Add
+            } else if self.eat("-") {
+                TermBinaryOp::Sub
+            } else {
+                return Ok((expr, span));
+            };
+            let lhs_height = self.height;
+            let (rhs, end) = self.factor()?;
+            span = span.to(end);
+            // One more for the `Expr::Factor` around the right operand.
+            self.grow(lhs_height.max(self.height + 1), span);
+            expr = Expr::BinaryOp {
+                op,
+                lhs: Box::new(expr),
+                rhs: Box::new(Expr::Factor(Box::new(rhs))),
+            };
+        }
+    }
+
+    fn factor(&mut self) -> Result<(Factor, Span), ParseError> {
+        let (mut factor, mut span) = self.call()?;
+        loop {
+            let op = if self.eat("*") {
+                FactorBinaryOp::Mul
+            } else if self.eat("/") {
+                FactorBinaryOp::Div
+            } else {
+                return Ok((factor, span));
+            };
+            let lhs_height = self.height;
+            let (rhs, end) = self.call()?;
+            span = span.to(end);
+            self.grow(lhs_height.max(self.height), span);
+            factor = Factor::BinaryOp {
+                op,
+                lhs: Box::new(factor),
+                rhs: Box::new(rhs),
+            };
+        }
+    }
+
+    fn call(&mut self) -> Result<(Factor, Span), ParseError> {
+        let (mut factor, mut span) = self.primary()?;
+        while self.eat("(") {
+            let mut height = self.height;
+            let mut args = Vec::new();
+            if !self.at(")") {
+                loop {
+                    args.push(self.nested(Self::expr)?.0);
+                    height = height.max(self.height);
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+            }
+            span = span.to(self.expect(")")?);
+            self.grow(height, span);
+            factor = Factor::Call(Box::new(factor), args);
+        }
+        Ok((factor, span))
+    }
+
+    fn primary(&mut self) -> Result<(Factor, Span), ParseError> {
+        let token = self.peek().clone();
+        self.height = 1;
+        match token.kind {
+            TokenKind::Number(x) => {
+                self.bump();
+                Ok((Factor::Literal(x), token.span))
+            }
+            // Negative literals, as printed by the unparser.
+            TokenKind::Symbol("-") => {
+                self.bump();
+                match self.peek().kind {
+                    TokenKind::Number(x) => {
+                        let end = self.bump().span;
+                        Ok((Factor::Literal(-x), token.span.to(end)))
+                    }
+                    _ => self.error("a number"),
+                }
+            }
+            TokenKind::Symbol("(") => {
+                self.bump();
+                let (expr, _) = self.nested(Self::expr)?;
+                let span = token.span.to(self.expect(")")?);
+                self.grow(self.height, span);
+                Ok((Factor::Group(Box::new(expr)), span))
+            }
+            _ => {
+                let (name, span) = self.name().or_else(|_| self.error("an expression"))?;
+                let definition = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(&name).copied());
+                self.symbol(name.clone(), span, SymbolKind::Use(definition));
+                Ok((Factor::Variable(name), span))
+            }
+        }
+    }
+}
+
+/// Parses a program.  Stops at the first syntax error.
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    let (tokens, comments) = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        symbols: Vec::new(),
+        scopes: vec![HashMap::new()],
+        nesting: 0,
+        height: 0,
+        too_tall: None,
+    };
+    let mut program = parser.program()?;
+    program.comments = comments;
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed_async::unparser::Unparser;
+
+    const SOURCE: &str = "\
+let x: number = 1
+fun add(a: number, b: number): number = a + b
+let y: number = add(x, -2) * (x - 3)
+";
+
+    fn unparse(stmts: &[Stmt]) -> String {
+        let mut unparser = Unparser::default();
+        unparser.unparse_stmts(stmts).unwrap();
+        unparser.output().to_owned()
+    }
+
+    #[test]
+    fn round_trips_through_unparser() {
+        let program = parse(SOURCE).unwrap();
+        assert_eq!(program.stmts.len(), 3);
+        assert_eq!(unparse(&program.stmts), SOURCE);
+    }
+
+    #[test]
+    fn binary_operators_associate_left() {
+        let program = parse("let x: number = 1 - 2 - 3").unwrap();
+        let Stmt::Let(_, _, expr) = &program.stmts[0] else {
+            panic!("expected let");
+        };
+        let Expr::BinaryOp { lhs, rhs, .. } = expr.as_ref() else {
+            panic!("expected binary op");
+        };
+        assert!(matches!(lhs.as_ref(), Expr::BinaryOp { .. }));
+        assert!(matches!(rhs.as_ref(), Expr::Factor(_)));
+    }
+
+    #[test]
+    fn records_spans_and_resolves_names() {
+        let program = parse(SOURCE).unwrap();
+        let span = program.spans[1].span;
+        assert_eq!(span.start, Position { line: 1, column: 0 });
+        assert_eq!(
+            span.end,
+            Position {
+                line: 1,
+                column: 45
+            }
+        );
+
+        let add = program.spans[1].name;
+        assert_eq!(program.symbols[add].name, "add");
+        assert_eq!(
+            program.ty(add).unwrap().to_string(),
+            "(number, number) -> number"
+        );
+
+        // `add` in `let y`.
+        let call = program
+            .symbol_at(Position {
+                line: 2,
+                column: 17,
+            })
+            .unwrap();
+        assert_eq!(program.definition(call), Some(add));
+        // `a` in the body of `add` is its parameter.
+        let a = program
+            .symbol_at(Position {
+                line: 1,
+                column: 40,
+            })
+            .unwrap();
+        let param = program.definition(a).unwrap();
+        assert_eq!(program.symbols[param].kind, SymbolKind::Param(Type::Number));
+    }
+
+    #[test]
+    fn names_are_bound_after_their_statement() {
+        let program = parse("let x: number = x").unwrap();
+        assert_eq!(program.symbols[0].kind, SymbolKind::Use(None));
+        assert!(program.ty(0).is_none());
+    }
+
+    #[test]
+    fn records_comments() {
+        let program = parse("# The answer.\nlet x: number = 42 # Or is it?  \n").unwrap();
+        assert_eq!(program.stmts.len(), 1);
+        let comments = program
+            .comments
+            .iter()
+            .map(|comment| {
+                (
+                    comment.text.as_str(),
+                    comment.span.start,
+                    comment.span.end.column,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            [
+                ("# The answer.", Position { line: 0, column: 0 }, 13),
+                (
+                    "# Or is it?",
+                    Position {
+                        line: 1,
+                        column: 19
+                    },
+                    30
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_position_of_syntax_errors() {
+        let err = parse("let x: number = 1\nlet y = 2").unwrap_err();
+        assert_eq!(err.to_string(), "2:7: Expected `:`, found `=`");
+        let err = parse("let x: number = 1 $").unwrap_err();
+        assert_eq!(err.to_string(), "1:19: Unexpected character: '$'");
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth| {
+            format!(
+                "let x: number = {}1{}",
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        assert!(parse(&nested(MAX_NESTING)).is_ok());
+        let err = parse(&nested(100_000)).unwrap_err();
+        assert_eq!(err.to_string(), "1:274: Nested more than 256 deep");
+    }
+
+    #[test]
+    fn limits_chains() {
+        let chain = |terms| format!("let x: number = 1{}", " + 1".repeat(terms - 1));
+        // The first term is a literal in an `Expr::Factor`, and each operator adds one.
+        assert!(parse(&chain(MAX_HEIGHT - 1)).is_ok());
+        assert!(parse(&chain(MAX_HEIGHT)).is_err());
+        let err = parse(&chain(20_000)).unwrap_err();
+        assert_eq!(err.to_string(), "1:17: Nested more than 1024 deep");
+        let products = format!("let x: number = 1{}", " * 1".repeat(MAX_HEIGHT));
+        assert!(parse(&products).is_err());
+    }
+}
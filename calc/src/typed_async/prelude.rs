@@ -0,0 +1,33 @@
+//! The math functions and constants of the [common prelude](crate::common::prelude)
+//! as values, bound in every new
+//! [`Evaluator`](crate::typed_async::evaluator::Evaluator), and whose types
+//! every new [`TypeChecker`](crate::typed_async::tc::TypeChecker) knows.
+use std::sync::Arc;
+
+use crate::{
+    common::prelude::{self, Call},
+    typed_async::{
+        ast::Type,
+        runtime::{Env, Native, Value},
+    },
+};
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn native(name: &'static str, arity: usize, call: Call) -> Value {
+    Value::Native(Native {
+        name: name.to_owned(),
+        signature: Type::Arrow(vec![Type::Number; arity], Box::new(Type::Number)),
+        fun: Arc::new(move |args| Ok(Value::Number(call(&prelude::numbers(name, args, number)?)))),
+    })
+}
+
+/// The built-in functions and constants, by name.
+pub fn bindings() -> Env {
+    prelude::bindings(native, Value::Number)
+}
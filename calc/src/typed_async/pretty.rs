@@ -0,0 +1,363 @@
+//! A pretty printer in the style of Wadler's "A prettier printer".
+//!
+//! Unlike the [`Unparser`](crate::typed_async::unparser::Unparser), which prints each
+//! statement on one line exactly as the tree is shaped, this breaks lines to
+//! fit a width and adds the parentheses needed for the output to parse back
+//! into the same tree.  Comments recorded by the parser are kept.
+use crate::typed_async::{
+    ast::{Expr, Factor, FactorBinaryOp, Stmt, TermBinaryOp},
+    parser::Program,
+    unparser::INDENT_WIDTH,
+};
+
+/// Width that `calc fmt` fits lines to unless told otherwise.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// A document that can be laid out at different widths.
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    /// A space, or a newline if the enclosing group doesn't fit.
+    Line,
+    /// Nothing, or a newline if the enclosing group doesn't fit.
+    SoftLine,
+    /// Indents the lines broken inside by this many more columns.
+    Nest(usize, Box<Doc>),
+    Concat(Vec<Doc>),
+    /// Lays out its lines flat if they fit, and breaks all of them otherwise.
+    Group(Box<Doc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn text(text: impl Into<String>) -> Doc {
+    Doc::Text(text.into())
+}
+
+fn nest(doc: Doc) -> Doc {
+    Doc::Nest(INDENT_WIDTH, Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+/// `docs` separated by commas and lines.
+fn comma_separated(docs: impl IntoIterator<Item = Doc>) -> Doc {
+    let mut out = Vec::new();
+    for (i, doc) in docs.into_iter().enumerate() {
+        if i > 0 {
+            out.push(text(","));
+            out.push(Doc::Line);
+        }
+        out.push(doc);
+    }
+    Doc::Concat(out)
+}
+
+fn parenthesized(doc: Doc) -> Doc {
+    Doc::Concat(vec![text("("), doc, text(")")])
+}
+
+impl Doc {
+    /// Lays out the document in `width` columns where possible.
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, Mode::Break, self)];
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line | Doc::SoftLine if mode == Mode::Flat => {
+                    if let Doc::Line = doc {
+                        out.push(' ');
+                        column += 1;
+                    }
+                }
+                Doc::Line | Doc::SoftLine => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::Nest(more, doc) => stack.push((indent + more, mode, doc)),
+                Doc::Concat(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+                }
+                Doc::Group(doc) => {
+                    let flat =
+                        mode == Mode::Flat || fits(width.saturating_sub(column), doc, &stack);
+                    let mode = if flat { Mode::Flat } else { Mode::Break };
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Whether `doc` laid out flat, and whatever follows it up to the next
+/// line break, fit in `width` columns.
+fn fits(width: usize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut width = width as isize;
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+    while width >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => width -= text.chars().count() as isize,
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => width -= 1,
+            Doc::SoftLine => {}
+            Doc::Nest(_, doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+        }
+    }
+    false
+}
+
+pub fn stmt(stmt: &Stmt) -> Doc {
+    match stmt {
+        Stmt::Let(name, ty, expr) => group(Doc::Concat(vec![
+            text(format!("let {name}: {ty} =")),
+            nest(Doc::Concat(vec![Doc::Line, self::expr(expr)])),
+        ])),
+        Stmt::Fun(name, fun) => {
+            let params = fun
+                .params
+                .iter()
+                .map(|param| text(format!("{}: {}", param.name, param.ty)));
+            let signature = group(Doc::Concat(vec![
+                text(format!("fun {name}(")),
+                nest(Doc::Concat(vec![Doc::SoftLine, comma_separated(params)])),
+                Doc::SoftLine,
+                text(")"),
+            ]));
+            group(Doc::Concat(vec![
+                signature,
+                text(format!(": {} =", fun.return_ty)),
+                nest(Doc::Concat(vec![Doc::Line, expr(&fun.body)])),
+            ]))
+        }
+    }
+}
+
+/// `lhs op rhs`, breaking before the operator.
+fn binary_op(lhs: Doc, op: &str, rhs: Doc) -> Doc {
+    group(Doc::Concat(vec![
+        lhs,
+        nest(Doc::Concat(vec![Doc::Line, text(format!("{op} ")), rhs])),
+    ]))
+}
+
+pub fn expr(expr: &Expr) -> Doc {
+    match expr {
+        Expr::Factor(f) => factor(f),
+        Expr::BinaryOp { op, lhs, rhs } => {
+            let op = match op {
+                TermBinaryOp::Add => "+",
+                TermBinaryOp::Sub => "-",
+            };
+            // Operators associate to the left, so a nested operation on
+            // the right needs parentheses.
+            let rhs = match rhs.as_ref() {
+                Expr::BinaryOp { .. } => parenthesized(self::expr(rhs)),
+                Expr::Factor(_) => self::expr(rhs),
+            };
+            binary_op(self::expr(lhs), op, rhs)
+        }
+    }
+}
+
+pub fn factor(factor: &Factor) -> Doc {
+    match factor {
+        Factor::Literal(value) => text(value.to_string()),
+        Factor::Variable(name) => text(name),
+        Factor::Group(expr) => parenthesized(self::expr(expr)),
+        Factor::BinaryOp { op, lhs, rhs } => {
+            let op = match op {
+                FactorBinaryOp::Mul => "*",
+                FactorBinaryOp::Div => "/",
+            };
+            let rhs = match rhs.as_ref() {
+                Factor::BinaryOp { .. } => parenthesized(self::factor(rhs)),
+                _ => self::factor(rhs),
+            };
+            binary_op(self::factor(lhs), op, rhs)
+        }
+        Factor::Call(fun, args) => {
+            // Calls bind tighter than operators.
+            let fun = match fun.as_ref() {
+                Factor::BinaryOp { .. } => parenthesized(self::factor(fun)),
+                _ => self::factor(fun),
+            };
+            group(Doc::Concat(vec![
+                fun,
+                text("("),
+                nest(Doc::Concat(vec![
+                    Doc::SoftLine,
+                    comma_separated(args.iter().map(self::expr)),
+                ])),
+                Doc::SoftLine,
+                text(")"),
+            ]))
+        }
+    }
+}
+
+/// Adds a blank line if there were any between `last_line` and `line`.
+fn blank_lines(out: &mut String, line: usize, last_line: Option<usize>) {
+    if last_line.is_some_and(|last_line| line > last_line + 1) {
+        out.push('\n');
+    }
+}
+
+/// Prints `program` with lines fit to `width` where possible.
+///
+/// Comments before a statement, or inside it but not on its last line, go on
+/// their own lines before it, and a comment on its last line goes after it.
+/// One blank line is kept wherever there were any.
+pub fn format(program: &Program, width: usize) -> String {
+    let mut out = String::new();
+    let mut comments = program.comments.iter().peekable();
+    // Line of the source last printed.
+    let mut last_line = None;
+    for (stmt, span) in program.stmts.iter().zip(&program.spans) {
+        let span = span.span;
+        while let Some(comment) = comments.next_if(|comment| {
+            comment.span.start < span.start || comment.span.start.line < span.end.line
+        }) {
+            blank_lines(&mut out, comment.span.start.line, last_line);
+            out.push_str(&comment.text);
+            out.push('\n');
+            last_line = Some(comment.span.start.line);
+        }
+        blank_lines(&mut out, span.start.line, last_line);
+        out.push_str(&self::stmt(stmt).render(width));
+        if let Some(comment) = comments.next_if(|comment| comment.span.start.line == span.end.line)
+        {
+            out.push(' ');
+            out.push_str(&comment.text);
+        }
+        out.push('\n');
+        last_line = Some(span.end.line);
+    }
+    for comment in comments {
+        blank_lines(&mut out, comment.span.start.line, last_line);
+        out.push_str(&comment.text);
+        out.push('\n');
+        last_line = Some(comment.span.start.line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed_async::parser::parse;
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn sub(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::BinaryOp {
+            op: TermBinaryOp::Sub,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    /// Formats `source` and checks that the result parses to the same tree
+    /// and formats to itself.
+    fn reformat(source: &str, width: usize) -> String {
+        let program = parse(source).unwrap();
+        let formatted = format(&program, width);
+        let reparsed = parse(&formatted).unwrap();
+        assert_eq!(
+            std::format!("{:?}", reparsed.stmts),
+            std::format!("{:?}", program.stmts)
+        );
+        assert_eq!(format(&reparsed, width), formatted);
+        formatted
+    }
+
+    #[test]
+    fn parenthesizes_only_where_needed() {
+        let left = sub(sub(var("a"), var("b")), var("c"));
+        assert_eq!(expr(&left).render(80), "a - b - c");
+        let right = sub(var("a"), sub(var("b"), var("c")));
+        assert_eq!(expr(&right).render(80), "a - (b - c)");
+
+        let source = "let x: number = a*(b/c) - f(x)(y)*2\n";
+        assert_eq!(
+            reformat(source, 80),
+            "let x: number = a * (b / c) - f(x)(y) * 2\n"
+        );
+    }
+
+    #[test]
+    fn breaks_lines_to_fit_width() {
+        let source = "fun average(first: number, second: number, third: number): number = (first + second + third) / 3\nlet y: number = average(1, 2, 3)\n";
+        assert_eq!(reformat(source, 100), source);
+        assert_eq!(
+            reformat(source, 70),
+            "\
+fun average(first: number, second: number, third: number): number =
+  (first + second + third) / 3
+let y: number = average(1, 2, 3)
+"
+        );
+        assert_eq!(
+            reformat(source, 20),
+            "\
+fun average(
+  first: number,
+  second: number,
+  third: number
+): number =
+  (first + second
+    + third)
+    / 3
+let y: number =
+  average(1, 2, 3)
+"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "\
+# Inputs.
+let x: number = 1 # One.
+
+
+let y: number = # Two,
+  x + 1
+# The end.
+";
+        assert_eq!(
+            reformat(source, 80),
+            "\
+# Inputs.
+let x: number = 1 # One.
+
+# Two,
+let y: number = x + 1
+# The end.
+"
+        );
+    }
+}
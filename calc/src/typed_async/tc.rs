@@ -2,27 +2,47 @@
 
 use std::collections::HashMap;
 
-use crate::{
+use crate::typed_async::{
     ast::{Expr, Factor, Identifier, Stmt, Type},
-    runtime::Error,
+    prelude,
+    runtime::{self, Error},
 };
 
-pub(crate) type Env = HashMap<Identifier, Type>;
+pub type Env = HashMap<Identifier, Type>;
 
-#[derive(Debug, Default)]
-pub(crate) struct TypeChecker {
+#[derive(Debug)]
+pub struct TypeChecker {
+    /// Starts out with the types of the built-ins from [`prelude`].
     pub bindings: Env,
 }
 
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new(&prelude::bindings())
+    }
+}
+
 impl TypeChecker {
-    pub(crate) fn check_stmts(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+    /// A type checker that knows the types of `values`, such as the bindings
+    /// of an [`Evaluator`](crate::typed_async::evaluator::Evaluator) with
+    /// functions registered by the host.
+    pub fn new(values: &runtime::Env) -> Self {
+        Self {
+            bindings: values
+                .iter()
+                .map(|(name, value)| (name.clone(), value.ty()))
+                .collect(),
+        }
+    }
+
+    pub fn check_stmts(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
         for stmt in stmts {
             self.check_stmt(stmt)?;
         }
         Ok(())
     }
 
-    pub(crate) fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    pub fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
         match stmt {
             Stmt::Let(ident, ty, expr) => {
                 let expr_ty = self.check_expr(expr)?;
@@ -41,14 +61,19 @@ impl TypeChecker {
                     Box::new(function.return_ty.clone()),
                 );
                 let body_ty = {
+                    let mut shadowed = Vec::with_capacity(function.params.len());
                     for param in &function.params {
-                        self.bindings.insert(param.name.clone(), param.ty.clone());
+                        let outer = self.bindings.insert(param.name.clone(), param.ty.clone());
+                        shadowed.push((&param.name, outer));
                     }
                     // Check the function body with parameters in scope.
                     let result = self.check_expr(&function.body);
-                    // Remove parameters.
-                    for param in function.params.iter().rev() {
-                        self.bindings.remove(&param.name);
+                    // Remove parameters, bringing back any bindings they shadowed.
+                    for (name, outer) in shadowed.into_iter().rev() {
+                        match outer {
+                            Some(ty) => self.bindings.insert(name.clone(), ty),
+                            None => self.bindings.remove(name),
+                        };
                     }
                     result
                 }?;
@@ -65,7 +90,7 @@ impl TypeChecker {
         Ok(())
     }
 
-    pub(crate) fn check_expr(&mut self, expr: &Expr) -> Result<Type, Error> {
+    pub fn check_expr(&mut self, expr: &Expr) -> Result<Type, Error> {
         match expr {
             Expr::Factor(factor) => self.check_factor(factor),
             Expr::BinaryOp { op: _op, lhs, rhs } => {
@@ -88,7 +113,7 @@ impl TypeChecker {
         }
     }
 
-    pub(crate) fn check_factor(&mut self, factor: &Factor) -> Result<Type, Error> {
+    pub fn check_factor(&mut self, factor: &Factor) -> Result<Type, Error> {
         match factor {
             Factor::Literal(_) => Ok(Type::Number),
             Factor::Variable(ident) => self
@@ -1,33 +1,40 @@
 //! An example of a static analysis.
 use std::fmt::Write;
 
-use crate::ast::{Expr, Factor, FactorBinaryOp, Stmt, TermBinaryOp, Type};
+use crate::typed_async::ast::{Expr, Factor, FactorBinaryOp, Stmt, TermBinaryOp, Type};
 
 #[derive(Debug, Default)]
-pub(crate) struct Unparser {
+pub struct Unparser {
     indent: usize,
     out: String,
 }
 
-const INDENT_WIDTH: usize = 2;
+pub const INDENT_WIDTH: usize = 2;
+
+/// Unparses a single node, without the trailing newline of a statement.
+pub fn unparse(f: impl FnOnce(&mut Unparser) -> std::fmt::Result) -> String {
+    let mut unparser = Unparser::default();
+    f(&mut unparser).expect("unparsing to a string never fails");
+    unparser.output().trim_end().to_owned()
+}
 
 impl Unparser {
-    pub(crate) fn output(&self) -> &str {
+    pub fn output(&self) -> &str {
         &self.out
     }
 
-    pub(crate) fn unparse_type(&mut self, ty: &Type) -> Result<(), std::fmt::Error> {
+    pub fn unparse_type(&mut self, ty: &Type) -> Result<(), std::fmt::Error> {
         write!(self.out, "{ty}")
     }
 
-    pub(crate) fn unparse_stmts(&mut self, stmts: &[Stmt]) -> Result<(), std::fmt::Error> {
+    pub fn unparse_stmts(&mut self, stmts: &[Stmt]) -> Result<(), std::fmt::Error> {
         for stmt in stmts {
             self.unparse_stmt(stmt)?;
         }
         Ok(())
     }
 
-    fn unparse_stmt(&mut self, stmt: &Stmt) -> Result<(), std::fmt::Error> {
+    pub fn unparse_stmt(&mut self, stmt: &Stmt) -> Result<(), std::fmt::Error> {
         match stmt {
             Stmt::Let(name, ty, expr) => {
                 write!(
@@ -67,7 +74,7 @@ impl Unparser {
         Ok(())
     }
 
-    fn unparse_expr(&mut self, expr: &Expr) -> Result<(), std::fmt::Error> {
+    pub fn unparse_expr(&mut self, expr: &Expr) -> Result<(), std::fmt::Error> {
         match expr {
             Expr::Factor(factor) => self.unparse_factor(factor)?,
             Expr::BinaryOp { op, lhs, rhs } => {
@@ -88,7 +95,7 @@ impl Unparser {
         }
     }
 
-    fn unparse_factor(&mut self, factor: &Factor) -> Result<(), std::fmt::Error> {
+    pub fn unparse_factor(&mut self, factor: &Factor) -> Result<(), std::fmt::Error> {
         match factor {
             Factor::Literal(value) => write!(self.out, "{}", value)?,
             Factor::Variable(name) => write!(self.out, "{}", name)?,
@@ -0,0 +1,99 @@
+//! Compares running static analyses one after another against running them
+//! fused in a single traversal.
+use std::rc::Rc;
+
+use calc::mutable_self::{
+    analysis::{Analysis, Driver},
+    ast::{Expr, Factor, FactorBinaryOp, Function, Stmt, TermBinaryOp},
+    count, free_vars, lint, unparser,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn var(name: String) -> Factor {
+    Factor::Variable(name)
+}
+
+/// A program of `n` functions, each called by the following `let`.
+fn program(n: usize) -> Vec<Stmt> {
+    let mut stmts = vec![Stmt::Let(
+        "x0".to_owned(),
+        Box::new(Expr::Factor(Box::new(Factor::Literal(0.0)))),
+    )];
+    for i in 1..=n {
+        let body = Expr::BinaryOp {
+            op: TermBinaryOp::Add,
+            lhs: Box::new(Expr::Factor(Box::new(var("a".to_owned())))),
+            rhs: Box::new(Expr::Factor(Box::new(Factor::BinaryOp {
+                op: FactorBinaryOp::Mul,
+                lhs: Box::new(var("b".to_owned())),
+                rhs: Box::new(Factor::Group(Box::new(Expr::BinaryOp {
+                    op: TermBinaryOp::Sub,
+                    lhs: Box::new(Expr::Factor(Box::new(var(format!("x{}", i - 1))))),
+                    rhs: Box::new(Expr::Factor(Box::new(Factor::Literal(i as f64)))),
+                }))),
+            }))),
+        };
+        stmts.push(Stmt::Fun(
+            format!("f{i}"),
+            Rc::new(Function {
+                params: vec!["a".to_owned(), "b".to_owned()],
+                body,
+            }),
+        ));
+        stmts.push(Stmt::Let(
+            format!("x{i}"),
+            Box::new(Expr::Factor(Box::new(Factor::Call(
+                Box::new(var(format!("f{i}"))),
+                vec![
+                    Expr::Factor(Box::new(var(format!("x{}", i - 1)))),
+                    Expr::Factor(Box::new(Factor::Literal(2.0))),
+                ],
+            )))),
+        ));
+    }
+    stmts
+}
+
+fn analyses() -> (
+    unparser::Unparser,
+    free_vars::FreeVars,
+    count::NodeCount,
+    lint::Lint,
+) {
+    Default::default()
+}
+
+fn bench_static_analysis(c: &mut Criterion) {
+    let mut group = c.benchmark_group("static_analysis");
+    for n in [100, 1_000, 10_000] {
+        let stmts = program(n);
+        group.bench_with_input(BenchmarkId::new("sequential", n), &stmts, |b, stmts| {
+            b.iter(|| {
+                let (mut unp, mut free_vars, mut count, mut lint) = analyses();
+                let passes: [&mut dyn Analysis; 4] =
+                    [&mut unp, &mut free_vars, &mut count, &mut lint];
+                for analysis in passes {
+                    let mut driver = Driver {
+                        analyses: vec![analysis],
+                    };
+                    driver.run_stmts(black_box(stmts)).unwrap();
+                }
+                black_box((unp, free_vars, count, lint))
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("fused", n), &stmts, |b, stmts| {
+            b.iter(|| {
+                let (mut unp, mut free_vars, mut count, mut lint) = analyses();
+                let mut driver = Driver {
+                    analyses: vec![&mut unp, &mut free_vars, &mut count, &mut lint],
+                };
+                driver.run_stmts(black_box(stmts)).unwrap();
+                black_box((unp, free_vars, count, lint))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_static_analysis);
+criterion_main!(benches);
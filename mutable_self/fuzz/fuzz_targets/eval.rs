@@ -0,0 +1,192 @@
+//! Evaluates arbitrary programs with visitors that fail to write their logs
+//! or fail on purpose.
+#![no_main]
+
+use std::{io::Write, ops::ControlFlow};
+
+use arbitrary::Unstructured;
+use calc::mutable_self::{
+    ast::{Expr, Factor, Identifier, Stmt},
+    evaluator::Evaluator,
+    log,
+    runtime::{Env, Error, Value, Visit},
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Nodes evaluated before giving up, since calls can take exponential time.
+const FUEL: usize = 10_000;
+
+/// Most visitors besides [`Fuel`].
+const MAX_VISITORS: u32 = 4;
+
+/// Fails once it has visited [`FUEL`] nodes.
+#[derive(Debug)]
+struct Fuel(usize);
+
+impl Fuel {
+    fn burn(&mut self) -> Result<Option<Value>, Error> {
+        if self.0 == 0 {
+            return Err("Out of fuel".into());
+        }
+        self.0 -= 1;
+        Ok(None)
+    }
+}
+
+impl Visit for Fuel {
+    fn pre_visit_stmt(&mut self, _stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.burn()
+    }
+
+    fn pre_visit_expr(&mut self, _expr: &Expr) -> Result<Option<Value>, Error> {
+        self.burn()
+    }
+
+    fn pre_visit_factor(&mut self, _factor: &Factor) -> Result<Option<Value>, Error> {
+        self.burn()
+    }
+}
+
+/// Fails the calls of its hooks whose numbers, counting from 1, are in
+/// `faults`.  Post-visits fail even if evaluation already did.
+#[derive(Debug)]
+struct Faults {
+    calls: usize,
+    faults: Vec<usize>,
+}
+
+impl Faults {
+    fn call(&mut self) -> Result<(), Error> {
+        self.calls += 1;
+        if self.faults.contains(&self.calls) {
+            return Err(format!("Fault in hook call {}", self.calls).into());
+        }
+        Ok(())
+    }
+}
+
+impl Visit for Faults {
+    fn pre_visit_stmt(&mut self, _stmt: &Stmt) -> Result<Option<Value>, Error> {
+        self.call()?;
+        Ok(None)
+    }
+
+    fn post_visit_stmt(
+        &mut self,
+        _stmt: &Stmt,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.call()?;
+        result
+    }
+
+    fn pre_visit_expr(&mut self, _expr: &Expr) -> Result<Option<Value>, Error> {
+        self.call()?;
+        Ok(None)
+    }
+
+    fn post_visit_expr(
+        &mut self,
+        _expr: &Expr,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.call()?;
+        result
+    }
+
+    fn pre_visit_factor(&mut self, _factor: &Factor) -> Result<Option<Value>, Error> {
+        self.call()?;
+        Ok(None)
+    }
+
+    fn post_visit_factor(
+        &mut self,
+        _factor: &Factor,
+        result: Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        self.call()?;
+        result
+    }
+
+    fn on_call(&mut self, _fun: &Identifier, _args: &[Value], _env: &Env) -> Result<(), Error> {
+        self.call()
+    }
+
+    fn on_return(&mut self, _value: &Value) -> Result<(), Error> {
+        self.call()
+    }
+
+    fn on_bind(&mut self, _name: &Identifier, _value: &Value) -> Result<(), Error> {
+        self.call()
+    }
+
+    fn on_lookup(&mut self, _name: &Identifier) -> Result<(), Error> {
+        self.call()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.call()
+    }
+}
+
+/// Fails every write after the first `remaining`.
+struct Flaky {
+    remaining: usize,
+}
+
+impl Write for Flaky {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return Err(std::io::Error::other("Flaky write"));
+        }
+        self.remaining -= 1;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// [`Fuel`], and [`log::Print`]s, [`log::JsonLines`] and [`Faults`] in
+/// arbitrary places around it.
+fn visitors(u: &mut Unstructured, stmts: &[Stmt]) -> arbitrary::Result<Vec<Box<dyn Visit>>> {
+    let mut visitors: Vec<Box<dyn Visit>> = vec![Box::new(Fuel(FUEL))];
+    u.arbitrary_loop(None, Some(MAX_VISITORS), |u| {
+        let visitor: Box<dyn Visit> = match u.choose_index(3)? {
+            0 => {
+                let remaining = u.int_in_range(0..=64)?;
+                Box::new(log::Print::new(Flaky { remaining }))
+            }
+            1 => {
+                let remaining = u.int_in_range(0..=64)?;
+                Box::new(log::JsonLines::new(Flaky { remaining }, stmts))
+            }
+            _ => {
+                let mut faults = Vec::new();
+                u.arbitrary_loop(None, Some(4), |u| {
+                    faults.push(u.int_in_range(1..=256)?);
+                    Ok(ControlFlow::Continue(()))
+                })?;
+                Box::new(Faults { calls: 0, faults })
+            }
+        };
+        let index = u.choose_index(visitors.len() + 1)?;
+        visitors.insert(index, visitor);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(visitors)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let Ok(stmts) = calc::fuzz::mutable_self::stmts(&mut u) else {
+        return;
+    };
+    let Ok(visitors) = visitors(&mut u, &stmts) else {
+        return;
+    };
+    let mut evaluator = Evaluator::default();
+    evaluator.visitors = visitors;
+    let _ = evaluator.eval_stmts(&stmts);
+});
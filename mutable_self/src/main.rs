@@ -1,12 +1,18 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    rc::Rc,
+};
+
+use calc::mutable_self::{
+    analysis, ast, count, coverage, debugger, evaluator, free_vars, lint, log, profile, trace,
+    unparser,
+};
+
 use ast::{Expr, Factor};
 use evaluator::Evaluator;
 
-mod ast;
-mod evaluator;
-mod log;
-mod runtime;
-mod unparser;
-
 fn main() {
     let stmts = [
         ast::Stmt::Let(
@@ -23,7 +29,7 @@ fn main() {
         ),
         ast::Stmt::Fun(
             "add".to_owned(),
-            Box::new(ast::Function {
+            Rc::new(ast::Function {
                 params: vec!["a".to_string(), "b".to_string()],
                 body: Expr::BinaryOp {
                     op: ast::TermBinaryOp::Add,
@@ -44,8 +50,41 @@ fn main() {
         ),
     ];
 
+    let args = std::env::args().collect::<Vec<_>>();
     let mut evaluator = Evaluator::default();
     evaluator.visitors.push(Box::new(log::Print::default()));
+    if let Some(path) = path_after(&args, "--log") {
+        let jsonl = create(path);
+        evaluator
+            .visitors
+            .push(Box::new(log::JsonLines::new(jsonl, &stmts)));
+    }
+    evaluator
+        .visitors
+        .push(Box::new(profile::Profile::default()));
+    if let Some(path) = path_after(&args, "--trace") {
+        // Folded stacks go next to the Chrome trace.
+        let folded = Path::new(path).with_extension("folded");
+        evaluator
+            .visitors
+            .push(Box::new(trace::Trace::new(path, folded)));
+    }
+    if let Some(path) = path_after(&args, "--coverage") {
+        // The LCOV report refers to the program's source by this path.
+        let source = Path::new(path).with_file_name("program.calc");
+        evaluator
+            .visitors
+            .push(Box::new(coverage::Coverage::new(&stmts, source, path)));
+    }
+    if std::env::args().any(|arg| arg == "--debug") {
+        let debugger = debugger::Debugger::new(
+            io::stdin().lock(),
+            io::stdout(),
+            &stmts,
+            &evaluator.bindings,
+        );
+        evaluator.visitors.push(Box::new(debugger));
+    }
     let result = evaluator.eval_stmts(&stmts);
     println!("{:?}", result);
     println!("{:#?}", evaluator.bindings);
@@ -54,4 +93,35 @@ fn main() {
     unp.unparse_stmts(&stmts).unwrap();
     println!();
     println!("{}", unp.output());
+
+    // Run several static analyses in a single pass.
+    let mut fused_unp = unparser::Unparser::default();
+    let mut free_vars = free_vars::FreeVars::default();
+    let mut count = count::NodeCount::default();
+    let mut lint = lint::Lint::default();
+    let mut driver = analysis::Driver {
+        analyses: vec![&mut fused_unp, &mut free_vars, &mut count, &mut lint],
+    };
+    driver.run_stmts(&stmts).unwrap();
+    println!("{}", fused_unp.output());
+    println!("{:?}", free_vars.free);
+    println!("{:?}", count);
+    println!("{:?}", lint.warnings);
+}
+
+/// The path after `flag` on the command line, if it's there.
+fn path_after<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(i + 1).expect("expected a path after the flag"))
+}
+
+/// Creates the output file at `path`, or exits if it can't.
+fn create(path: &str) -> BufWriter<File> {
+    match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Can't create {path}: {err}");
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,650 @@
+//! A [Debug Adapter Protocol](https://microsoft.github.io/debug-adapter-protocol/)
+//! server on top of [`Debugger`].
+//!
+//! Supports launch, breakpoints by line and function name, threads, stack
+//! traces, scopes and variables, stepping and continuing.  Closures can be
+//! expanded to show their captured environments.  There's no source file, so
+//! frames refer to the unparsed program, which clients fetch with a `source`
+//! request.
+//!
+//! This variant has no parser, so the server debugs the program it was started
+//! with, and `launch` only takes `stopOnEntry`.  A `program` to launch is
+//! refused rather than ignored.
+use std::sync::Arc;
+
+use calc::protocol::{read_message, write_message};
+use serde_json::{json, Value as Json};
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::{
+    ast::Stmt,
+    debugger::{Breakpoint, Command, Controller, Debugger, Paused},
+    evaluator::Evaluator,
+    runtime::{Env, Error, Value},
+    unparser::Unparser,
+};
+
+/// The only thread, since evaluation is sequential.
+const THREAD_ID: u64 = 1;
+
+/// Reference that clients pass to a `source` request for the program.
+const SOURCE_REFERENCE: u64 = 1;
+
+/// What the main loop should do after a request.
+enum Flow {
+    Continue,
+    /// Start evaluating.
+    Run,
+    Disconnect,
+}
+
+struct Server<W> {
+    output: W,
+    seq: u64,
+    stmts: Arc<Vec<Stmt>>,
+    commands: mpsc::UnboundedSender<Command>,
+    stop_on_entry: bool,
+    /// Why evaluation will stop next, e.g. `step` after a step request.
+    stop_reason: &'static str,
+    line_breakpoints: Vec<Breakpoint>,
+    function_breakpoints: Vec<Breakpoint>,
+    paused: Option<Paused>,
+    /// Environments handed out as variable references while paused.  The
+    /// reference of an environment is its index plus one.
+    references: Vec<Env>,
+}
+
+impl<W: AsyncWrite + Unpin> Server<W> {
+    async fn send(&mut self, mut message: Json) -> Result<(), Error> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message).await
+    }
+
+    async fn respond(&mut self, request: &Json, body: Json) -> Result<(), Error> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+        .await
+    }
+
+    async fn respond_error(&mut self, request: &Json, message: String) -> Result<(), Error> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+        .await
+    }
+
+    async fn event(&mut self, event: &str, body: Json) -> Result<(), Error> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+            .await
+    }
+
+    fn source(&self) -> Result<String, Error> {
+        let mut unparser = Unparser::default();
+        unparser.unparse_stmts(&self.stmts)?;
+        Ok(unparser.output().to_owned())
+    }
+
+    fn reference(&mut self, env: Env) -> usize {
+        self.references.push(env);
+        self.references.len()
+    }
+
+    fn resume(&mut self, command: Command, stop_reason: &'static str) -> Result<(), Error> {
+        self.paused = None;
+        self.references.clear();
+        self.stop_reason = stop_reason;
+        self.commands.send(command)?;
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self) -> Result<(), Error> {
+        let breakpoints = self
+            .line_breakpoints
+            .iter()
+            .chain(&self.function_breakpoints)
+            .cloned()
+            .collect();
+        self.commands.send(Command::SetBreakpoints(breakpoints))?;
+        Ok(())
+    }
+
+    async fn handle(&mut self, request: Json) -> Result<Flow, Error> {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                });
+                self.respond(&request, capabilities).await?;
+                self.event("initialized", json!({})).await?;
+            }
+            "launch" => {
+                if !arguments["program"].is_null() {
+                    let message = "Can't launch a program, only the one the server was \
+                                   started with"
+                        .to_owned();
+                    self.respond_error(&request, message).await?;
+                    return Ok(Flow::Continue);
+                }
+                self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                self.respond(&request, json!({})).await?;
+            }
+            "setBreakpoints" => {
+                let lines = self.source()?.lines().count();
+                let requested = arguments["breakpoints"].as_array().cloned();
+                let mut breakpoints = Vec::new();
+                self.line_breakpoints.clear();
+                for breakpoint in requested.unwrap_or_default() {
+                    let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+                    let verified = (1..=lines).contains(&line);
+                    if verified {
+                        self.line_breakpoints.push(Breakpoint::Line(line));
+                    }
+                    breakpoints.push(json!({ "verified": verified, "line": line }));
+                }
+                self.set_breakpoints()?;
+                self.respond(&request, json!({ "breakpoints": breakpoints }))
+                    .await?;
+            }
+            "setFunctionBreakpoints" => {
+                let requested = arguments["breakpoints"].as_array().cloned();
+                let mut breakpoints = Vec::new();
+                self.function_breakpoints.clear();
+                for breakpoint in requested.unwrap_or_default() {
+                    let name = breakpoint["name"].as_str().unwrap_or_default();
+                    self.function_breakpoints
+                        .push(Breakpoint::Function(name.to_owned()));
+                    breakpoints.push(json!({ "verified": true }));
+                }
+                self.set_breakpoints()?;
+                self.respond(&request, json!({ "breakpoints": breakpoints }))
+                    .await?;
+            }
+            "configurationDone" => {
+                if !self.stop_on_entry {
+                    self.resume(Command::Continue, "breakpoint")?;
+                }
+                self.respond(&request, json!({})).await?;
+                return Ok(Flow::Run);
+            }
+            "threads" => {
+                let threads = json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] });
+                self.respond(&request, threads).await?;
+            }
+            "source" => {
+                let content = self.source()?;
+                self.respond(&request, json!({ "content": content }))
+                    .await?;
+            }
+            "stackTrace" => {
+                let Some(paused) = &self.paused else {
+                    return self.not_paused(&request).await;
+                };
+                let mut frames = Vec::new();
+                for (id, scope) in paused.scopes.iter().enumerate().rev() {
+                    let line = match paused.scopes.get(id + 1) {
+                        Some(callee) => callee.call_line,
+                        None => paused.location.line,
+                    };
+                    frames.push(json!({
+                        "id": id,
+                        "name": scope.name,
+                        "line": line,
+                        "column": 1,
+                        "source": { "name": "program.calc", "sourceReference": SOURCE_REFERENCE },
+                    }));
+                }
+                let body = json!({ "stackFrames": frames, "totalFrames": frames.len() });
+                self.respond(&request, body).await?;
+            }
+            "scopes" => {
+                let Some(paused) = &self.paused else {
+                    return self.not_paused(&request).await;
+                };
+                let id = arguments["frameId"].as_u64().unwrap_or_default() as usize;
+                let Some(scope) = paused.scopes.get(id) else {
+                    let message = format!("Unknown frame {id}");
+                    self.respond_error(&request, message).await?;
+                    return Ok(Flow::Continue);
+                };
+                let name = if id == 0 { "Globals" } else { "Locals" };
+                let env = scope.env.clone();
+                let reference = self.reference(env);
+                let scopes = json!({ "scopes": [{
+                    "name": name,
+                    "variablesReference": reference,
+                    "expensive": false,
+                }] });
+                self.respond(&request, scopes).await?;
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                let Some(env) = self.references.get((reference as usize).wrapping_sub(1)) else {
+                    let message = format!("Unknown variables reference {reference}");
+                    self.respond_error(&request, message).await?;
+                    return Ok(Flow::Continue);
+                };
+                let mut bindings = env.clone().into_iter().collect::<Vec<_>>();
+                bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+                let mut variables = Vec::new();
+                for (name, value) in bindings {
+                    let reference = match &value {
+                        Value::Number(_) | Value::Native(_) => 0,
+                        Value::Closure(_, _, captured) => self.reference(captured.clone()),
+                    };
+                    variables.push(json!({
+                        "name": name,
+                        "value": value.to_string(),
+                        "variablesReference": reference,
+                    }));
+                }
+                self.respond(&request, json!({ "variables": variables }))
+                    .await?;
+            }
+            command @ ("next" | "stepIn" | "stepOut" | "continue") => {
+                if self.paused.is_none() {
+                    return self.not_paused(&request).await;
+                }
+                let (command, reason) = match command {
+                    "next" => (Command::StepOver, "step"),
+                    "stepIn" => (Command::StepInto, "step"),
+                    "stepOut" => (Command::StepOut, "step"),
+                    _ => (Command::Continue, "breakpoint"),
+                };
+                self.resume(command, reason)?;
+                self.respond(&request, json!({ "allThreadsContinued": true }))
+                    .await?;
+            }
+            "disconnect" => {
+                self.respond(&request, json!({})).await?;
+                return Ok(Flow::Disconnect);
+            }
+            command => {
+                let message = format!("Unsupported command: {command}");
+                self.respond_error(&request, message).await?;
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    async fn not_paused(&mut self, request: &Json) -> Result<Flow, Error> {
+        let message = "Evaluation isn't paused".to_owned();
+        self.respond_error(request, message).await?;
+        Ok(Flow::Continue)
+    }
+
+    async fn stopped(&mut self, paused: Paused) -> Result<(), Error> {
+        self.paused = Some(paused);
+        let body = json!({
+            "reason": self.stop_reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        self.event("stopped", body).await
+    }
+
+    async fn finished(&mut self, result: Result<Value, Error>) -> Result<(), Error> {
+        let (output, exit_code) = match result {
+            Ok(value) => (format!("{value}\n"), 0),
+            Err(err) => (format!("{err}\n"), 1),
+        };
+        let body = json!({ "category": "console", "output": output });
+        self.event("output", body).await?;
+        self.event("exited", json!({ "exitCode": exit_code }))
+            .await?;
+        self.event("terminated", json!({})).await
+    }
+}
+
+/// Debugs `stmts` for a client that sends requests to `input` and reads
+/// responses and events from `output`.  Returns when the client disconnects
+/// or closes `input`.
+pub(crate) async fn serve(
+    stmts: Arc<Vec<Stmt>>,
+    input: impl AsyncBufRead + Unpin + Send + 'static,
+    output: impl AsyncWrite + Unpin,
+) -> Result<(), Error> {
+    // Read on a separate task, since reading a message isn't cancellation
+    // safe.
+    let (requests_tx, mut requests) = mpsc::unbounded_channel();
+    let reader = tokio::spawn(async move {
+        let mut input = input;
+        while let Some(message) = read_message(&mut input).await? {
+            if requests_tx.send(message).is_err() {
+                break;
+            }
+        }
+        Ok::<_, Error>(())
+    });
+
+    let evaluator = Evaluator::default();
+    let (
+        debugger,
+        Controller {
+            mut paused,
+            commands,
+        },
+    ) = Debugger::new(&stmts, &evaluator.bindings);
+    let mut debugger = Some((debugger, evaluator));
+    let mut server = Server {
+        output,
+        seq: 0,
+        stmts: Arc::clone(&stmts),
+        commands,
+        stop_on_entry: false,
+        stop_reason: "entry",
+        line_breakpoints: Vec::new(),
+        function_breakpoints: Vec::new(),
+        paused: None,
+        references: Vec::new(),
+    };
+    let mut evaluation: Option<JoinHandle<Result<Value, Error>>> = None;
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    break;
+                };
+                match server.handle(request).await? {
+                    Flow::Continue => {}
+                    Flow::Run => {
+                        let Some((debugger, mut evaluator)) = debugger.take() else {
+                            continue;
+                        };
+                        let stmts = Arc::clone(&stmts);
+                        evaluation = Some(tokio::spawn(async move {
+                            evaluator.visitors.push(Box::new(debugger));
+                            evaluator.eval_stmts(&stmts).await
+                        }));
+                    }
+                    Flow::Disconnect => break,
+                }
+            }
+            Some(stopped) = paused.recv() => server.stopped(stopped).await?,
+            result = async { evaluation.as_mut().expect("guarded by the condition").await }, if evaluation.is_some() => {
+                evaluation = None;
+                server.finished(result?).await?;
+            }
+        }
+    }
+    if let Some(evaluation) = evaluation {
+        evaluation.abort();
+    }
+    reader.abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Factor, Function, TermBinaryOp};
+    use tokio::io::{BufReader, DuplexStream, ReadHalf, WriteHalf};
+
+    /// A scripted client talking to a server over an in-memory pipe.
+    struct Client {
+        input: BufReader<ReadHalf<DuplexStream>>,
+        output: WriteHalf<DuplexStream>,
+        seq: u64,
+    }
+
+    impl Client {
+        async fn send(&mut self, command: &str, arguments: Json) {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            write_message(&mut self.output, &request).await.unwrap();
+        }
+
+        async fn receive(&mut self) -> Json {
+            read_message(&mut self.input).await.unwrap().unwrap()
+        }
+
+        /// Sends a request and returns the body of its successful response.
+        async fn request(&mut self, command: &str, arguments: Json) -> Json {
+            self.send(command, arguments).await;
+            let response = self.receive().await;
+            assert_eq!(response["type"], "response", "{response}");
+            assert_eq!(response["command"], command, "{response}");
+            assert_eq!(response["success"], true, "{response}");
+            response["body"].clone()
+        }
+
+        async fn expect_event(&mut self, event: &str) -> Json {
+            let message = self.receive().await;
+            assert_eq!(message["type"], "event", "{message}");
+            assert_eq!(message["event"], event, "{message}");
+            message["body"].clone()
+        }
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Factor(Box::new(Factor::Variable(name.to_owned())))
+    }
+
+    fn num(x: f64) -> Expr {
+        Expr::Factor(Box::new(Factor::Literal(x)))
+    }
+
+    /// ```text
+    /// let x = 1
+    /// fun add(a, b) = a + b
+    /// let y = add(x, 2)
+    /// let z = y
+    /// ```
+    fn program() -> Vec<Stmt> {
+        vec![
+            Stmt::Let("x".to_owned(), Box::new(num(1.0))),
+            Stmt::Fun(
+                "add".to_owned(),
+                Arc::new(Function {
+                    params: vec!["a".to_owned(), "b".to_owned()],
+                    body: Expr::BinaryOp {
+                        op: TermBinaryOp::Add,
+                        lhs: Box::new(var("a")),
+                        rhs: Box::new(var("b")),
+                    },
+                }),
+            ),
+            Stmt::Let(
+                "y".to_owned(),
+                Box::new(Expr::Factor(Box::new(Factor::Call(
+                    Box::new(Factor::Variable("add".to_owned())),
+                    vec![var("x"), num(2.0)],
+                )))),
+            ),
+            Stmt::Let("z".to_owned(), Box::new(var("y"))),
+        ]
+    }
+
+    fn connect() -> (Client, JoinHandle<Result<(), Error>>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_input, server_output) = tokio::io::split(server);
+        let server = tokio::spawn(serve(
+            Arc::new(program()),
+            BufReader::new(server_input),
+            server_output,
+        ));
+        let (client_input, client_output) = tokio::io::split(client);
+        let client = Client {
+            input: BufReader::new(client_input),
+            output: client_output,
+            seq: 0,
+        };
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn scripted_session() {
+        let (mut client, server) = connect();
+
+        let capabilities = client.request("initialize", json!({})).await;
+        assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+        client.expect_event("initialized").await;
+        client.request("launch", json!({})).await;
+        let body = client
+            .request(
+                "setBreakpoints",
+                json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 2 }, { "line": 9 }] }),
+            )
+            .await;
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][1]["verified"], false);
+        client.request("configurationDone", json!({})).await;
+
+        // Stops where `add` is defined on line 2, then in its body.
+        let stopped = client.expect_event("stopped").await;
+        assert_eq!(stopped["reason"], "breakpoint");
+        let body = client.request("stackTrace", json!({ "threadId": 1 })).await;
+        assert_eq!(body["stackFrames"][0]["line"], 2);
+        assert_eq!(body["stackFrames"].as_array().unwrap().len(), 1);
+        client.request("continue", json!({ "threadId": 1 })).await;
+        let stopped = client.expect_event("stopped").await;
+        assert_eq!(stopped["reason"], "breakpoint");
+        let body = client.request("threads", json!({})).await;
+        assert_eq!(body["threads"][0]["id"], THREAD_ID);
+        let body = client.request("stackTrace", json!({ "threadId": 1 })).await;
+        let frames = body["stackFrames"].as_array().unwrap().clone();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["name"], "add");
+        assert_eq!(frames[0]["line"], 2);
+        assert_eq!(frames[1]["name"], "<top level>");
+        assert_eq!(frames[1]["line"], 3);
+
+        let body = client
+            .request("scopes", json!({ "frameId": frames[1]["id"] }))
+            .await;
+        let reference = body["scopes"][0]["variablesReference"].clone();
+        let body = client
+            .request("variables", json!({ "variablesReference": reference }))
+            .await;
+        let variables = body["variables"].as_array().unwrap();
+        let names = variables
+            .iter()
+            .map(|v| v["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        // The built-ins are listed with the program's bindings.
+        assert!(names.contains(&"sqrt"));
+        let add = variables.iter().find(|v| v["name"] == "add").unwrap();
+        assert!(variables.iter().any(|v| v["name"] == "x"));
+        // The closure's captured environment can be expanded.
+        let captured = add["variablesReference"].clone();
+        assert_ne!(captured, 0);
+        let body = client
+            .request("variables", json!({ "variablesReference": captured }))
+            .await;
+        let captured = body["variables"].as_array().unwrap();
+        assert!(captured.iter().any(|v| v["name"] == "x"));
+
+        let body = client
+            .request("scopes", json!({ "frameId": frames[0]["id"] }))
+            .await;
+        let reference = body["scopes"][0]["variablesReference"].clone();
+        let body = client
+            .request("variables", json!({ "variablesReference": reference }))
+            .await;
+        let variables = body["variables"].as_array().unwrap();
+        let value = |name: &str| {
+            let variable = variables.iter().find(|v| v["name"] == name).unwrap();
+            variable["value"].clone()
+        };
+        assert_eq!(value("a"), "1");
+        assert_eq!(value("b"), "2");
+
+        client.request("stepIn", json!({ "threadId": 1 })).await;
+        let stopped = client.expect_event("stopped").await;
+        assert_eq!(stopped["reason"], "step");
+        client.request("stepOut", json!({ "threadId": 1 })).await;
+        client.expect_event("stopped").await;
+        let body = client.request("stackTrace", json!({ "threadId": 1 })).await;
+        assert_eq!(body["stackFrames"].as_array().unwrap().len(), 1);
+        assert_eq!(body["stackFrames"][0]["line"], 4);
+
+        client.request("continue", json!({ "threadId": 1 })).await;
+        let output = client.expect_event("output").await;
+        assert_eq!(output["output"], "3\n");
+        let exited = client.expect_event("exited").await;
+        assert_eq!(exited["exitCode"], 0);
+        client.expect_event("terminated").await;
+
+        client.request("disconnect", json!({})).await;
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn function_breakpoints_and_source() {
+        let (mut client, server) = connect();
+
+        client.request("initialize", json!({})).await;
+        client.expect_event("initialized").await;
+        client
+            .request("launch", json!({ "stopOnEntry": true }))
+            .await;
+        client.request("configurationDone", json!({})).await;
+        let stopped = client.expect_event("stopped").await;
+        assert_eq!(stopped["reason"], "entry");
+
+        let body = client
+            .request("source", json!({ "sourceReference": 1 }))
+            .await;
+        assert_eq!(
+            body["content"],
+            "let x = 1\nfun add(a, b) = a + b\nlet y = add(x, 2)\nlet z = y\n"
+        );
+
+        client
+            .request(
+                "setFunctionBreakpoints",
+                json!({ "breakpoints": [{ "name": "add" }] }),
+            )
+            .await;
+        client.request("continue", json!({ "threadId": 1 })).await;
+        client.expect_event("stopped").await;
+        let body = client.request("stackTrace", json!({ "threadId": 1 })).await;
+        assert_eq!(body["stackFrames"][0]["name"], "add");
+
+        client.send("evaluate", json!({ "expression": "a" })).await;
+        let response = client.receive().await;
+        assert_eq!(response["success"], false);
+
+        client.request("disconnect", json!({})).await;
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn launching_a_program_is_refused() {
+        let (mut client, server) = connect();
+        client.request("initialize", json!({})).await;
+        client.expect_event("initialized").await;
+        client
+            .send("launch", json!({ "program": "program.calc" }))
+            .await;
+        let response = client.receive().await;
+        assert_eq!(response["success"], false);
+        assert_eq!(
+            response["message"],
+            "Can't launch a program, only the one the server was started with"
+        );
+
+        client.request("disconnect", json!({})).await;
+        server.await.unwrap().unwrap();
+    }
+}
@@ -1,11 +1,18 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::Arc,
+};
+
+use calc::mutable_self_async::{
+    ast, coverage, debugger, evaluator, log, profile, replay, runtime, trace, unparser,
+};
+
 use ast::{Expr, Factor};
 use evaluator::Evaluator;
 
-mod ast;
-mod evaluator;
-mod log;
-mod runtime;
-mod unparser;
+mod dap;
 
 #[tokio::main]
 async fn main() {
@@ -24,7 +31,7 @@ async fn main() {
         ),
         ast::Stmt::Fun(
             "add".to_owned(),
-            Box::new(ast::Function {
+            Arc::new(ast::Function {
                 params: vec!["a".to_string(), "b".to_string()],
                 body: Expr::BinaryOp {
                     op: ast::TermBinaryOp::Add,
@@ -50,8 +57,69 @@ async fn main() {
         ),
     ];
 
+    if std::env::args().any(|arg| arg == "--dap") {
+        // Serve a debugger client over stdio instead of running the demo.
+        let input = tokio::io::BufReader::new(tokio::io::stdin());
+        let stmts = Arc::new(stmts.to_vec());
+        dap::serve(stmts, input, tokio::io::stdout()).await.unwrap();
+        return;
+    }
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let read_recording =
+        |path: &String| replay::Recording::read(BufReader::new(File::open(path).unwrap())).unwrap();
+
+    if let Some(path) = path_after(&args, "--travel") {
+        // Step through a recording instead of running the demo.
+        let stdin = std::io::stdin().lock();
+        replay::travel(read_recording(path), stdin, std::io::stdout()).unwrap();
+        return;
+    }
+
     let mut evaluator = Evaluator::default();
+    if let Some(path) = path_after(&args, "--record") {
+        // First, so it records results as the evaluator sees them.
+        let out = create(path);
+        evaluator
+            .visitors
+            .push(Box::new(replay::Recorder::new(out, &stmts)));
+    }
     evaluator.visitors.push(Box::new(log::Print::default()));
+    if let Some(path) = path_after(&args, "--log") {
+        let jsonl = create(path);
+        evaluator
+            .visitors
+            .push(Box::new(log::JsonLines::new(jsonl, &stmts)));
+    }
+    evaluator
+        .visitors
+        .push(Box::new(profile::Profile::default()));
+    if let Some(path) = path_after(&args, "--trace") {
+        // Folded stacks go next to the Chrome trace.
+        let folded = Path::new(path).with_extension("folded");
+        evaluator
+            .visitors
+            .push(Box::new(trace::Trace::new(path, folded)));
+    }
+    if let Some(path) = path_after(&args, "--coverage") {
+        // The LCOV report refers to the program's source by this path.
+        let source = Path::new(path).with_file_name("program.calc");
+        evaluator
+            .visitors
+            .push(Box::new(coverage::Coverage::new(&stmts, source, path)));
+    }
+    if std::env::args().any(|arg| arg == "--debug") {
+        let (debugger, controller) = debugger::Debugger::new(&stmts, &evaluator.bindings);
+        evaluator.visitors.push(Box::new(debugger));
+        tokio::spawn(async {
+            if let Err(err) = debugger::repl(controller).await {
+                eprintln!("Debugger failed: {err}");
+            }
+        });
+    }
+    if let Some(path) = path_after(&args, "--replay") {
+        evaluator.replay(read_recording(path), &stmts);
+    }
     let result = evaluator.eval_stmts(&stmts).await;
     println!("{:?}", result);
     println!("{:#?}", evaluator.bindings);
@@ -61,3 +129,20 @@ async fn main() {
     println!();
     println!("{}", unp.output());
 }
+
+/// The path after `flag` on the command line, if it's there.
+fn path_after<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(i + 1).expect("expected a path after the flag"))
+}
+
+/// Creates the output file at `path`, or exits if it can't.
+fn create(path: &str) -> BufWriter<File> {
+    match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Can't create {path}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Measures the evaluator on the same workloads as the other crates'
+//! `evaluator` benchmarks.  It has no visitors, so it's only measured
+//! without them.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use calc::recursion_crate::{
+    ast::{Expr, Factor, FactorBinaryOp, Function, Stmt, TermBinaryOp},
+    evaluator::Evaluator,
+};
+
+/// Sizes of each workload.
+const SIZES: [usize; 3] = [10, 100, 1_000];
+
+/// Calls of the function capturing the large environment.
+const CALLS: usize = 10;
+
+/// Builds a program of a given size.
+type Workload = fn(usize) -> Vec<Stmt>;
+
+fn num(x: f64) -> Expr {
+    Expr::Factor(Box::new(Factor::Literal(x)))
+}
+
+fn var(name: impl Into<String>) -> Expr {
+    Expr::Factor(Box::new(Factor::Variable(name.into())))
+}
+
+fn add(lhs: Expr, rhs: Expr) -> Expr {
+    Expr::BinaryOp {
+        op: TermBinaryOp::Add,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
+
+fn call(fun: &str, args: Vec<Expr>) -> Expr {
+    Expr::Factor(Box::new(Factor::Call(
+        Box::new(Factor::Variable(fun.to_owned())),
+        args,
+    )))
+}
+
+fn fun(name: &str, params: &[&str], body: Expr) -> Stmt {
+    Stmt::Fun(
+        name.to_owned(),
+        Box::new(Function {
+            params: params.iter().map(|&param| param.to_owned()).collect(),
+            body,
+        }),
+    )
+}
+
+/// `let x = 0 + 1 * 0.5 + 2 * 0.5 + ...` with `n` terms, nested to the left.
+fn deep_arithmetic(n: usize) -> Vec<Stmt> {
+    let mut expr = num(0.0);
+    for i in 1..=n {
+        let term = Factor::BinaryOp {
+            op: FactorBinaryOp::Mul,
+            lhs: Box::new(Factor::Literal(i as f64)),
+            rhs: Box::new(Factor::Literal(0.5)),
+        };
+        expr = add(expr, Expr::Factor(Box::new(term)));
+    }
+    vec![Stmt::Let("x".to_owned(), Box::new(expr))]
+}
+
+/// `fun add(a, b) = a + b`, then `let x = add(x, 1)` `n` times.
+fn closure_calls(n: usize) -> Vec<Stmt> {
+    let mut stmts = vec![
+        fun("add", &["a", "b"], add(var("a"), var("b"))),
+        Stmt::Let("x".to_owned(), Box::new(num(0.0))),
+    ];
+    for _ in 0..n {
+        let next = call("add", vec![var("x"), num(1.0)]);
+        stmts.push(Stmt::Let("x".to_owned(), Box::new(next)));
+    }
+    stmts
+}
+
+/// `n` bindings, then a function capturing all of them, called [`CALLS`]
+/// times.
+fn large_env(n: usize) -> Vec<Stmt> {
+    let mut stmts = (0..n)
+        .map(|i| Stmt::Let(format!("v{i}"), Box::new(num(i as f64))))
+        .collect::<Vec<_>>();
+    stmts.push(fun("get", &["a"], add(var("a"), var("v0"))));
+    for _ in 0..CALLS {
+        stmts.push(Stmt::Let(
+            "r".to_owned(),
+            Box::new(call("get", vec![num(1.0)])),
+        ));
+    }
+    stmts
+}
+
+fn bench_evaluator(c: &mut Criterion) {
+    let workloads: [(&str, Workload); 3] = [
+        ("deep_arithmetic", deep_arithmetic),
+        ("closure_calls", closure_calls),
+        ("large_env", large_env),
+    ];
+    for (name, workload) in workloads {
+        let mut group = c.benchmark_group(name);
+        for n in SIZES {
+            let stmts = workload(n);
+            // Named like the other crates' runs without visitors.
+            let id = BenchmarkId::new("0_visitors", n);
+            group.bench_with_input(id, &stmts, |b, stmts| {
+                b.iter(|| {
+                    let mut evaluator = Evaluator::default();
+                    black_box(evaluator.eval_stmts(black_box(stmts)).unwrap())
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_evaluator);
+criterion_main!(benches);
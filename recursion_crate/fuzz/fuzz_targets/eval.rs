@@ -0,0 +1,18 @@
+//! Evaluates arbitrary programs.
+#![no_main]
+
+use arbitrary::Unstructured;
+use calc::recursion_crate::evaluator::Evaluator;
+use libfuzzer_sys::fuzz_target;
+
+/// Calls made before giving up, since calls can take exponential time.
+const MAX_CALLS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(stmts) = calc::fuzz::recursion_crate::stmts(&mut Unstructured::new(data)) else {
+        return;
+    };
+    let mut evaluator = Evaluator::default();
+    evaluator.max_calls = Some(MAX_CALLS);
+    let _ = evaluator.eval_stmts(&stmts);
+});
@@ -1,11 +1,8 @@
+use calc::recursion_crate::{ast, evaluator};
+
 use ast::{Expr, Factor};
 use evaluator::Evaluator;
 
-mod ast;
-mod evaluator;
-mod recursion;
-mod runtime;
-
 fn main() {
     let stmts = [
         ast::Stmt::Let(
@@ -0,0 +1,13 @@
+//! Type checks arbitrary programs.
+#![no_main]
+
+use arbitrary::Unstructured;
+use calc::typed_async::tc;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(stmts) = calc::fuzz::typed_async::stmts(&mut Unstructured::new(data)) else {
+        return;
+    };
+    let _ = tc::TypeChecker::default().check_stmts(&stmts);
+});
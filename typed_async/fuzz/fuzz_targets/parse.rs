@@ -0,0 +1,16 @@
+//! Parses arbitrary text, then formats and type checks whatever parses.
+#![no_main]
+
+use calc::typed_async::{parser, pretty, tc};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(program) = parser::parse(source) else {
+        return;
+    };
+    pretty::format(&program, pretty::DEFAULT_WIDTH);
+    let _ = tc::TypeChecker::default().check_stmts(&program.stmts);
+});
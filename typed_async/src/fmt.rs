@@ -0,0 +1,228 @@
+//! `weaver fmt [--check] [--width N] [FILE]...` formats source files in place,
+//! or stdin to stdout, with the [pretty printer](crate::pretty).  With
+//! `--check`, changes nothing, lists the files that aren't formatted, and fails
+//! if there are any.
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{parser, pretty};
+
+/// Runs `fmt` with the arguments after it.  Reports problems to `stderr`, and
+/// returns whether there were none.
+pub(crate) fn run(
+    args: &[String],
+    stdin: impl Read,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> bool {
+    let mut check = false;
+    let mut width = pretty::DEFAULT_WIDTH;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--width" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(value) => width = value,
+                None => {
+                    let _ = writeln!(stderr, "Expected a number after --width");
+                    return false;
+                }
+            },
+            option if option.starts_with('-') => {
+                let _ = writeln!(stderr, "Unknown option: {option}");
+                return false;
+            }
+            _ => paths.push(arg.as_str()),
+        }
+    }
+    let format =
+        |source: &str| parser::parse(source).map(|program| pretty::format(&program, width));
+
+    if paths.is_empty() {
+        let source = match std::io::read_to_string(stdin) {
+            Ok(source) => source,
+            Err(err) => {
+                let _ = writeln!(stderr, "<stdin>: {err}");
+                return false;
+            }
+        };
+        return match format(&source) {
+            Ok(formatted) if check && formatted != source => {
+                let _ = writeln!(stdout, "stdin is not formatted");
+                false
+            }
+            Ok(_) if check => true,
+            Ok(formatted) => write!(stdout, "{formatted}").is_ok(),
+            Err(err) => {
+                let _ = writeln!(stderr, "<stdin>:{err}");
+                false
+            }
+        };
+    }
+    let mut ok = true;
+    for path in paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                let _ = writeln!(stderr, "{path}: {err}");
+                ok = false;
+                continue;
+            }
+        };
+        match format(&source) {
+            Ok(formatted) if formatted == source => {}
+            Ok(_) if check => {
+                let _ = writeln!(stdout, "{path} is not formatted");
+                ok = false;
+            }
+            Ok(formatted) => {
+                if let Err(err) = write_in_place(Path::new(path), &formatted) {
+                    let _ = writeln!(stderr, "{path}: {err}");
+                    ok = false;
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(stderr, "{path}:{err}");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Where [`write_in_place`] writes `path`'s new contents before moving them
+/// over it: a hidden file beside it, so that they're on the same file system.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.fmt"))
+}
+
+/// Replaces `path`'s contents with `contents` by renaming a temporary file over
+/// it, so that a failed write leaves it as it was.
+fn write_in_place(path: &Path, contents: &str) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    let result = std::fs::write(&temp, contents)
+        .and_then(|()| std::fs::set_permissions(&temp, std::fs::metadata(path)?.permissions()))
+        .and_then(|()| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNFORMATTED: &str = "let x:number=1+2\n";
+    const FORMATTED: &str = "let x: number = 1 + 2\n";
+
+    /// Runs `fmt` on empty stdin, and returns whether it succeeded and what it
+    /// wrote to stdout and stderr.
+    fn fmt(args: &[&str]) -> (bool, String, String) {
+        let args = args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let ok = run(&args, std::io::empty(), &mut stdout, &mut stderr);
+        let text = |bytes| String::from_utf8(bytes).unwrap();
+        (ok, text(stdout), text(stderr))
+    }
+
+    /// A file of `source` named after the test, so that tests don't share any.
+    fn file(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("weaver-fmt-{}-{name}", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn formats_stdin_to_stdout() {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let ok = run(&[], UNFORMATTED.as_bytes(), &mut stdout, &mut stderr);
+        assert!(ok);
+        assert_eq!(String::from_utf8(stdout).unwrap(), FORMATTED);
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn rewrites_files_in_place() {
+        let path = file("in_place.calc", UNFORMATTED);
+        assert_eq!(fmt(&[&path]), (true, String::new(), String::new()));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), FORMATTED);
+        assert!(!temp_path(Path::new(&path)).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn check_lists_unformatted_files_without_changing_them() {
+        let unformatted = file("check_unformatted.calc", UNFORMATTED);
+        let formatted = file("check_formatted.calc", FORMATTED);
+        let (ok, stdout, stderr) = fmt(&["--check", &unformatted, &formatted]);
+        assert!(!ok);
+        assert_eq!(stdout, format!("{unformatted} is not formatted\n"));
+        assert_eq!(stderr, "");
+        assert_eq!(std::fs::read_to_string(&unformatted).unwrap(), UNFORMATTED);
+        assert_eq!(
+            fmt(&["--check", &formatted]),
+            (true, String::new(), String::new())
+        );
+        std::fs::remove_file(unformatted).unwrap();
+        std::fs::remove_file(formatted).unwrap();
+    }
+
+    #[test]
+    fn check_reports_unformatted_stdin() {
+        let check = |source: &str| {
+            let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+            let ok = run(
+                &["--check".to_owned()],
+                source.as_bytes(),
+                &mut stdout,
+                &mut stderr,
+            );
+            assert!(stderr.is_empty());
+            (ok, String::from_utf8(stdout).unwrap())
+        };
+        assert_eq!(
+            check(UNFORMATTED),
+            (false, "stdin is not formatted\n".to_owned())
+        );
+        assert_eq!(check(FORMATTED), (true, String::new()));
+    }
+
+    #[test]
+    fn leaves_files_as_they_were_when_writing_fails() {
+        let path = file("write_fails.calc", UNFORMATTED);
+        // The temporary file can't be created where there's a directory.
+        let temp = temp_path(Path::new(&path));
+        std::fs::create_dir(&temp).unwrap();
+        let (ok, _, stderr) = fmt(&[&path]);
+        assert!(!ok);
+        assert!(stderr.starts_with(&format!("{path}: ")), "{stderr}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), UNFORMATTED);
+        std::fs::remove_dir(temp).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_bad_arguments() {
+        let (ok, _, stderr) = fmt(&["--width", "wide"]);
+        assert!(!ok);
+        assert_eq!(stderr, "Expected a number after --width\n");
+        let (ok, _, stderr) = fmt(&["--chek"]);
+        assert!(!ok);
+        assert_eq!(stderr, "Unknown option: --chek\n");
+
+        // Other files are still formatted.
+        let missing = file("missing.calc", "");
+        std::fs::remove_file(&missing).unwrap();
+        let path = file("after_missing.calc", UNFORMATTED);
+        let (ok, _, stderr) = fmt(&[&missing, &path]);
+        assert!(!ok);
+        assert!(stderr.starts_with(&format!("{missing}: ")), "{stderr}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), FORMATTED);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! Golden-file tests.
+//!
+//! Each `.calc` program in `tests/golden` is run through the same pipeline as
+//! `main` (parse, type check, evaluate with [`log::Print`]), and through the
+//! other designs' evaluators with its types erased, and what comes out is
+//! compared with the `.out` file next to it.  The types and bindings shown
+//! are only those the program binds, not the rest of the built-ins.  Run the
+//! tests with `BLESS=1` to write the current output to the `.out` files
+//! instead, after making sure the changes are intended.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{Display, Write as _},
+    fs,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use calc::{mutable_self, mutable_self_async, recursion_crate, typed_async::erase};
+
+use crate::{
+    ast::Stmt, evaluator::Evaluator, log, parser::parse, tc::TypeChecker, unparser::Unparser,
+};
+
+/// Set to update the golden files.
+const BLESS: &str = "BLESS";
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+/// A writer to a buffer that can be read after the writer is given away.
+#[derive(Debug, Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends a section headed `name`.
+fn section(out: &mut String, name: &str, body: impl std::fmt::Display) {
+    let body = body.to_string();
+    writeln!(out, "--- {name}").unwrap();
+    out.push_str(&body);
+    if !body.is_empty() && !body.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// The bindings of the names in `bound`, sorted, one per line.
+fn list_bindings<V: Display>(env: &HashMap<String, V>, bound: &BTreeSet<&String>) -> String {
+    let bindings = env
+        .iter()
+        .filter(|(name, _)| bound.contains(name))
+        .collect::<BTreeMap<_, _>>();
+    bindings
+        .iter()
+        .fold(String::new(), |mut bindings, (name, value)| {
+            writeln!(bindings, "{name} = {value}").unwrap();
+            bindings
+        })
+}
+
+/// Appends the sections of what `design` evaluated: its trace, if it has
+/// visitors, its value or error, and its bindings.
+fn evaluated(
+    out: &mut String,
+    design: &str,
+    trace: Option<&Buffer>,
+    result: Result<impl Display, impl Display>,
+    bindings: String,
+) {
+    if let Some(trace) = trace {
+        section(out, &format!("{design} trace"), trace.contents());
+    }
+    match result {
+        Ok(value) => section(out, &format!("{design} value"), value),
+        Err(err) => section(out, &format!("{design} error"), err),
+    }
+    section(out, &format!("{design} bindings"), bindings);
+}
+
+/// Runs `source` through every design as far as it gets, and returns what
+/// each step printed.  `typed_async` stops at type errors, and the untyped
+/// designs run the program with its types erased whether it type checks or
+/// not.
+async fn run(source: &str) -> String {
+    let mut out = String::new();
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(err) => {
+            section(&mut out, "error", err);
+            return out;
+        }
+    };
+    let mut unparser = Unparser::default();
+    unparser.unparse_stmts(&program.stmts).unwrap();
+    section(&mut out, "unparsed", unparser.output());
+    let bound = program
+        .stmts
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Let(name, _, _) | Stmt::Fun(name, _) => name,
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut type_checker = TypeChecker::default();
+    match type_checker.check_stmts(&program.stmts) {
+        Ok(_) => {
+            let types = type_checker
+                .bindings
+                .iter()
+                .filter(|(name, _)| bound.contains(name))
+                .collect::<BTreeMap<_, _>>();
+            let types = types.iter().fold(String::new(), |mut types, (name, ty)| {
+                writeln!(types, "{name}: {ty}").unwrap();
+                types
+            });
+            section(&mut out, "types", types);
+
+            let trace = Buffer::default();
+            let mut evaluator = Evaluator::default();
+            evaluator
+                .visitors
+                .push(Box::new(log::Print::new(trace.clone())));
+            let result = evaluator.eval_stmts(&program.stmts).await;
+            let bindings = list_bindings(&evaluator.bindings, &bound);
+            evaluated(&mut out, "typed_async", Some(&trace), result, bindings);
+        }
+        Err(err) => section(&mut out, "type error", err),
+    }
+
+    let trace = Buffer::default();
+    let mut evaluator = mutable_self::evaluator::Evaluator::default();
+    evaluator
+        .visitors
+        .push(Box::new(mutable_self::log::Print::new(trace.clone())));
+    let result = evaluator.eval_stmts(&erase::mutable_self::stmts(&program.stmts));
+    let bindings = list_bindings(&evaluator.bindings, &bound);
+    evaluated(&mut out, "mutable_self", Some(&trace), result, bindings);
+
+    let trace = Buffer::default();
+    let mut evaluator = mutable_self_async::evaluator::Evaluator::default();
+    evaluator.op_delay = Duration::ZERO;
+    evaluator
+        .visitors
+        .push(Box::new(mutable_self_async::log::Print::new(trace.clone())));
+    let stmts = erase::mutable_self_async::stmts(&program.stmts);
+    let result = evaluator.eval_stmts(&stmts).await;
+    let bindings = list_bindings(&evaluator.bindings, &bound);
+    evaluated(
+        &mut out,
+        "mutable_self_async",
+        Some(&trace),
+        result,
+        bindings,
+    );
+
+    // `recursion_crate` has no visitors to trace it with.
+    let mut evaluator = recursion_crate::evaluator::Evaluator::default();
+    let result = evaluator.eval_stmts(&erase::recursion_crate::stmts(&program.stmts));
+    let bindings = list_bindings(&evaluator.bindings, &bound);
+    evaluated(&mut out, "recursion_crate", None, result, bindings);
+    out
+}
+
+/// The lines removed from `expected` and added in `actual`, going by their
+/// longest common subsequence.
+fn diff(expected: &str, actual: &str) -> String {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // `lcs[i][j]` is the length of the longest common subsequence of
+    // `old[i..]` and `new[j..]`.
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            writeln!(out, "- {}", old[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(out, "+ {}", new[j]).unwrap();
+            j += 1;
+        }
+    }
+    out
+}
+
+#[tokio::test]
+async fn golden_files() {
+    let bless = std::env::var_os(BLESS).is_some();
+    let mut paths = fs::read_dir(DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "calc"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {DIR}");
+
+    let mut failures = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        let actual = run(&source).await;
+        let golden = path.with_extension("out");
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if actual == expected {
+            continue;
+        }
+        if bless {
+            fs::write(&golden, actual).unwrap();
+        } else {
+            let name = golden.file_name().unwrap();
+            failures.push(format!(
+                "{}:\n{}",
+                Path::new("tests/golden").join(name).display(),
+                diff(&expected, &actual)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\nRun with {BLESS}=1 to update the golden files.",
+        failures.join("\n")
+    );
+}
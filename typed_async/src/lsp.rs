@@ -0,0 +1,517 @@
+//! A [Language Server Protocol](https://microsoft.github.io/language-server-protocol/)
+//! server for calc source files.
+//!
+//! Documents are synced in full on every change.  The server publishes
+//! diagnostics from the parser and the [`TypeChecker`], shows the type of a
+//! name on hover, goes to the definition of a variable, lists top-level
+//! functions as document symbols, and formats documents with the
+//! [pretty printer](crate::pretty).
+use std::collections::HashMap;
+
+use calc::protocol::{read_body, write_message};
+use serde_json::{json, Value as Json};
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{
+    ast::Stmt,
+    parser::{self, ParseError, Position, Program, Span},
+    pretty,
+    runtime::Error,
+    tc::TypeChecker,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// `DiagnosticSeverity.Error`.
+const SEVERITY_ERROR: u64 = 1;
+
+/// `SymbolKind.Function`.
+const SYMBOL_FUNCTION: u64 = 12;
+
+/// `TextDocumentSyncKind.Full`.
+const SYNC_FULL: u64 = 1;
+
+fn position(json: &Json) -> Position {
+    Position {
+        line: json["line"].as_u64().unwrap_or_default() as usize,
+        column: json["character"].as_u64().unwrap_or_default() as usize,
+    }
+}
+
+fn range(span: Span) -> Json {
+    json!({
+        "start": { "line": span.start.line, "character": span.start.column },
+        "end": { "line": span.end.line, "character": span.end.column },
+    })
+}
+
+/// An open source file.
+#[derive(Debug)]
+struct Document {
+    text: String,
+    program: Result<Program, ParseError>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let program = parser::parse(&text);
+        Self { text, program }
+    }
+
+    /// The syntax error, or else the first type error.
+    fn diagnostics(&self) -> Vec<Json> {
+        let (message, span) = match &self.program {
+            Err(err) => (err.message.clone(), err.span),
+            Ok(program) => {
+                let mut type_checker = TypeChecker::default();
+                let error = program
+                    .stmts
+                    .iter()
+                    .zip(&program.spans)
+                    .find_map(|(stmt, span)| Some((type_checker.check_stmt(stmt).err()?, span)));
+                match error {
+                    Some((err, span)) => (err.to_string(), span.span),
+                    None => return Vec::new(),
+                }
+            }
+        };
+        vec![json!({
+            "range": range(span),
+            "severity": SEVERITY_ERROR,
+            "source": "calc",
+            "message": message,
+        })]
+    }
+
+    fn hover(&self, position: Position) -> Json {
+        let Ok(program) = &self.program else {
+            return Json::Null;
+        };
+        let Some(index) = program.symbol_at(position) else {
+            return Json::Null;
+        };
+        let Some(ty) = program.ty(index) else {
+            return Json::Null;
+        };
+        let symbol = &program.symbols[index];
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```\n{}: {ty}\n```", symbol.name),
+            },
+            "range": range(symbol.span),
+        })
+    }
+
+    fn definition(&self, uri: &str, position: Position) -> Json {
+        let Ok(program) = &self.program else {
+            return Json::Null;
+        };
+        let Some(definition) = program
+            .symbol_at(position)
+            .and_then(|index| program.definition(index))
+        else {
+            return Json::Null;
+        };
+        json!({ "uri": uri, "range": range(program.symbols[definition].span) })
+    }
+
+    fn symbols(&self) -> Json {
+        let Ok(program) = &self.program else {
+            return Json::Null;
+        };
+        let functions = program
+            .stmts
+            .iter()
+            .zip(&program.spans)
+            .filter(|(stmt, _)| matches!(stmt, Stmt::Fun(..)))
+            .map(|(_, span)| {
+                let symbol = &program.symbols[span.name];
+                json!({
+                    "name": symbol.name,
+                    "detail": program.ty(span.name).map(ToString::to_string),
+                    "kind": SYMBOL_FUNCTION,
+                    "range": range(span.span),
+                    "selectionRange": range(symbol.span),
+                })
+            })
+            .collect();
+        Json::Array(functions)
+    }
+
+    /// Replaces the whole document with the pretty-printed program.
+    fn format(&self) -> Json {
+        let Ok(program) = &self.program else {
+            return Json::Null;
+        };
+        let mut end = Position::default();
+        end.advance(&self.text);
+        let whole = Span {
+            start: Position::default(),
+            end,
+        };
+        let text = pretty::format(program, pretty::DEFAULT_WIDTH);
+        json!([{ "range": range(whole), "newText": text }])
+    }
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+impl<W: AsyncWrite + Unpin> Server<W> {
+    async fn respond(&mut self, request: &Json, result: Json) -> Result<(), Error> {
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+        write_message(&mut self.output, &response).await
+    }
+
+    async fn respond_error(
+        &mut self,
+        request: &Json,
+        code: i64,
+        message: String,
+    ) -> Result<(), Error> {
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": { "code": code, "message": message },
+        });
+        write_message(&mut self.output, &response).await
+    }
+
+    async fn notify(&mut self, method: &str, params: Json) -> Result<(), Error> {
+        let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&mut self.output, &notification).await
+    }
+
+    async fn publish_diagnostics(&mut self, uri: &str) -> Result<(), Error> {
+        let diagnostics = self
+            .documents
+            .get(uri)
+            .map(Document::diagnostics)
+            .unwrap_or_default();
+        let params = json!({ "uri": uri, "diagnostics": diagnostics });
+        self.notify("textDocument/publishDiagnostics", params).await
+    }
+
+    /// Handles a request or notification.  Returns whether to exit.
+    async fn handle(&mut self, message: Json) -> Result<bool, Error> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        match message["method"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "textDocumentSync": SYNC_FULL,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
+                });
+                let result = json!({
+                    "capabilities": capabilities,
+                    "serverInfo": { "name": "calc" },
+                });
+                self.respond(&message, result).await?;
+            }
+            "shutdown" => self.respond(&message, Json::Null).await?,
+            "exit" => return Ok(true),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents
+                    .insert(uri.clone(), Document::new(text.to_owned()));
+                self.publish_diagnostics(&uri).await?;
+            }
+            "textDocument/didChange" => {
+                // With full sync, the last change has the whole text.
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                if let Some(text) = text {
+                    self.documents
+                        .insert(uri.clone(), Document::new(text.to_owned()));
+                }
+                self.publish_diagnostics(&uri).await?;
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri).await?;
+            }
+            method @ ("textDocument/hover"
+            | "textDocument/definition"
+            | "textDocument/documentSymbol"
+            | "textDocument/formatting") => {
+                let Some(document) = self.documents.get(&uri) else {
+                    let error = format!("Unknown document: {uri}");
+                    self.respond_error(&message, INVALID_PARAMS, error).await?;
+                    return Ok(false);
+                };
+                let position = position(&params["position"]);
+                let result = match method {
+                    "textDocument/hover" => document.hover(position),
+                    "textDocument/definition" => document.definition(&uri, position),
+                    "textDocument/documentSymbol" => document.symbols(),
+                    _ => document.format(),
+                };
+                self.respond(&message, result).await?;
+            }
+            // Ignore responses and notifications we don't handle.
+            _ if message.get("id").is_none() || message.get("method").is_none() => {}
+            method => {
+                let error = format!("Unsupported method: {method}");
+                self.respond_error(&message, METHOD_NOT_FOUND, error)
+                    .await?;
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Serves a client that sends requests to `input` and reads responses and
+/// notifications from `output`.  Returns when the client exits or closes
+/// `input`.
+pub(crate) async fn serve(
+    mut input: impl AsyncBufRead + Unpin,
+    output: impl AsyncWrite + Unpin,
+) -> Result<(), Error> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+    };
+    while let Some(body) = read_body(&mut input).await? {
+        let message = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                // With no request to answer, the response's id is null.
+                let error = format!("Invalid JSON: {err}");
+                server
+                    .respond_error(&Json::Null, PARSE_ERROR, error)
+                    .await?;
+                continue;
+            }
+        };
+        if server.handle(message).await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calc::protocol::read_message;
+    use tokio::{
+        io::{AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf},
+        task::JoinHandle,
+    };
+
+    const URI: &str = "file:///program.calc";
+
+    /// A scripted client talking to a server over an in-memory pipe.
+    struct Client {
+        input: BufReader<ReadHalf<DuplexStream>>,
+        output: WriteHalf<DuplexStream>,
+        id: u64,
+    }
+
+    impl Client {
+        async fn notify(&mut self, method: &str, params: Json) {
+            let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            write_message(&mut self.output, &notification)
+                .await
+                .unwrap();
+        }
+
+        async fn receive(&mut self) -> Json {
+            read_message(&mut self.input).await.unwrap().unwrap()
+        }
+
+        /// Sends a request and returns its successful result.
+        async fn request(&mut self, method: &str, params: Json) -> Json {
+            self.id += 1;
+            let request =
+                json!({ "jsonrpc": "2.0", "id": self.id, "method": method, "params": params });
+            write_message(&mut self.output, &request).await.unwrap();
+            let response = self.receive().await;
+            assert_eq!(response["id"], self.id, "{response}");
+            assert!(response.get("error").is_none(), "{response}");
+            response["result"].clone()
+        }
+
+        async fn expect_diagnostics(&mut self) -> Vec<Json> {
+            let message = self.receive().await;
+            assert_eq!(
+                message["method"], "textDocument/publishDiagnostics",
+                "{message}"
+            );
+            assert_eq!(message["params"]["uri"], URI);
+            message["params"]["diagnostics"].as_array().unwrap().clone()
+        }
+
+        async fn open(&mut self, text: &str) -> Vec<Json> {
+            let document = json!({ "uri": URI, "languageId": "calc", "version": 1, "text": text });
+            self.notify("textDocument/didOpen", json!({ "textDocument": document }))
+                .await;
+            self.expect_diagnostics().await
+        }
+
+        async fn change(&mut self, text: &str) -> Vec<Json> {
+            let params = json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": text }],
+            });
+            self.notify("textDocument/didChange", params).await;
+            self.expect_diagnostics().await
+        }
+
+        async fn at(&mut self, method: &str, line: usize, character: usize) -> Json {
+            let params = json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            });
+            self.request(method, params).await
+        }
+    }
+
+    fn connect() -> (Client, JoinHandle<Result<(), Error>>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_input, server_output) = tokio::io::split(server);
+        let server = tokio::spawn(serve(BufReader::new(server_input), server_output));
+        let (client_input, client_output) = tokio::io::split(client);
+        let client = Client {
+            input: BufReader::new(client_input),
+            output: client_output,
+            id: 0,
+        };
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn scripted_session() {
+        let (mut client, server) = connect();
+        let result = client.request("initialize", json!({})).await;
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        client.notify("initialized", json!({})).await;
+
+        // The second statement passes a function where a number is expected.
+        let diagnostics = client
+            .open("let x: number = 1\nfun add(a: number, b: number): number = a + b\nlet y: number = add(add, x)\n")
+            .await;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0]["message"],
+            "Type mismatch: expected number, found (number, number) -> number"
+        );
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+
+        let diagnostics = client
+            .change("let x: number = 1\nfun add(a: number,b: number): number = a+b\nlet y: number =   add(x, x)\n")
+            .await;
+        assert!(diagnostics.is_empty());
+
+        // `add` in `let y`.
+        let hover = client.at("textDocument/hover", 2, 19).await;
+        assert_eq!(
+            hover["contents"]["value"],
+            "```\nadd: (number, number) -> number\n```"
+        );
+        let definition = client.at("textDocument/definition", 2, 19).await;
+        assert_eq!(definition["uri"], URI);
+        assert_eq!(
+            definition["range"],
+            json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 7 } })
+        );
+        // `a` in the body of `add` is its parameter.
+        let definition = client.at("textDocument/definition", 1, 39).await;
+        assert_eq!(definition["range"]["start"]["character"], 8);
+        let hover = client.at("textDocument/hover", 0, 10).await;
+        assert_eq!(hover, Json::Null);
+
+        let symbols = client
+            .request(
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": URI } }),
+            )
+            .await;
+        assert_eq!(symbols.as_array().unwrap().len(), 1);
+        assert_eq!(symbols[0]["name"], "add");
+        assert_eq!(symbols[0]["kind"], SYMBOL_FUNCTION);
+
+        let edits = client
+            .request(
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": URI }, "options": { "tabSize": 2, "insertSpaces": true } }),
+            )
+            .await;
+        assert_eq!(
+            edits[0]["newText"],
+            "let x: number = 1\nfun add(a: number, b: number): number = a + b\nlet y: number = add(x, x)\n"
+        );
+        assert_eq!(
+            edits[0]["range"]["end"],
+            json!({ "line": 3, "character": 0 })
+        );
+
+        client.request("shutdown", Json::Null).await;
+        client.notify("exit", Json::Null).await;
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn syntax_errors_and_unknown_methods() {
+        let (mut client, server) = connect();
+        client.request("initialize", json!({})).await;
+
+        let diagnostics = client.open("let x: number = (1 +\n").await;
+        assert_eq!(
+            diagnostics[0]["message"],
+            "Expected an expression, found end of input"
+        );
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 1, "character": 0 })
+        );
+        let edits = client
+            .request(
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": URI } }),
+            )
+            .await;
+        assert_eq!(edits, Json::Null);
+
+        let request =
+            json!({ "jsonrpc": "2.0", "id": 99, "method": "textDocument/rename", "params": {} });
+        write_message(&mut client.output, &request).await.unwrap();
+        let response = client.receive().await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_serving_after_invalid_json() {
+        let (mut client, server) = connect();
+        let body = "{\"jsonrpc\": ";
+        let message = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+        client.output.write_all(message.as_bytes()).await.unwrap();
+        let response = client.receive().await;
+        assert_eq!(response["id"], Json::Null);
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let result = client.request("initialize", json!({})).await;
+        assert_eq!(result["serverInfo"]["name"], "calc");
+        // Too long to check recursively, but not to parse.
+        let text = format!("let x: number = 1{}", " + 1".repeat(20_000));
+        let diagnostics = client.open(&text).await;
+        assert_eq!(diagnostics[0]["message"], "Nested more than 1024 deep");
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+}
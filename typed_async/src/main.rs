@@ -1,16 +1,38 @@
+use std::{fs::File, io::BufWriter, path::Path, sync::Arc};
+
+use calc::typed_async::{
+    ast, coverage, debugger, evaluator, log, parser, pretty, profile, runtime, tc, trace, unparser,
+};
+
 use ast::{Expr, Factor, Param, Type};
 use evaluator::Evaluator;
 use tc::TypeChecker;
 
-mod ast;
-mod evaluator;
-mod log;
-mod runtime;
-mod tc;
-mod unparser;
+mod fmt;
+#[cfg(test)]
+mod golden;
+mod lsp;
 
 #[tokio::main]
 async fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("fmt") {
+        let (mut stdout, mut stderr) = (std::io::stdout(), std::io::stderr());
+        if !fmt::run(&args[2..], std::io::stdin(), &mut stdout, &mut stderr) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if std::env::args().any(|arg| arg == "--lsp") {
+        // Serve an editor over stdio instead of running the demo.
+        let input = tokio::io::BufReader::new(tokio::io::stdin());
+        if let Err(err) = lsp::serve(input, tokio::io::stdout()).await {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let stmts = [
         ast::Stmt::Let(
             "x".to_owned(),
@@ -28,7 +50,7 @@ async fn main() {
         ),
         ast::Stmt::Fun(
             "add".to_owned(),
-            Box::new(ast::Function {
+            Arc::new(ast::Function {
                 params: vec![
                     Param {
                         name: "a".to_owned(),
@@ -73,7 +95,39 @@ async fn main() {
 
     let mut evaluator = Evaluator::default();
     evaluator.visitors.push(Box::new(log::Print::default()));
+    if let Some(path) = path_after(&args, "--log") {
+        let jsonl = create(path);
+        evaluator
+            .visitors
+            .push(Box::new(log::JsonLines::new(jsonl, &stmts)));
+    }
+    evaluator
+        .visitors
+        .push(Box::new(profile::Profile::default()));
+    if let Some(path) = path_after(&args, "--trace") {
+        // Folded stacks go next to the Chrome trace.
+        let folded = Path::new(path).with_extension("folded");
+        evaluator
+            .visitors
+            .push(Box::new(trace::Trace::new(path, folded)));
+    }
+    if let Some(path) = path_after(&args, "--coverage") {
+        // The LCOV report refers to the program's source by this path.
+        let source = Path::new(path).with_file_name("program.calc");
+        evaluator
+            .visitors
+            .push(Box::new(coverage::Coverage::new(&stmts, source, path)));
+    }
     println!();
+    if std::env::args().any(|arg| arg == "--debug") {
+        let (debugger, controller) = debugger::Debugger::new(&stmts, &evaluator.bindings);
+        evaluator.visitors.push(Box::new(debugger));
+        tokio::spawn(async {
+            if let Err(err) = debugger::repl(controller).await {
+                eprintln!("Debugger failed: {err}");
+            }
+        });
+    }
     let result = evaluator.eval_stmts(&stmts).await;
     println!("{:?}", result);
     println!("{:#?}", evaluator.bindings);
@@ -83,3 +137,20 @@ async fn main() {
     println!();
     println!("{}", unp.output());
 }
+
+/// The path after `flag` on the command line, if it's there.
+fn path_after<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(i + 1).expect("expected a path after the flag"))
+}
+
+/// Creates the output file at `path`, or exits if it can't.
+fn create(path: &str) -> BufWriter<File> {
+    match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Can't create {path}: {err}");
+            std::process::exit(1);
+        }
+    }
+}